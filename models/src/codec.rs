@@ -0,0 +1,115 @@
+//! How envelopes are put on the wire. The codec is picked once per websocket connection through the ```rust Sec-WebSocket-Protocol ``` header: the client offers the subprotocols it speaks and the server answers with the first one it knows.
+//!
+//! Clients that don't offer a subprotocol get bincode, which is what the server spoke before there was a choice.
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use std::fmt;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Codec {
+    #[default]
+    Bincode,
+    /// Sent as text frames so that plain javascript clients can use ```rust JSON.parse ```
+    Json,
+    MessagePack,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecErrors {
+    Encoding { codec: Codec, reason: String },
+    Decoding { codec: Codec, reason: String },
+}
+
+impl fmt::Display for CodecErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecErrors::Encoding { codec, reason } => {
+                write!(f, "couldn't encode with {}: {}", codec.subprotocol(), reason)
+            }
+            CodecErrors::Decoding { codec, reason } => {
+                write!(f, "couldn't decode with {}: {}", codec.subprotocol(), reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CodecErrors {}
+
+impl Codec {
+    /// In order of preference
+    pub fn all() -> [Codec; 3] {
+        [Codec::Bincode, Codec::MessagePack, Codec::Json]
+    }
+
+    /// The name used in the ```rust Sec-WebSocket-Protocol ``` header
+    pub fn subprotocol(&self) -> &'static str {
+        match self {
+            Codec::Bincode => "bincode",
+            Codec::Json => "json",
+            Codec::MessagePack => "msgpack",
+        }
+    }
+
+    pub fn from_subprotocol(subprotocol: &str) -> Option<Codec> {
+        Codec::all()
+            .iter()
+            .copied()
+            .find(|codec| codec.subprotocol() == subprotocol.trim())
+    }
+
+    /// Picks the first codec the client offered that is known, the offer being the comma separated value of the ```rust Sec-WebSocket-Protocol ``` request header.
+    ///
+    /// Returns None when the client offered subprotocols but none of them are codecs. No offer at all means bincode.
+    pub fn negotiate(offered: Option<&str>) -> Option<Codec> {
+        match offered {
+            None => Some(Codec::default()),
+            Some(offered) => offered.split(',').find_map(Codec::from_subprotocol),
+        }
+    }
+
+    /// Json goes over text frames, everything else over binary frames
+    pub fn is_binary(&self) -> bool {
+        !matches!(self, Codec::Json)
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, CodecErrors> {
+        let encoded = match self {
+            Codec::Bincode => bincode::serialize(value).map_err(|err| err.to_string()),
+            Codec::Json => serde_json::to_vec(value).map_err(|err| err.to_string()),
+            Codec::MessagePack => rmp_serde::to_vec_named(value).map_err(|err| err.to_string()),
+        };
+
+        encoded.map_err(|reason| CodecErrors::Encoding {
+            codec: *self,
+            reason,
+        })
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, CodecErrors> {
+        let decoded = match self {
+            Codec::Bincode => bincode::deserialize(bytes).map_err(|err| err.to_string()),
+            Codec::Json => serde_json::from_slice(bytes).map_err(|err| err.to_string()),
+            Codec::MessagePack => rmp_serde::from_slice(bytes).map_err(|err| err.to_string()),
+        };
+
+        decoded.map_err(|reason| CodecErrors::Decoding {
+            codec: *self,
+            reason,
+        })
+    }
+}
+
+/// How the websocket channels put a message on the wire: as a ```rust RawMessage ``` so that the other side can decode it with its ```rust MessageRegistry ```.
+#[cfg(any(feature = "native", all(feature = "wasm", target_arch = "wasm32")))]
+pub(crate) fn encode_message(
+    codec: Codec,
+    message: &dyn crate::Message,
+) -> Result<Vec<u8>, crate::CommunicationErrors> {
+    let raw = crate::RawMessage::from_message(message)
+        .map_err(|err| crate::CommunicationErrors::Unencodable(format!("{:?}", err)))?;
+
+    codec
+        .encode(&raw)
+        .map_err(|err| crate::CommunicationErrors::Unencodable(err.to_string()))
+}
@@ -0,0 +1,196 @@
+//! Metadata that lets messages be matched and ordered after they arrived, possibly over different channels. A reply carries the correlation id of its request, and every sender numbers the messages it sends to each receiver so that the receiving side can drop duplicates and hold back messages that overtook earlier ones.
+
+use crate::Message;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Correlation {
+    /// Shared by a request and all of its replies
+    pub correlation_id: Uuid,
+    /// The identity of the message this one answers
+    pub reply_to: Option<Uuid>,
+    pub sender: Uuid,
+    pub receiver: Uuid,
+    /// Counts up from 0 for every message from the sender to the receiver, so that every receiver sees an unbroken sequence
+    pub sequence: u64,
+}
+
+impl Correlation {
+    pub fn is_reply(&self) -> bool {
+        self.reply_to.is_some()
+    }
+}
+
+/// Hands out the correlations of a single sender.
+#[derive(Debug, Clone)]
+pub struct Sequencer {
+    sender: Uuid,
+    /// The next sequence number for each receiver
+    next: HashMap<Uuid, u64>,
+}
+
+impl Sequencer {
+    pub fn new(sender: Uuid) -> Sequencer {
+        Sequencer {
+            sender,
+            next: HashMap::new(),
+        }
+    }
+
+    pub fn sender(&self) -> Uuid {
+        self.sender
+    }
+
+    /// Starts a new conversation with the receiver
+    pub fn request(&mut self, receiver: Uuid) -> Correlation {
+        let correlation_id = Uuid::new_v4();
+        self.correlation(receiver, correlation_id, None)
+    }
+
+    /// Answers the message with the given identity and correlation, which goes back to its sender
+    pub fn reply(&mut self, request_identity: Uuid, request: &Correlation) -> Correlation {
+        self.correlation(request.sender, request.correlation_id, Some(request_identity))
+    }
+
+    fn correlation(&mut self, receiver: Uuid, correlation_id: Uuid, reply_to: Option<Uuid>) -> Correlation {
+        let next = self.next.entry(receiver).or_insert(0);
+        let sequence = *next;
+        *next += 1;
+
+        Correlation {
+            correlation_id,
+            reply_to,
+            sender: self.sender,
+            receiver,
+            sequence,
+        }
+    }
+}
+
+/// How many messages of a sender are held back at most before the missing ones are given up on
+pub const DEFAULT_HOLD_BACK: usize = 64;
+
+/// Puts the messages of every sender back in the order they were sent in, for every receiver separately.
+///
+/// A message is released once every message the sender sent to the same receiver before it was released. Messages that were already released or are already held back are duplicates and get dropped.
+///
+/// A message that never arrives would hold back everything after it forever, so once too many messages are waiting the missing ones are treated as lost: the waiting messages are released and the lost ones are dropped should they still show up.
+#[derive(Debug)]
+pub struct InOrder<M> {
+    max_held: usize,
+    /// Keyed by sender and receiver
+    expected: HashMap<(Uuid, Uuid), u64>,
+    held: HashMap<(Uuid, Uuid), BTreeMap<u64, M>>,
+}
+
+impl<M> Default for InOrder<M> {
+    fn default() -> Self {
+        InOrder::holding_back(DEFAULT_HOLD_BACK)
+    }
+}
+
+impl<M> InOrder<M> {
+    pub fn new() -> InOrder<M> {
+        InOrder::default()
+    }
+
+    /// Gives up on missing messages once more than ```max_held``` later ones are waiting for them
+    pub fn holding_back(max_held: usize) -> InOrder<M> {
+        InOrder {
+            max_held,
+            expected: HashMap::new(),
+            held: HashMap::new(),
+        }
+    }
+
+    /// Returns the messages that can be handled now, in order. Empty when the message has to wait for an earlier one or was a duplicate.
+    pub fn accept(&mut self, correlation: &Correlation, message: M) -> Vec<M> {
+        let key = (correlation.sender, correlation.receiver);
+        let expected = self.expected.entry(key).or_insert(0);
+        let held = self.held.entry(key).or_default();
+
+        if correlation.sequence < *expected || held.contains_key(&correlation.sequence) {
+            return vec![];
+        }
+        held.insert(correlation.sequence, message);
+
+        if held.len() > self.max_held {
+            if let Some(first_held) = held.keys().next() {
+                *expected = *first_held;
+            }
+        }
+
+        let mut released = vec![];
+        while let Some(message) = held.remove(expected) {
+            released.push(message);
+            *expected += 1;
+        }
+        if held.is_empty() {
+            self.held.remove(&key);
+        }
+        released
+    }
+
+    /// How many messages from the sender to the receiver are waiting for an earlier one
+    pub fn held_back(&self, sender: &Uuid, receiver: &Uuid) -> usize {
+        self.held.get(&(*sender, *receiver)).map(BTreeMap::len).unwrap_or(0)
+    }
+}
+
+impl InOrder<Box<dyn Message>> {
+    /// Like ```rust accept ``` with the correlation of the message. Messages without one can't be ordered and are released right away.
+    pub fn accept_message(&mut self, message: Box<dyn Message>) -> Vec<Box<dyn Message>> {
+        match message.correlation() {
+            Some(correlation) => self.accept(&correlation, message),
+            None => vec![message],
+        }
+    }
+}
+
+/// Requests that are still waiting for their reply, by correlation id.
+#[derive(Debug)]
+pub struct PendingReplies<R> {
+    pending: HashMap<Uuid, R>,
+}
+
+impl<R> Default for PendingReplies<R> {
+    fn default() -> Self {
+        PendingReplies {
+            pending: HashMap::new(),
+        }
+    }
+}
+
+impl<R> PendingReplies<R> {
+    pub fn new() -> PendingReplies<R> {
+        PendingReplies::default()
+    }
+
+    pub fn expect(&mut self, request: &Correlation, waiting: R) {
+        self.pending.insert(request.correlation_id, waiting);
+    }
+
+    /// The request the reply answers, which stops being pending. None for messages that aren't replies and for replies to unknown or already answered requests.
+    pub fn resolve(&mut self, reply: &Correlation) -> Option<R> {
+        if !reply.is_reply() {
+            return None;
+        }
+        self.pending.remove(&reply.correlation_id)
+    }
+
+    pub fn is_pending(&self, correlation_id: &Uuid) -> bool {
+        self.pending.contains_key(correlation_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
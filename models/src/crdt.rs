@@ -0,0 +1,241 @@
+//! Conflict-free replicated data types. Every replica changes its own copy and sends the whole value around; merging is commutative, associative and idempotent, so replicas that have seen the same updates hold the same value no matter in which order (or how often) the updates arrived.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+pub trait Crdt: Serialize + DeserializeOwned + Default + Clone + Send + Sync + 'static {
+    /// Folds everything the other replica knows into this one.
+    fn merge(&mut self, other: &Self);
+}
+
+/// A counter that only goes up. Every replica counts on its own and the value is the sum of all of them.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct GCounter {
+    counts: BTreeMap<Uuid, u64>,
+}
+
+impl GCounter {
+    pub fn increment(&mut self, replica: Uuid, by: u64) {
+        *self.counts.entry(replica).or_insert(0) += by;
+    }
+
+    pub fn value(&self) -> u64 {
+        self.counts.values().sum()
+    }
+}
+
+impl Crdt for GCounter {
+    fn merge(&mut self, other: &Self) {
+        for (replica, count) in &other.counts {
+            let local = self.counts.entry(*replica).or_insert(0);
+            *local = (*local).max(*count);
+        }
+    }
+}
+
+/// A single value where the latest write wins. Writes with the same timestamp are ordered by the uuid of the writer, so that every replica picks the same one.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LwwRegister<T> {
+    value: Option<T>,
+    timestamp: u64,
+    writer: Uuid,
+}
+
+impl<T> Default for LwwRegister<T> {
+    fn default() -> Self {
+        LwwRegister {
+            value: None,
+            timestamp: 0,
+            writer: Uuid::nil(),
+        }
+    }
+}
+
+impl<T> LwwRegister<T> {
+    pub fn get(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+
+    /// Ignored when the register already holds a later write.
+    pub fn set(&mut self, value: T, timestamp: u64, writer: Uuid) {
+        self.write(Some(value), timestamp, writer);
+    }
+
+    /// Empties the register, as a write of its own that a later set wins over.
+    pub fn clear(&mut self, timestamp: u64, writer: Uuid) {
+        self.write(None, timestamp, writer);
+    }
+
+    fn write(&mut self, value: Option<T>, timestamp: u64, writer: Uuid) {
+        if (timestamp, writer) > (self.timestamp, self.writer) {
+            self.value = value;
+            self.timestamp = timestamp;
+            self.writer = writer;
+        }
+    }
+}
+
+impl<T> Crdt for LwwRegister<T>
+where
+    T: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    fn merge(&mut self, other: &Self) {
+        if (other.timestamp, other.writer) > (self.timestamp, self.writer) {
+            *self = other.clone();
+        }
+    }
+}
+
+/// A map whose values are CRDTs themselves and are merged key by key, e.g. the presence of every client keyed by their uuid, with a ```rust LwwRegister ``` per client.
+///
+/// The keys are an ```rust OrSet ```: removing a key takes its value out of the map for good, unless another replica wrote to the key at the same time, in which case the write wins.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CrdtMap<K: Ord, V> {
+    keys: OrSet<K>,
+    values: BTreeMap<K, V>,
+}
+
+impl<K: Ord, V> Default for CrdtMap<K, V> {
+    fn default() -> Self {
+        CrdtMap {
+            keys: OrSet::default(),
+            values: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Default> CrdtMap<K, V> {
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.values.get(key)
+    }
+
+    /// Changes the value under the key, starting from the empty one for a key that isn't in the map.
+    pub fn update(&mut self, replica: Uuid, key: K, change: impl FnOnce(&mut V)) {
+        self.keys.add(replica, key.clone());
+        change(self.values.entry(key).or_default());
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        self.keys.remove(key);
+        self.values.remove(key);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.values.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl<K, V> Crdt for CrdtMap<K, V>
+where
+    K: Ord + Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    V: Crdt,
+{
+    fn merge(&mut self, other: &Self) {
+        self.keys.merge(&other.keys);
+        for (key, value) in &other.values {
+            if self.keys.contains(key) {
+                self.values.entry(key.clone()).or_default().merge(value);
+            }
+        }
+
+        let keys = &self.keys;
+        self.values.retain(|key, _| keys.contains(key));
+    }
+}
+
+/// An observed-remove set: every add is tagged with the replica that made it and that replica's count of adds, and a remove only takes away the tags that the removing replica has seen. An add that happens concurrently with a remove of the same element wins.
+///
+/// Removes leave nothing behind. Every replica keeps the highest count it has seen from each replica instead, so a tag that is missing here but covered by those counts was removed, and a late copy of an old add can't bring the element back.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct OrSet<T: Ord> {
+    entries: BTreeMap<T, BTreeSet<(Uuid, u64)>>,
+    seen: BTreeMap<Uuid, u64>,
+}
+
+impl<T: Ord> Default for OrSet<T> {
+    fn default() -> Self {
+        OrSet {
+            entries: BTreeMap::new(),
+            seen: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: Ord> OrSet<T> {
+    /// The new tag stands in for the tags the element already had here, which keeps an element that is added over and over at a single tag per replica that added it concurrently.
+    pub fn add(&mut self, replica: Uuid, value: T) {
+        let count = self.seen.entry(replica).or_insert(0);
+        *count += 1;
+        let tags = self.entries.entry(value).or_default();
+        tags.clear();
+        tags.insert((replica, *count));
+    }
+
+    pub fn remove(&mut self, value: &T) {
+        self.entries.remove(value);
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.entries.contains_key(value)
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &T> {
+        self.entries.keys()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn has_seen(&self, (replica, count): &(Uuid, u64)) -> bool {
+        matches!(self.seen.get(replica), Some(seen) if count <= seen)
+    }
+}
+
+impl<T> Crdt for OrSet<T>
+where
+    T: Ord + Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    fn merge(&mut self, other: &Self) {
+        let mut values: BTreeSet<T> = self.entries.keys().cloned().collect();
+        values.extend(other.entries.keys().cloned());
+
+        let empty = BTreeSet::new();
+        let mut entries = BTreeMap::new();
+        for value in values {
+            let mine = self.entries.get(&value).unwrap_or(&empty);
+            let theirs = other.entries.get(&value).unwrap_or(&empty);
+            // A tag only one side has is either news to the other side or was removed there
+            let tags: BTreeSet<(Uuid, u64)> = mine
+                .iter()
+                .filter(|tag| theirs.contains(tag) || !other.has_seen(tag))
+                .chain(theirs.iter().filter(|tag| !self.has_seen(tag)))
+                .copied()
+                .collect();
+            if !tags.is_empty() {
+                entries.insert(value, tags);
+            }
+        }
+        self.entries = entries;
+
+        for (replica, count) in &other.seen {
+            let local = self.seen.entry(*replica).or_insert(0);
+            *local = (*local).max(*count);
+        }
+    }
+}
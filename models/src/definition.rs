@@ -0,0 +1,258 @@
+//! A file format for processes so that new interaction flows can be written down without recompiling anything.
+//!
+//! A definition lists the parties that take part and the ordered steps, where every step names the message that has to be posted and the type of entity that posts it:
+//!
+//! ```toml
+//! name = "Signaling handshake"
+//! explanation = "Two clients exchange an SDP offer and answer through the server"
+//! blocking = true
+//!
+//! [[involved_parties]]
+//! entity_type = "Client"
+//! exactly = 2
+//!
+//! [[involved_parties]]
+//! entity_type = "Server"
+//!
+//! [[steps]]
+//! posted_by = "Client"
+//! message = "SdpOffer"
+//! ```
+//!
+//! The same structure can be written as JSON. Message names are looked up in a ```rust MessageRegistry ```, so every message used by a definition has to be registered before the definition can be turned into a process.
+
+use crate::registry::MessageRegistry;
+use crate::{Entities, EntityTypes, Message, Process};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProcessDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub explanation: String,
+    #[serde(default)]
+    pub blocking: bool,
+    #[serde(default)]
+    pub looping: bool,
+    pub involved_parties: Vec<PartyDefinition>,
+    pub steps: Vec<StepDefinition>,
+}
+
+/// How many entities of a type take part. Leaving out all of the counts means exactly one entity.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PartyDefinition {
+    pub entity_type: EntityTypes,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exactly: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub up_to: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at_least: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct StepDefinition {
+    pub posted_by: EntityTypes,
+    /// The name of a message in the registry
+    pub message: String,
+    /// Shown in place of the description of the message while the step is in focus
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessDefinitionErrors {
+    /// The file could not be read
+    Io(String),
+    /// Only files ending in .toml or .json can be loaded
+    UnsupportedFormat(String),
+    /// The text is not valid TOML/JSON or does not have the shape of a definition, which includes keys that a definition doesn't have
+    Parse(String),
+    MissingName,
+    NoSteps,
+    /// A party sets more than one of exactly, up_to and at_least
+    AmbiguousParty { party: usize },
+    /// The step uses a message that is not in the registry
+    UnknownMessage { step: usize, message: String },
+    /// The step is posted by a type of entity that is not one of the involved parties
+    PartyNotInvolved { step: usize, posted_by: EntityTypes },
+}
+
+impl fmt::Display for ProcessDefinitionErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessDefinitionErrors::Io(reason) => write!(f, "could not read the definition: {}", reason),
+            ProcessDefinitionErrors::UnsupportedFormat(extension) => {
+                write!(f, "unsupported definition format '{}', expected toml or json", extension)
+            }
+            ProcessDefinitionErrors::Parse(reason) => write!(f, "could not parse the definition: {}", reason),
+            ProcessDefinitionErrors::MissingName => write!(f, "the process needs a name"),
+            ProcessDefinitionErrors::NoSteps => write!(f, "the process needs at least one step"),
+            ProcessDefinitionErrors::AmbiguousParty { party } => write!(
+                f,
+                "party {}: only one of exactly, up_to and at_least can be set",
+                party
+            ),
+            ProcessDefinitionErrors::UnknownMessage { step, message } => {
+                write!(f, "step {}: no message named '{}' is registered", step, message)
+            }
+            ProcessDefinitionErrors::PartyNotInvolved { step, posted_by } => write!(
+                f,
+                "step {}: {:?} is not one of the involved parties",
+                step, posted_by
+            ),
+        }
+    }
+}
+
+impl PartyDefinition {
+    pub fn to_entities(&self) -> Option<Entities> {
+        let entity_type = self.entity_type;
+        match (self.exactly, self.up_to, self.at_least) {
+            (None, None, None) => Some(Entities::One(entity_type)),
+            (Some(count), None, None) => Some(Entities::Exactly(count, entity_type)),
+            (None, Some(count), None) => Some(Entities::UpTo(count, entity_type)),
+            (None, None, Some(count)) => Some(Entities::AtLeast(count, entity_type)),
+            _ => None,
+        }
+    }
+}
+
+/// Stands in for the message of a step. Processes only look at the name of a step, the actual payload arrives when the step is posted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageTemplate {
+    identity: Uuid,
+    name: String,
+    description: String,
+}
+
+impl Message for MessageTemplate {
+    fn identity(&self) -> Uuid {
+        self.identity
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    fn data(&self) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+impl ProcessDefinition {
+    pub fn from_toml_str(text: &str) -> Result<ProcessDefinition, ProcessDefinitionErrors> {
+        toml::from_str(text).map_err(|err| ProcessDefinitionErrors::Parse(err.to_string()))
+    }
+
+    pub fn from_json_str(text: &str) -> Result<ProcessDefinition, ProcessDefinitionErrors> {
+        serde_json::from_str(text).map_err(|err| ProcessDefinitionErrors::Parse(err.to_string()))
+    }
+
+    /// Picks the format by the extension of the file, a file of any other format isn't read at all.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<ProcessDefinition, ProcessDefinitionErrors> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        let parse = match extension.as_str() {
+            "toml" => ProcessDefinition::from_toml_str,
+            "json" => ProcessDefinition::from_json_str,
+            _ => return Err(ProcessDefinitionErrors::UnsupportedFormat(extension)),
+        };
+
+        let text = fs::read_to_string(path).map_err(|err| ProcessDefinitionErrors::Io(err.to_string()))?;
+        parse(&text)
+    }
+
+    /// Collects every problem with the definition instead of stopping at the first one. Steps and parties are counted from 0, like the index of a ```rust ProcessStep ```.
+    pub fn validate(&self, registry: &MessageRegistry) -> Result<(), Vec<ProcessDefinitionErrors>> {
+        let mut errors = Vec::new();
+
+        if self.name.trim().is_empty() {
+            errors.push(ProcessDefinitionErrors::MissingName);
+        }
+        if self.steps.is_empty() {
+            errors.push(ProcessDefinitionErrors::NoSteps);
+        }
+
+        for (party, definition) in self.involved_parties.iter().enumerate() {
+            if definition.to_entities().is_none() {
+                errors.push(ProcessDefinitionErrors::AmbiguousParty { party });
+            }
+        }
+
+        for (step, definition) in self.steps.iter().enumerate() {
+            if !registry.contains(&definition.message) {
+                errors.push(ProcessDefinitionErrors::UnknownMessage {
+                    step,
+                    message: definition.message.clone(),
+                });
+            }
+            if !self
+                .involved_parties
+                .iter()
+                .any(|party| party.entity_type == definition.posted_by)
+            {
+                errors.push(ProcessDefinitionErrors::PartyNotInvolved {
+                    step,
+                    posted_by: definition.posted_by,
+                });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Validates the definition and hands its parts to ```rust Process::new ```.
+    pub fn to_process<P: Process>(&self, registry: &MessageRegistry) -> Result<P, Vec<ProcessDefinitionErrors>> {
+        self.validate(registry)?;
+
+        let involved_parties = self
+            .involved_parties
+            .iter()
+            .filter_map(PartyDefinition::to_entities)
+            .collect();
+
+        let ordered_messages = self
+            .steps
+            .iter()
+            .map(|step| {
+                let template = MessageTemplate {
+                    identity: Uuid::new_v4(),
+                    name: step.message.clone(),
+                    description: step.description.clone().unwrap_or_default(),
+                };
+                (step.posted_by, Box::new(template) as Box<dyn Message>)
+            })
+            .collect();
+
+        Ok(P::new(
+            involved_parties,
+            ordered_messages,
+            self.name.clone(),
+            self.explanation.clone(),
+            self.blocking,
+            self.looping,
+        ))
+    }
+}
@@ -0,0 +1,113 @@
+//! The bus that carries ```rust InternalMessage ```s between the components of an environment. Components are registered under their ```rust get_uuid ``` and every message is delivered to exactly one of them, either to the component it is addressed to or, when routed, to the component that ```rust handles ``` its subject.
+//!
+//! Every delivery (and every message that had nowhere to go) is reported to the observers, which is how tests follow the message flow.
+
+use crate::{InternalMessage, InternalSystemComponents};
+
+use tokio::sync::{mpsc, Mutex};
+use uuid::Uuid;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as SyncMutex};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DispatchErrors {
+    /// No component is registered under the uuid
+    UnknownComponent { target: Uuid, message: InternalMessage },
+}
+
+/// A message as it passed through the dispatcher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dispatched {
+    /// The component that sent the message, None when it came from outside of the environment
+    pub from: Option<Uuid>,
+    pub to: Uuid,
+    pub message: InternalMessage,
+    /// False when there was no component to deliver to
+    pub delivered: bool,
+}
+
+type SharedComponent = Arc<Mutex<dyn InternalSystemComponents + Send>>;
+
+#[derive(Default)]
+pub struct Dispatcher {
+    components: HashMap<Uuid, SharedComponent>,
+    observers: SyncMutex<Vec<mpsc::UnboundedSender<Dispatched>>>,
+}
+
+impl Dispatcher {
+    pub fn new() -> Dispatcher {
+        Dispatcher::default()
+    }
+
+    /// Takes over the component and hands back a shared handle to it. A component that is registered under a uuid that is already taken replaces the earlier one.
+    pub fn register<C>(&mut self, component: C) -> Arc<Mutex<C>>
+    where
+        C: InternalSystemComponents + Send + 'static,
+    {
+        let uuid = component.get_uuid();
+        let shared = Arc::new(Mutex::new(component));
+        self.components.insert(uuid, shared.clone());
+        shared
+    }
+
+    pub fn unregister(&mut self, component: &Uuid) -> bool {
+        self.components.remove(component).is_some()
+    }
+
+    pub fn is_registered(&self, component: &Uuid) -> bool {
+        self.components.contains_key(component)
+    }
+
+    /// Every message that is dispatched from now on shows up on the returned receiver. Dropping the receiver stops the observation.
+    pub fn observe(&self) -> mpsc::UnboundedReceiver<Dispatched> {
+        let (observer, observed) = mpsc::unbounded_channel();
+        self.observers.lock().unwrap().push(observer);
+        observed
+    }
+
+    /// Delivers the message to the component registered under ```rust to ```.
+    ///
+    /// The component is locked while it handles the message, so a component must not be locked by the caller while a message is dispatched to it.
+    pub async fn dispatch(&self, from: Option<Uuid>, to: Uuid, message: InternalMessage) -> Result<(), DispatchErrors> {
+        let component = self.components.get(&to).cloned();
+        self.notify(Dispatched {
+            from,
+            to,
+            message: message.clone(),
+            delivered: component.is_some(),
+        });
+
+        match component {
+            Some(component) => {
+                component.lock().await.send_receive_messages(message).await;
+                Ok(())
+            }
+            None => Err(DispatchErrors::UnknownComponent { target: to, message }),
+        }
+    }
+
+    /// Delivers the message to the component that owns its subject, e.g. a ```rust StartProcess ``` to the process manager the process was registered with. A component registered under the subject itself comes first.
+    ///
+    /// Every component but the one registered under the subject is locked in turn to ask it, so none of them may be locked by the caller.
+    pub async fn route(&self, from: Option<Uuid>, message: InternalMessage) -> Result<(), DispatchErrors> {
+        let subject = message.subject();
+        if self.components.contains_key(&subject) {
+            return self.dispatch(from, subject, message).await;
+        }
+
+        for (uuid, component) in &self.components {
+            if component.lock().await.handles(&message) {
+                return self.dispatch(from, *uuid, message).await;
+            }
+        }
+        self.dispatch(from, subject, message).await
+    }
+
+    fn notify(&self, dispatched: Dispatched) {
+        self.observers
+            .lock()
+            .unwrap()
+            .retain(|observer| observer.send(dispatched.clone()).is_ok());
+    }
+}
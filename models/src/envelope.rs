@@ -0,0 +1,70 @@
+//! What travels over the websocket between the server and its clients: a ```rust Command ``` along with who sent it and whom it is for. Messages between two clients go through the server, which is then the intermediary.
+
+use crate::{Codec, CodecErrors, Command, Entity, EntityDetails, CODED_ERRORS_VERSION};
+
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+
+/// Where ```rust Command::Error ``` is in the enum, which is all that bincode puts on the wire to tell the variants apart
+const ERROR_VARIANT_INDEX: u32 = 4;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub sender: Entity,
+    pub receiver: Entity,
+    /// The entity that passes the envelope on to the receiver, None when the sender is directly connected to it
+    pub intermediary: Option<Entity>,
+    pub command: Command,
+}
+
+impl Envelope {
+    pub fn new(
+        sender: EntityDetails,
+        receiver: EntityDetails,
+        intermediary: Option<EntityDetails>,
+        command: Command,
+    ) -> Envelope {
+        Envelope {
+            sender: Entity::new(sender),
+            receiver: Entity::new(receiver),
+            intermediary: intermediary.map(Entity::new),
+            command,
+        }
+    }
+
+    /// Encodes the envelope the way a client that negotiated ```version``` decodes it: errors go to clients older than ```rust CODED_ERRORS_VERSION ``` as ```Error(String)```.
+    pub fn encode_for(&self, codec: Codec, version: u32) -> Result<Vec<u8>, CodecErrors> {
+        match &self.command {
+            Command::Error(error) if version < CODED_ERRORS_VERSION => codec.encode(&LegacyError {
+                envelope: self,
+                message: error.message(),
+            }),
+            _ => codec.encode(self),
+        }
+    }
+}
+
+/// An envelope carrying an error as ```Command::Error(String)```, the way it was before the errors got codes
+struct LegacyError<'a> {
+    envelope: &'a Envelope,
+    message: String,
+}
+
+impl Serialize for LegacyError<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut envelope = serializer.serialize_struct("Envelope", 4)?;
+        envelope.serialize_field("sender", &self.envelope.sender)?;
+        envelope.serialize_field("receiver", &self.envelope.receiver)?;
+        envelope.serialize_field("intermediary", &self.envelope.intermediary)?;
+        envelope.serialize_field("command", &LegacyCommand(&self.message))?;
+        envelope.end()
+    }
+}
+
+struct LegacyCommand<'a>(&'a str);
+
+impl Serialize for LegacyCommand<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_variant("Command", ERROR_VARIANT_INDEX, "Error", self.0)
+    }
+}
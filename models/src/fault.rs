@@ -0,0 +1,318 @@
+//! A ```rust CommunicationChannel ``` wrapper that misbehaves on purpose, for testing how the rest of the system copes with a bad network. Every decision is drawn from a seeded RNG, so a run with the same seed and the same messages misbehaves the same way.
+//!
+//! Only messages sent through the wrapper are affected, so wrap both ends of a channel to disturb both directions. Latency and the severing schedule are measured on tokio time, which lets tests run them with paused time instead of waiting.
+
+use crate::{
+    ChannelType, CommunicationChannel, CommunicationErrors, Entity, InternalMessage,
+    InternalSystemComponents, Message, PingTime, Process, RawMessage,
+};
+
+use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::sync::watch;
+use tokio::time::{sleep_until, Duration, Instant};
+use uuid::Uuid;
+
+use std::sync::{Arc, Mutex};
+
+/// What goes wrong on a polluted channel. The chances are between 0 and 1 and are drawn independently for every message.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Faults {
+    /// Every message is delayed by a duration between the two, messages with different delays overtake each other
+    pub latency: (Duration, Duration),
+    pub drop: f64,
+    pub duplicate: f64,
+    /// The message is held back and sent right after the next one
+    pub reorder: f64,
+    /// A random bit of the message's data is flipped
+    pub corrupt: f64,
+    /// How long after being polluted the channel is cut off for good
+    pub sever_after: Option<Duration>,
+}
+
+impl Faults {
+    pub fn none() -> Faults {
+        Faults::default()
+    }
+}
+
+/// How many messages were sent through a polluted channel and what happened to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FaultStats {
+    pub sent: u64,
+    pub dropped: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+    pub corrupted: u64,
+    pub delayed: u64,
+}
+
+struct Injector {
+    faults: Faults,
+    rng: StdRng,
+    /// Set while the faults are applied, together with the moment the channel gets severed
+    polluted: Option<Option<Instant>>,
+    held_back: Option<Box<dyn Message>>,
+    stats: FaultStats,
+}
+
+impl Injector {
+    fn chance(&mut self, probability: f64) -> bool {
+        // Zero chances don't draw, so that enabling one fault doesn't change the draws of the others
+        probability > 0.0 && self.rng.gen_bool(probability.min(1.0))
+    }
+
+    fn delay(&mut self) -> Duration {
+        let (min, max) = self.faults.latency;
+        if max > min {
+            self.rng.gen_range(min..=max)
+        } else {
+            min
+        }
+    }
+
+    /// The messages to send in place of the given one, each with its delay
+    fn plan(&mut self, message: Box<dyn Message>) -> Vec<(Duration, Box<dyn Message>)> {
+        if self.polluted.is_none() {
+            let held_back = self.held_back.take();
+            return std::iter::once(message)
+                .chain(held_back)
+                .map(|message| (Duration::ZERO, message))
+                .collect();
+        }
+        self.stats.sent += 1;
+
+        if self.chance(self.faults.drop) {
+            self.stats.dropped += 1;
+            return vec![];
+        }
+
+        let corrupt = self.chance(self.faults.corrupt);
+        let duplicate = self.chance(self.faults.duplicate);
+        let mut messages = match RawMessage::from_message(message.as_ref()) {
+            // Messages without data can't be copied or corrupted and go through as they are
+            Err(_) => vec![message],
+            Ok(mut raw) => {
+                if corrupt && !raw.data.is_empty() {
+                    let bit = self.rng.gen_range(0..raw.data.len() * 8);
+                    raw.data[bit / 8] ^= 1 << (bit % 8);
+                    self.stats.corrupted += 1;
+                }
+                let mut messages: Vec<Box<dyn Message>> = vec![];
+                if duplicate {
+                    messages.push(Box::new(raw.clone()));
+                    self.stats.duplicated += 1;
+                }
+                if corrupt || duplicate {
+                    messages.push(Box::new(raw));
+                } else {
+                    messages.push(message);
+                }
+                messages
+            }
+        };
+
+        if self.held_back.is_none() && self.chance(self.faults.reorder) {
+            self.held_back = messages.pop();
+            self.stats.reordered += 1;
+        } else if let Some(held_back) = self.held_back.take() {
+            messages.push(held_back);
+        }
+
+        messages
+            .into_iter()
+            .map(|message| {
+                let delay = self.delay();
+                if delay > Duration::ZERO {
+                    self.stats.delayed += 1;
+                }
+                (delay, message)
+            })
+            .collect()
+    }
+}
+
+/// Clones share the wrapped channel, the RNG and the statistics.
+pub struct FaultyChannel<C> {
+    inner: Arc<C>,
+    injector: Arc<Mutex<Injector>>,
+    severed: Arc<watch::Sender<bool>>,
+}
+
+impl<C> Clone for FaultyChannel<C> {
+    fn clone(&self) -> Self {
+        FaultyChannel {
+            inner: self.inner.clone(),
+            injector: self.injector.clone(),
+            severed: self.severed.clone(),
+        }
+    }
+}
+
+impl<C: CommunicationChannel + 'static> FaultyChannel<C> {
+    /// Wraps the channel with the faults applied right away.
+    pub fn new(inner: C, faults: Faults, seed: u64) -> FaultyChannel<C> {
+        let channel = FaultyChannel::dormant(inner, faults, seed);
+        channel.pollute();
+        channel
+    }
+
+    /// Wraps the channel without disturbing it until ```rust pollute ``` is called or ```rust InternalMessage::PolluteProcess ``` arrives with the identity of the channel.
+    pub fn dormant(inner: C, faults: Faults, seed: u64) -> FaultyChannel<C> {
+        FaultyChannel {
+            inner: Arc::new(inner),
+            injector: Arc::new(Mutex::new(Injector {
+                faults,
+                rng: StdRng::seed_from_u64(seed),
+                polluted: None,
+                held_back: None,
+                stats: FaultStats::default(),
+            })),
+            severed: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    /// Starts applying the faults. The severing schedule starts counting now.
+    pub fn pollute(&self) {
+        let mut injector = self.injector.lock().unwrap();
+        if injector.polluted.is_none() {
+            let sever_at = injector.faults.sever_after.map(|after| Instant::now() + after);
+            injector.polluted = Some(sever_at);
+        }
+    }
+
+    /// Stops applying the faults. A message that is held back for reordering is sent with the next one.
+    pub fn clean(&self) {
+        self.injector.lock().unwrap().polluted = None;
+    }
+
+    pub fn is_polluted(&self) -> bool {
+        self.injector.lock().unwrap().polluted.is_some()
+    }
+
+    /// Cuts the channel off: sends fail, receives end and messages still in flight are lost.
+    pub fn sever(&self) {
+        self.severed.send_replace(true);
+    }
+
+    pub fn is_severed(&self) -> bool {
+        if *self.severed.borrow() {
+            return true;
+        }
+        match self.sever_at() {
+            Some(sever_at) if Instant::now() >= sever_at => {
+                self.sever();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn stats(&self) -> FaultStats {
+        self.injector.lock().unwrap().stats
+    }
+
+    fn sever_at(&self) -> Option<Instant> {
+        self.injector.lock().unwrap().polluted.flatten()
+    }
+}
+
+fn far_future() -> Instant {
+    Instant::now() + Duration::from_secs(60 * 60 * 24 * 365)
+}
+
+#[async_trait]
+impl<C: CommunicationChannel + 'static> CommunicationChannel for FaultyChannel<C> {
+    /// Initializes the wrapped channel, the faults carry over to the channel that comes out of it.
+    async fn initialize(
+        &self,
+        setup_channel: impl Process + Send,
+        participant: Entity,
+        keep_alive: PingTime,
+        channel_type: ChannelType,
+    ) -> Result<(Entity, Self), CommunicationErrors> {
+        let (participant, inner) = self
+            .inner
+            .initialize(setup_channel, participant, keep_alive, channel_type)
+            .await?;
+
+        Ok((
+            participant,
+            FaultyChannel {
+                inner: Arc::new(inner),
+                injector: self.injector.clone(),
+                severed: self.severed.clone(),
+            },
+        ))
+    }
+
+    /// Dropped messages count as sent, like they would on a real network.
+    async fn send(
+        &self,
+        sender: Entity,
+        receiver: Entity,
+        message: Box<dyn Message>,
+    ) -> Result<(), CommunicationErrors> {
+        if self.is_severed() {
+            return Err(CommunicationErrors::ChannelClosed);
+        }
+
+        let planned = self.injector.lock().unwrap().plan(message);
+        for (delay, message) in planned {
+            if delay == Duration::ZERO {
+                self.inner.send(sender, receiver, message).await?;
+                continue;
+            }
+
+            let delayed = self.clone();
+            let deliver_at = Instant::now() + delay;
+            tokio::spawn(async move {
+                sleep_until(deliver_at).await;
+                if !delayed.is_severed() {
+                    let _ = delayed.inner.send(sender, receiver, message).await;
+                }
+            });
+        }
+        Ok(())
+    }
+
+    async fn receive(&self) -> Option<Box<dyn Message>> {
+        if self.is_severed() {
+            return None;
+        }
+
+        let mut severed = self.severed.subscribe();
+        let sever_at = self.sever_at().unwrap_or_else(far_future);
+        // Biased, so that a message and the sever arriving together always end the same way
+        tokio::select! {
+            biased;
+            received = self.inner.receive() => if self.is_severed() { None } else { received },
+            _ = severed.wait_for(|severed| *severed) => None,
+            _ = sleep_until(sever_at) => {
+                self.sever();
+                None
+            }
+        }
+    }
+
+    fn identity(&self) -> Uuid {
+        self.inner.identity()
+    }
+}
+
+#[async_trait]
+impl<C: CommunicationChannel + 'static> InternalSystemComponents for FaultyChannel<C> {
+    /// ```rust PolluteProcess ``` pollutes the channel and ```rust CloseChannel ``` severs it, when they carry its identity.
+    async fn send_receive_messages(&mut self, message: InternalMessage) {
+        match message {
+            InternalMessage::PolluteProcess(channel) if channel == self.identity() => self.pollute(),
+            InternalMessage::CloseChannel(channel) if channel == self.identity() => self.sever(),
+            _ => {}
+        }
+    }
+
+    fn get_uuid(&self) -> Uuid {
+        self.identity()
+    }
+}
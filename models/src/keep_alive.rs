@@ -0,0 +1,142 @@
+//! Decides when each channel gets pinged according to its ```rust PingTime ``` and when a channel has gone quiet for long enough to be closed.
+//!
+//! The keep-alive never looks at a clock. Whoever drives it passes the current time into ```rust tick ```, in whatever unit the ```rust PingTime ``` values were given in (the server uses rounds of the game loop). This keeps the schedule reproducible: together with a seeded RNG for ```rust PingTime::RandomBetween ``` the same calls always produce the same pings.
+
+use crate::{PingStatus, PingTime};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use uuid::Uuid;
+
+use std::collections::BTreeMap;
+
+/// What the owner of the channels has to do after a ```rust tick ```.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepAliveAction {
+    /// Send a ping carrying this time over the channel. The reply should carry the same time.
+    Ping { channel: Uuid, at: u64 },
+    /// The channel missed too many replies and is no longer watched. Usually answered with ```rust InternalMessage::CloseChannel ```.
+    Close(Uuid),
+}
+
+struct WatchedChannel {
+    ping_time: PingTime,
+    status: PingStatus,
+    next_ping: Option<u64>,
+    missed_replies: u32,
+}
+
+pub struct KeepAlive {
+    max_missed_replies: u32,
+    rng: StdRng,
+    /// Ordered by uuid so that the actions of a tick always come out in the same order
+    channels: BTreeMap<Uuid, WatchedChannel>,
+}
+
+impl KeepAlive {
+    /// A channel is closed at the ping that would follow its max_missed_replies-th unanswered ping.
+    pub fn new(max_missed_replies: u32) -> KeepAlive {
+        KeepAlive::with_rng(max_missed_replies, StdRng::from_entropy())
+    }
+
+    /// Same as ```rust new ``` but the random intervals are drawn from a seeded RNG.
+    pub fn seeded(max_missed_replies: u32, seed: u64) -> KeepAlive {
+        KeepAlive::with_rng(max_missed_replies, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(max_missed_replies: u32, rng: StdRng) -> KeepAlive {
+        KeepAlive {
+            max_missed_replies,
+            rng,
+            channels: BTreeMap::new(),
+        }
+    }
+
+    /// Starts watching a channel. The first ping goes out on the next tick, watching a channel again resets it.
+    pub fn watch(&mut self, channel: Uuid, ping_time: PingTime, now: u64) {
+        let next_ping = match ping_time {
+            PingTime::Never => None,
+            _ => Some(now),
+        };
+
+        self.channels.insert(
+            channel,
+            WatchedChannel {
+                ping_time,
+                status: PingStatus::NeverPinged,
+                next_ping,
+                missed_replies: 0,
+            },
+        );
+    }
+
+    pub fn forget(&mut self, channel: &Uuid) {
+        self.channels.remove(channel);
+    }
+
+    pub fn is_watching(&self, channel: &Uuid) -> bool {
+        self.channels.contains_key(channel)
+    }
+
+    pub fn status(&self, channel: &Uuid) -> Option<&PingStatus> {
+        self.channels.get(channel).map(|watched| &watched.status)
+    }
+
+    /// Records the reply to a ping and tells whether it was taken. Only a reply that carries the time of the outstanding ping counts, so replies to older pings, repeated replies and replies to channels that aren't watched (anymore) are ignored.
+    pub fn pong(&mut self, channel: &Uuid, at: u64) -> bool {
+        match self.channels.get_mut(channel) {
+            Some(watched) if watched.status == PingStatus::Pinged(at) => {
+                watched.status = PingStatus::Ponged(at);
+                watched.missed_replies = 0;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Pings every channel that is due, and closes the ones that never answered their last pings.
+    pub fn tick(&mut self, now: u64) -> Vec<KeepAliveAction> {
+        let mut actions = Vec::new();
+        let mut closed = Vec::new();
+
+        for (channel, watched) in self.channels.iter_mut() {
+            match watched.next_ping {
+                Some(next_ping) if next_ping <= now => {}
+                _ => continue,
+            }
+
+            if let PingStatus::Pinged(_) = watched.status {
+                watched.missed_replies += 1;
+                if watched.missed_replies >= self.max_missed_replies {
+                    closed.push(*channel);
+                    actions.push(KeepAliveAction::Close(*channel));
+                    continue;
+                }
+            }
+
+            watched.status = PingStatus::Pinged(now);
+            watched.next_ping = next_interval(&watched.ping_time, &mut self.rng).map(|interval| now + interval);
+            actions.push(KeepAliveAction::Ping {
+                channel: *channel,
+                at: now,
+            });
+        }
+
+        for channel in closed {
+            self.channels.remove(&channel);
+        }
+        actions
+    }
+}
+
+/// Intervals are at least 1 so that a channel is never pinged twice within the same tick.
+fn next_interval(ping_time: &PingTime, rng: &mut StdRng) -> Option<u64> {
+    match *ping_time {
+        PingTime::Never => None,
+        PingTime::Every(interval) => Some(u64::from(interval.max(1))),
+        PingTime::RandomBetween(a, b) => {
+            let (low, high) = if a <= b { (a, b) } else { (b, a) };
+            Some(u64::from(rng.gen_range(low..=high).max(1)))
+        }
+    }
+}
@@ -2,36 +2,73 @@
 
 use petgraph::EdgeType;
 use serde::{Deserialize, Serialize};
-use erased_serde::Serialize as ErasedSerialize;
 use uuid::Uuid;
 
 
 // use anyhow::Error;
 
 
-/*
-HtmlMediaElement, MediaDevices, MediaStream, MediaStreamConstraints, MediaStreamTrack,
-    MessageEvent, RtcConfiguration, RtcIceCandidate, RtcIceCandidateInit, RtcIceConnectionState,
-    RtcOfferOptions, RtcPeerConnection, RtcPeerConnectionIceEvent, RtcRtpReceiver,
-    RtcRtpTransceiver, RtcRtpTransceiverDirection, RtcSdpType, RtcSessionDescriptionInit,
-    RtcTrackEvent,
-*/
-use web_sys::{
-     WebSocket as WS,
-};
-
 use petgraph::graphmap::{GraphMap, UnGraphMap};
 
 
 use std::net::SocketAddr;
 use std::{
+    any::Any,
     collections::{HashMap},
     hash::Hash,
 };
 
+pub mod codec;
+pub mod correlation;
+pub mod crdt;
+pub mod definition;
+pub mod dispatcher;
+pub mod envelope;
+pub mod fault;
+pub mod keep_alive;
+pub mod loopback;
+pub mod matchmaking;
+#[cfg(feature = "native")]
+pub mod native_websocket;
+pub mod policy;
+pub mod presence;
+pub mod process;
+pub mod process_manager;
+pub mod protocol;
+pub mod registry;
+pub mod replicated;
+pub mod server_state;
+pub mod state;
+pub mod topology;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub mod wasm_websocket;
+
+pub use codec::{Codec, CodecErrors};
+pub use correlation::{Correlation, InOrder, PendingReplies, Sequencer};
+pub use crdt::{Crdt, CrdtMap, GCounter, LwwRegister, OrSet};
+pub use definition::{ProcessDefinition, ProcessDefinitionErrors};
+pub use dispatcher::{Dispatched, DispatchErrors, Dispatcher};
+pub use envelope::Envelope;
+pub use fault::{FaultStats, FaultyChannel, Faults};
+pub use keep_alive::{KeepAlive, KeepAliveAction};
+pub use matchmaking::{Matchmaker, RandomPairs};
+pub use policy::CommunicationPolicy;
+pub use presence::{allowed, transition, IllegalTransition, PresenceEvent};
+pub use process::{DeclarativeProcess, JournalSink, ProcessErrors, ProcessStep};
+pub use process_manager::{check_parties, LocalProcessManager, PartyMismatch, ProcessManagerErrors};
+pub use protocol::{
+    Capability, Handshake, Negotiated, ProtocolError, ProtocolMismatch, CODED_ERRORS_VERSION,
+    MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+pub use registry::{MessageErrors, MessageRegistry, RawMessage, TypedMessage};
+pub use replicated::{ReplicatedStateManager, StateUpdate};
+pub use server_state::{Effect, Inbound, OnlineClient, ServerState, MATCH_TIMEOUT_ROUNDS};
+pub use state::{StateDiff, StateErrors, StateManager, StateSnapshot};
+pub use topology::{ForwardStep, Forwarded, RoutingErrors, Topology};
+
 use async_trait::async_trait;
 
-#[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Eq, Clone)]
+#[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct Client {
     pub username: Option<String>,
     pub email: Option<String>,
@@ -43,6 +80,8 @@ pub enum Status {
     InCall(Uuid, Uuid),
     WaitingForPartner,
     AnsweringQuestionAboutLastPartner,
+    /// Online but not to be paired
+    Idle,
 }
 
 // #[derive(Debug, Serialize, Deserialize, Hash, Clone, Eq, PartialEq)]
@@ -78,7 +117,7 @@ impl Client {
         }
     }
 }
-#[derive(Debug, Serialize, Deserialize, Eq, Hash, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
 ///This enum will be used for keeping the connections alive and informing the clients of the round number
 pub enum PingStatus {
     /// This is when the client has last been communicated with, the u64 value refers to the round number
@@ -89,57 +128,26 @@ pub enum PingStatus {
     Ponged(u64),
 }
 
-impl PartialEq for PingStatus {
-    fn eq(&self, other: &PingStatus) -> bool {
-        match self {
-            PingStatus::Pinged(a) => match other {
-                PingStatus::Pinged(c) => {
-                    if a == c {
-                        true
-                    } else {
-                        false
-                    }
-                }
-                PingStatus::NeverPinged => false,
-                PingStatus::Ponged(_d) => false,
-            },
-            PingStatus::NeverPinged => match other {
-                PingStatus::Pinged(_c) => false,
-                PingStatus::NeverPinged => true,
-                PingStatus::Ponged(_d) => false,
-            },
-            PingStatus::Ponged(a) => match other {
-                PingStatus::Pinged(_c) => false,
-                PingStatus::NeverPinged => false,
-                PingStatus::Ponged(d) => {
-                    if a == d {
-                        true
-                    } else {
-                        false
-                    }
-                }
-            },
-        }
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum EntityTypes {
     Client,
     Server,
 }
 
-type NetworkTopology = GraphMap<Entity, (Entity,Entity), Undirected>;
+/// Every entity an environment knows about, connected by the channels that are open between them. Convert it into a ```rust Topology ``` for routing.
+pub type NetworkTopology = GraphMap<Entity, (Entity,Entity), Undirected>;
 
 #[async_trait]
 pub trait Environment : InternalSystemComponents{
     async fn initialize(&mut self, communication_manager : impl CommunicationManager,  environment_processes : Vec<impl Process>) -> Result<(NetworkTopology, Entity), EnvironmentErrors>;
     async fn run(&mut self);
+    /// The protocol version the environment speaks, usually ```rust PROTOCOL_VERSION ```
     fn version(&self) -> u32;
     fn identity(&self) -> Entity; 
 
 }
 
+#[derive(Debug, Clone, Copy, Default)]
 pub struct Undirected  {
 }
 
@@ -156,47 +164,109 @@ pub enum EnvironmentErrors {
 
 #[async_trait]
 pub trait CommunicationManager : InternalSystemComponents {
-    /// The return Entity refers to the identity of the environment. In this system, the identity refers to the environment instead of the user. This is mainly because Entities are used to connect the network in a certain topology...
-async fn new(&mut self, allowed_communication_types : Vec<(EntityTypes,EntityTypes)>, signaling_server : (Entity, impl CommunicationChannel), signal_channel_initialization : impl Process) -> (Self, Entity) where Self : Sized;
-async fn add_channel(&mut self, channel: impl CommunicationChannel, participant :Entity) -> Result<(), CommunicationErrors>;
-async fn open_channels(&self) -> &Vec<(&dyn CommunicationChannel, Entity)>;
-async fn register_process_message(&self, waiting_process : impl Process, wait_for_message : impl Message);
-async fn pop_queue(&mut self) -> dyn Message;
-async fn add_to_queue(&mut self, message: dyn Message);  
+    /// The identity refers to the environment instead of the user. This is mainly because Entities are used to connect the network in a certain topology...
+    async fn new(identity : Entity, allowed_communication_types : Vec<(EntityTypes,EntityTypes)>) -> Self where Self : Sized;
+    async fn add_channel(&mut self, channel: impl CommunicationChannel + 'static, participant :Entity) -> Result<(), CommunicationErrors>;
+    /// Lists the identity of every open channel along with the participant on the other end of it.
+    async fn open_channels(&self) -> Vec<(Uuid, Entity)>;
+    async fn send(&self, receiver : &Entity, message : Box<dyn Message>) -> Result<(), CommunicationErrors>;
+    async fn register_process_message(&mut self, waiting_process : Uuid, wait_for_message : String);
+    /// Waits for the next message from any of the open channels. Messages placed with ```rust add_to_queue ``` are handed out first.
+    async fn pop_queue(&mut self) -> Option<(Entity, Box<dyn Message>)>;
+    async fn add_to_queue(&mut self, sender : Entity, message: Box<dyn Message>);
 
 
 }
 
 #[async_trait]
 pub trait InternalSystemComponents {
-    async fn send_receive_messages(&self, message : InternalMessage);
+    async fn send_receive_messages(&mut self, message : InternalMessage);
     fn get_uuid(&self) -> Uuid;
+    /// Whether the component owns the process or channel that the message is about. The dispatcher routes messages by it.
+    fn handles(&self, _message: &InternalMessage) -> bool {
+        false
+    }
 }
 
 /// The process manager hooks up the process runtime with the network topology. It keeps track of receving internal control messages from the communication manager.
 #[async_trait]
 pub trait ProcessManager : InternalSystemComponents{
     async fn initialize(identity : Entity) -> Self where Self : Sized;
-    fn register_functionality(process : impl Process);
+    /// Hands a process over to the manager, the returned uuid is used for starting it.
+    fn register_functionality(&mut self, process : impl Process + Send + Sync + 'static) -> Uuid where Self : Sized;
+    /// Replaces the topology that the involved parties of processes are checked against.
+    fn update_topology(&mut self, topology : NetworkTopology);
+    /// Refuses to start the process when the topology can't provide the parties it involves.
+    async fn start_process(&mut self, process : Uuid) -> Result<(), ProcessManagerErrors>;
 }
 
-pub struct StateManager {
-    state: HashMap<String, Box<dyn ErasedSerialize>>
-}
 
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CommunicationErrors {
+    /// The communication policy doesn't allow the two entities to talk to each other
     DisallowedEntityType,
     ChannelInitializationFailed,
-} 
+    /// There is no open channel to the requested participant
+    UnknownParticipant,
+    /// The other end of the channel has gone away
+    ChannelClosed,
+    /// The message couldn't be put on the wire, e.g. because it doesn't carry any data
+    Unencodable(String),
+}
 
-pub trait Message {
+/// Messages are passed around as ```rust Box<dyn Message> ```. The name is used for looking up the concrete type in a ```rust MessageRegistry ``` so that the bytes returned by ```rust data ``` can be decoded on the other side.
+pub trait Message : AsAny + Send + Sync + 'static {
     fn identity(&self) -> Uuid;
     fn name(&self) -> String;
     fn description(&self) -> String;
-    fn data(&self, send_data : Option<Box<dyn ErasedSerialize>>) -> Option<Vec<u8>>;
+    /// The encoded payload of the message. None means the message doesn't carry any data and can't be sent over the wire.
+    fn data(&self) -> Option<Vec<u8>>;
+    /// Where the message belongs in its conversation. Messages without one can't be matched to a request or put in order.
+    fn correlation(&self) -> Option<Correlation> {
+        None
+    }
+}
+
+impl dyn Message {
+    pub fn is<T: Message>(&self) -> bool {
+        self.as_any().is::<T>()
+    }
+
+    pub fn downcast_ref<T: Message>(&self) -> Option<&T> {
+        self.as_any().downcast_ref::<T>()
+    }
+
+    /// Gives back the concrete message, or the message untouched if it was some other type.
+    pub fn downcast<T: Message>(self: Box<Self>) -> Result<Box<T>, Box<dyn Message>> {
+        if self.is::<T>() {
+            Ok(self
+                .into_any()
+                .downcast::<T>()
+                .expect("the type was checked right before downcasting"))
+        } else {
+            Err(self)
+        }
+    }
+}
+
+/// Lets a ```rust dyn Message ``` be turned back into the concrete type it was made from. This is implemented for every type, so messages never implement it by hand.
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
 }
 /// This enum will be used for communicating between the major independent components of the system. As of writing, these include: 1) The process manager, 2) The state manager, 3) The Network manager. All communication between entities will be specified by sending processes between network entities. The process manager and communcation(network) manager will then orchestrate amongst themselves how processes are carried out. In order for the system to be very flexible and extensible, the state manager and process manager are going to be defined in terms of traits (abstract interfaces). Also, the data-structures used for distributed state management is based on p2p consensus, allowing for the applications to be more akin to configurations of a dynamic/powerful system built with the rust language. The internal messages are separated from process messages for another good reason: Any internal message interpreted within an environment or system can easily considered to have fully-authorized permission and allows for rapid velocity of development.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum InternalMessage{
     StartProcess(Uuid),
     PolluteProcess(Uuid),
@@ -206,14 +276,28 @@ pub enum InternalMessage{
     OpenChannel(Uuid)
 }
 
+impl InternalMessage {
+    /// The uuid the message is about: a process for the process messages and a channel for the channel messages.
+    pub fn subject(&self) -> Uuid {
+        match self {
+            InternalMessage::StartProcess(uuid)
+            | InternalMessage::PolluteProcess(uuid)
+            | InternalMessage::AdvanceProcess(uuid)
+            | InternalMessage::SendProcess(uuid)
+            | InternalMessage::CloseChannel(uuid)
+            | InternalMessage::OpenChannel(uuid) => *uuid,
+        }
+    }
+}
+
 #[async_trait]
-pub trait CommunicationChannel :  ErasedSerialize{
+pub trait CommunicationChannel : Send + Sync {
     /// The first element in the returned tuple will be the identity of the client who sent the initialize process
     /// The second item represents the role that the client is currently allowed to take on
-    async fn initialize  (&self, setup_channel : impl Process, participant: Entity, keep_alive : PingTime, channel_type : ChannelType) -> Result<(Entity, Self), CommunicationErrors> where Self : Sized; 
-    async fn send(&self, sender: Entity, receiver : Entity, message: impl Message) where Self : Sized;
-    fn channel(&self) -> (tokio::sync::mpsc::Sender<Box<dyn Message>>,tokio::sync::mpsc::Receiver<Box<dyn Message>>); 
-    async fn receive(&self, sender: EntityDetails, message: impl Message) where Self : Sized;
+    async fn initialize  (&self, setup_channel : impl Process + Send, participant: Entity, keep_alive : PingTime, channel_type : ChannelType) -> Result<(Entity, Self), CommunicationErrors> where Self : Sized; 
+    async fn send(&self, sender: Entity, receiver : Entity, message: Box<dyn Message>) -> Result<(), CommunicationErrors>;
+    /// Waits for the next message arriving on this channel. Returns None once the other end has been dropped.
+    async fn receive(&self) -> Option<Box<dyn Message>>;
     fn identity(&self) -> Uuid;
 }
 
@@ -228,30 +312,9 @@ pub enum ChannelType {
 //     /// The environment will use this to negotiate the lifetime of the process
 //     pub keep_alive : PingTime
 
-struct WebSocket {
-    websocket : WS,
-    channel: (tokio::sync::mpsc::Sender<Box<dyn Message>>,tokio::sync::mpsc::Receiver<Box<dyn Message>>),
-
-
-}
 
-// #[async_trait]
-// impl CommunicationChannel for WebSocket {
-//     async fn initialize  (&self, process : Process) -> (&Self, Entity, Vec<Role>) where Self : Sized {
-//         todo!()
-//     }
-
-//     async fn send(&self, sender: Entity, receiver : Entity, message: ContextualizedCommand) where Self : Sized {
-//         todo!()
-//     }
-
-//     fn channel(&self) -> (tokio::sync::mpsc::Sender<ContextualizedCommand>,tokio::sync::mpsc::Receiver<ContextualizedCommand>) {
-//         todo!()
-//     }
-// }
 
-
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct Entity {
     pub entity_type: EntityTypes,
     pub entity_detail: EntityDetails,
@@ -270,9 +333,14 @@ impl Entity {
         }
     }
 
+    pub fn uuid(&self) -> Uuid {
+        match self.entity_detail {
+            EntityDetails::Client(uuid, _) | EntityDetails::Server(uuid, _) => uuid,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum EntityDetails {
     Client(uuid::Uuid, Option<SocketAddr>),
     Server(uuid::Uuid, SocketAddr),
@@ -280,7 +348,7 @@ pub enum EntityDetails {
 
 
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub enum Command {
     /// This will send out the most up-to-date state of the online clients
     BroadcastUpdate,
@@ -290,8 +358,8 @@ pub enum Command {
     EndCall(Uuid, Uuid),
     ///This will inform all clients of the current state of the system... In the future this will not need to be sent to all clients, instead it can be sent to a strongly-connected client which then propagates the updates to all other clients
     UpdateClient(Client),
-    /// For the time being, the error will be a string. In the future, it will be a struct/enum containing all possible errors that could occur
-    Error(String),
+    /// Something the client did went wrong, see ProtocolError for the codes
+    Error(ProtocolError),
     // This indicates that this client is ready to be paired at whatever future round, the server will respond with a Self::OnlineClients variant
     // ReadyForPartner(Client),
     ///  When the server is initiated, the server sends this to the client and the client responds in turn (of course, changing the MessageDirection).
@@ -312,11 +380,21 @@ pub enum Command {
     Ping(Uuid, u64),
     /// Websocket Pong
     Pong(Uuid, u64),
+    /// The client answers ServerInitiated with the protocol versions and capabilities it speaks
+    Handshake(Handshake),
+    /// The server settled on a version with the client. When the versions don't overlap the server answers with ProtocolError::VersionMismatch instead.
+    HandshakeAccepted(Negotiated),
+    /// Sent by a client for the presence events only it knows about, see PresenceEvent::is_reported_by_client
+    UpdatePresence(PresenceEvent),
+    /// Sent by the server to both clients of a pair it matched up. The first uuid is the initiator, who sends the sdp offer to the second one.
+    Matched(Uuid, Uuid),
+    /// Sent by a client that is answering questions about its last partner: how the call went, from -10 (extremely bad) to 10 (extremely good). The server uses it for matchmaking.
+    RateLastPartner(i32),
 }
 
 
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub enum Entities {
     One(EntityTypes),
     Exactly(u32, EntityTypes),
@@ -324,6 +402,27 @@ pub enum Entities {
     AtLeast(u32, EntityTypes)
 }
 
+impl Entities {
+    pub fn entity_type(&self) -> EntityTypes {
+        match self {
+            Entities::One(entity_type)
+            | Entities::Exactly(_, entity_type)
+            | Entities::UpTo(_, entity_type)
+            | Entities::AtLeast(_, entity_type) => *entity_type,
+        }
+    }
+
+    /// The smallest and largest number of entities that satisfy the requirement, None meaning there is no upper limit.
+    pub fn bounds(&self) -> (u32, Option<u32>) {
+        match self {
+            Entities::One(_) => (1, Some(1)),
+            Entities::Exactly(count, _) => (*count, Some(*count)),
+            Entities::UpTo(count, _) => (0, Some(*count)),
+            Entities::AtLeast(count, _) => (*count, None),
+        }
+    }
+}
+
 
 
 
@@ -332,27 +431,35 @@ pub trait Process : InternalSystemComponents{
     fn new(involved_parties : Vec<Entities>,
         ordered_messages : Vec<(EntityTypes, Box<dyn Message> )> ,name: String, explanation: String, blocking : bool, looping: bool) -> Self where Self: Sized;
 
-    async fn log_step(&mut self, step : impl Message, status: ProcessStatus, posted_by : Entity);
+    async fn log_step(&mut self, step : &dyn Message, status: ProcessStatus, posted_by : Entity);
+
+    fn involved_parties(&self) -> &[Entities];
 
-    fn waiting_for_message_type(&self) -> dyn Message;
+    /// The name of the message that the process is currently waiting for, if any.
+    fn waiting_for_message_type(&self) -> Option<String>;
     
-    async fn receive_message(&self, message : dyn Message);
+    /// Feeds a message that was posted by another entity into the process. The returned status tells whether the message moved the process along.
+    async fn receive_message(&mut self, message : Box<dyn Message>, posted_by : Entity) -> Result<ProcessStatus, ProcessErrors>;
     /// Sends the message and pushes the 'focus token' onto the next message in the  ```rust ordered_message_pairs ```
-    async fn send_message(&mut self, message: dyn Message);
+    async fn send_message(&mut self, message: Box<dyn Message>, posted_by : Entity) -> Result<ProcessStatus, ProcessErrors>;
     async fn start(&mut self);
+    /// Same as ```rust start ``` but every logged step also records how long after the start it happened.
     async fn start_timed(&mut self);
 }
 
 
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ProcessStatus {
     Received,
     Sent,
     Waiting,
-    Running
+    Running,
+    /// The last step of a process that doesn't loop has been posted
+    Finished,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum PingTime {
     /// The process doesn't need to ping the participants
     Never,
@@ -0,0 +1,282 @@
+//! In-process implementations of the communication traits. Everything is backed by tokio mpsc channels so that several environments can be wired together inside of a single process (mainly for tests) without ever touching a real socket.
+
+use crate::{
+    ChannelType, CommunicationChannel, CommunicationErrors, CommunicationManager,
+    CommunicationPolicy, Entity, EntityTypes, InOrder, InternalMessage, InternalSystemComponents, Message, PingTime, Process,
+};
+
+use async_trait::async_trait;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex as SyncMutex};
+
+/// How many messages can be waiting on one end of a loopback channel before `send` has to wait.
+pub const LOOPBACK_CHANNEL_CAPACITY: usize = 64;
+
+/// One end of an in-process channel. Whatever is sent on one end comes out of the `receive` of the other end.
+#[derive(Clone)]
+pub struct LoopbackChannel {
+    identity: Uuid,
+    outgoing: mpsc::Sender<Box<dyn Message>>,
+    incoming: Arc<Mutex<mpsc::Receiver<Box<dyn Message>>>>,
+}
+
+impl LoopbackChannel {
+    /// Creates both ends of a channel. Both ends share the same identity since they describe the same channel.
+    pub fn pair() -> (LoopbackChannel, LoopbackChannel) {
+        let identity = Uuid::new_v4();
+        let (a_tx, a_rx) = mpsc::channel::<Box<dyn Message>>(LOOPBACK_CHANNEL_CAPACITY);
+        let (b_tx, b_rx) = mpsc::channel::<Box<dyn Message>>(LOOPBACK_CHANNEL_CAPACITY);
+
+        let a = LoopbackChannel {
+            identity,
+            outgoing: b_tx,
+            incoming: Arc::new(Mutex::new(a_rx)),
+        };
+        let b = LoopbackChannel {
+            identity,
+            outgoing: a_tx,
+            incoming: Arc::new(Mutex::new(b_rx)),
+        };
+        (a, b)
+    }
+}
+
+#[async_trait]
+impl CommunicationChannel for LoopbackChannel {
+    /// There is nothing to negotiate for an in-process channel, so the setup process is never run and the participant is accepted as is.
+    async fn initialize(
+        &self,
+        _setup_channel: impl Process + Send,
+        participant: Entity,
+        _keep_alive: PingTime,
+        _channel_type: ChannelType,
+    ) -> Result<(Entity, Self), CommunicationErrors> {
+        Ok((participant, self.clone()))
+    }
+
+    async fn send(
+        &self,
+        _sender: Entity,
+        _receiver: Entity,
+        message: Box<dyn Message>,
+    ) -> Result<(), CommunicationErrors> {
+        self.outgoing
+            .send(message)
+            .await
+            .map_err(|_| CommunicationErrors::ChannelClosed)
+    }
+
+    async fn receive(&self) -> Option<Box<dyn Message>> {
+        self.incoming.lock().await.recv().await
+    }
+
+    fn identity(&self) -> Uuid {
+        self.identity
+    }
+}
+
+struct OpenChannel {
+    channel: Arc<dyn CommunicationChannel>,
+    participant: Entity,
+    /// Moves everything that arrives on the channel into the inbox of the manager
+    forwarder: JoinHandle<()>,
+}
+
+/// A communication manager for in-process channels. Every channel that is added gets a task that forwards its messages into a single inbox which is drained with `pop_queue`.
+///
+/// Messages that carry a correlation come out of `pop_queue` in the order their sender sent them, whichever channel they arrived on, and duplicates are dropped. A message that stays missing while too many later ones arrive is given up on, see `InOrder`.
+///
+/// Channels and sends to entities the policy doesn't allow are refused with `DisallowedEntityType` and counted by the policy.
+///
+/// The forwarding tasks are spawned onto the current tokio runtime, so channels must be added from within one.
+pub struct LoopbackManager {
+    uuid: Uuid,
+    identity: Entity,
+    policy: CommunicationPolicy,
+    channels: SyncMutex<HashMap<Uuid, OpenChannel>>,
+    inbox_sender: mpsc::UnboundedSender<(Entity, Box<dyn Message>)>,
+    inbox: mpsc::UnboundedReceiver<(Entity, Box<dyn Message>)>,
+    queue: VecDeque<(Entity, Box<dyn Message>)>,
+    ordering: InOrder<(Entity, Box<dyn Message>)>,
+    waiting_processes: HashMap<String, Vec<Uuid>>,
+}
+
+impl LoopbackManager {
+    pub fn identity(&self) -> &Entity {
+        &self.identity
+    }
+
+    pub fn allowed_communication_types(&self) -> Vec<(EntityTypes, EntityTypes)> {
+        self.policy.allowed()
+    }
+
+    pub fn policy(&self) -> &CommunicationPolicy {
+        &self.policy
+    }
+
+    /// E.g. to permit two clients to talk to each other once they were paired
+    pub fn policy_mut(&mut self) -> &mut CommunicationPolicy {
+        &mut self.policy
+    }
+
+    /// The processes that registered interest in messages with the given name, in the order they registered.
+    pub fn processes_waiting_for(&self, message_name: &str) -> &[Uuid] {
+        self.waiting_processes
+            .get(message_name)
+            .map(|processes| processes.as_slice())
+            .unwrap_or(&[])
+    }
+
+    fn channel_to(&self, participant: &Entity) -> Option<Arc<dyn CommunicationChannel>> {
+        self.channels
+            .lock()
+            .unwrap()
+            .values()
+            .find(|open| &open.participant == participant)
+            .map(|open| open.channel.clone())
+    }
+
+    fn close_channel(&self, channel: &Uuid) {
+        if let Some(open) = self.channels.lock().unwrap().remove(channel) {
+            open.forwarder.abort();
+        }
+    }
+}
+
+#[async_trait]
+impl CommunicationManager for LoopbackManager {
+    async fn new(identity: Entity, allowed_communication_types: Vec<(EntityTypes, EntityTypes)>) -> Self {
+        let (inbox_sender, inbox) = mpsc::unbounded_channel();
+
+        LoopbackManager {
+            uuid: Uuid::new_v4(),
+            identity,
+            policy: CommunicationPolicy::new(&allowed_communication_types),
+            channels: SyncMutex::new(HashMap::new()),
+            inbox_sender,
+            inbox,
+            queue: VecDeque::new(),
+            ordering: InOrder::new(),
+            waiting_processes: HashMap::new(),
+        }
+    }
+
+    async fn add_channel(
+        &mut self,
+        channel: impl CommunicationChannel + 'static,
+        participant: Entity,
+    ) -> Result<(), CommunicationErrors> {
+        self.policy.check(&self.identity, &participant)?;
+
+        let channel: Arc<dyn CommunicationChannel> = Arc::new(channel);
+        let identity = channel.identity();
+
+        let forwarded = channel.clone();
+        let inbox = self.inbox_sender.clone();
+        let sender = participant;
+        let forwarder = tokio::spawn(async move {
+            while let Some(message) = forwarded.receive().await {
+                if inbox.send((sender, message)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let replaced = self.channels.lock().unwrap().insert(
+            identity,
+            OpenChannel {
+                channel,
+                participant,
+                forwarder,
+            },
+        );
+        if let Some(replaced) = replaced {
+            replaced.forwarder.abort();
+        }
+        Ok(())
+    }
+
+    async fn open_channels(&self) -> Vec<(Uuid, Entity)> {
+        self.channels
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(identity, open)| (*identity, open.participant))
+            .collect()
+    }
+
+    async fn send(&self, receiver: &Entity, message: Box<dyn Message>) -> Result<(), CommunicationErrors> {
+        self.policy.check(&self.identity, receiver)?;
+
+        let channel = self
+            .channel_to(receiver)
+            .ok_or(CommunicationErrors::UnknownParticipant)?;
+
+        channel
+            .send(self.identity, *receiver, message)
+            .await
+    }
+
+    async fn register_process_message(&mut self, waiting_process: Uuid, wait_for_message: String) {
+        self.waiting_processes
+            .entry(wait_for_message)
+            .or_default()
+            .push(waiting_process);
+    }
+
+    async fn pop_queue(&mut self) -> Option<(Entity, Box<dyn Message>)> {
+        while self.queue.is_empty() {
+            let (sender, message) = self.inbox.recv().await?;
+            match message.correlation() {
+                Some(correlation) => {
+                    let released = self.ordering.accept(&correlation, (sender, message));
+                    self.queue.extend(released);
+                }
+                None => self.queue.push_back((sender, message)),
+            }
+        }
+        self.queue.pop_front()
+    }
+
+    async fn add_to_queue(&mut self, sender: Entity, message: Box<dyn Message>) {
+        self.queue.push_back((sender, message));
+    }
+}
+
+#[async_trait]
+impl InternalSystemComponents for LoopbackManager {
+    async fn send_receive_messages(&mut self, message: InternalMessage) {
+        match message {
+            InternalMessage::CloseChannel(channel) => self.close_channel(&channel),
+            // Loopback channels are opened by handing them to add_channel, and the rest of the messages are meant for the process manager.
+            InternalMessage::OpenChannel(_)
+            | InternalMessage::StartProcess(_)
+            | InternalMessage::PolluteProcess(_)
+            | InternalMessage::AdvanceProcess(_)
+            | InternalMessage::SendProcess(_) => {}
+        }
+    }
+
+    fn get_uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    fn handles(&self, message: &InternalMessage) -> bool {
+        match message {
+            InternalMessage::CloseChannel(channel) => self.channels.lock().unwrap().contains_key(channel),
+            _ => false,
+        }
+    }
+}
+
+impl Drop for LoopbackManager {
+    fn drop(&mut self) {
+        for (_, open) in self.channels.lock().unwrap().drain() {
+            open.forwarder.abort();
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Who gets to talk to whom. At every round the server hands the clients that are waiting for a partner to a ```rust Matchmaker ``` and tells both sides of each pair about it with ```rust Command::Matched ```. The call itself only starts once the initiator's sdp offer has been relayed, a pair whose offer never comes is simply matched again in a later round.
+
+use pairing::{FriendSpace, Rating};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use uuid::Uuid;
+
+/// A strategy for pairing clients.
+pub trait Matchmaker: Send {
+    /// Pairs up clients from ```rust waiting ```, which is ordered by uuid. The first client of a pair sends the sdp offer. Clients that are left out keep waiting, pairs with a client that isn't waiting or that is already paired are ignored.
+    fn pair(&mut self, round: u64, waiting: &[Uuid]) -> Vec<(Uuid, Uuid)>;
+
+    /// The client rated the partner of the call it just ended, from -10 to 10. Ignored by default.
+    fn rated(&mut self, _client: Uuid, _partner: Uuid, _rating: i32) {}
+
+    /// The client went offline, anything kept about it can be forgotten. Ignored by default.
+    fn left(&mut self, _client: Uuid) {}
+}
+
+/// Pairs the waiting clients at random. With an odd number of clients a random one sits the round out.
+pub struct RandomPairs {
+    rng: StdRng,
+}
+
+impl RandomPairs {
+    pub fn new() -> RandomPairs {
+        RandomPairs {
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Same as ```rust new ``` but the pairs are drawn from a seeded RNG.
+    pub fn seeded(seed: u64) -> RandomPairs {
+        RandomPairs {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Default for RandomPairs {
+    fn default() -> Self {
+        RandomPairs::new()
+    }
+}
+
+impl Matchmaker for RandomPairs {
+    fn pair(&mut self, _round: u64, waiting: &[Uuid]) -> Vec<(Uuid, Uuid)> {
+        let mut shuffled = waiting.to_vec();
+        shuffled.shuffle(&mut self.rng);
+        shuffled
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect()
+    }
+}
+
+/// The pairing of the simulation: the nearest person in friend-space that wasn't a recent partner. Newcomers join at a random spot, and everyone who is matched is remembered as a recent partner of the other. The ratings the two give each other after their call fill in that conversation, which moves them around like in the simulation. Clients that go offline leave friend-space.
+impl Matchmaker for FriendSpace {
+    fn pair(&mut self, _round: u64, waiting: &[Uuid]) -> Vec<(Uuid, Uuid)> {
+        for person in waiting {
+            self.join(*person, 0);
+        }
+
+        let pairs = self.pair_round(waiting);
+        for (initiator, receiver) in &pairs {
+            self.record(Rating::unrated(*initiator, *receiver));
+        }
+        pairs
+    }
+
+    fn rated(&mut self, client: Uuid, partner: Uuid, rating: i32) {
+        self.rate(client, partner, rating);
+    }
+
+    fn left(&mut self, client: Uuid) {
+        self.leave(&client);
+    }
+}
@@ -0,0 +1,142 @@
+//! A ```rust CommunicationChannel ``` over a tokio-tungstenite websocket, for the server and for native clients. Messages travel as ```rust RawMessage ```s encoded with the codec the channel was opened with.
+
+use crate::codec::encode_message;
+use crate::{
+    ChannelType, Codec, CommunicationChannel, CommunicationErrors, Entity, Message, PingTime,
+    Process, RawMessage,
+};
+
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{mpsc, Mutex};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message as Frame;
+use tokio_tungstenite::WebSocketStream;
+use uuid::Uuid;
+
+use std::sync::Arc;
+
+/// Both halves of the websocket are driven by their own task, the channel only talks to them through mpsc channels.
+#[derive(Clone)]
+pub struct NativeWebSocketChannel {
+    identity: Uuid,
+    codec: Codec,
+    outgoing: mpsc::UnboundedSender<Frame>,
+    incoming: Arc<Mutex<mpsc::UnboundedReceiver<Box<dyn Message>>>>,
+}
+
+impl NativeWebSocketChannel {
+    /// Connects to a websocket server, offering the codec as the subprotocol.
+    pub async fn connect(url: &str, codec: Codec) -> Result<NativeWebSocketChannel, CommunicationErrors> {
+        let mut request = url
+            .into_client_request()
+            .map_err(|_| CommunicationErrors::ChannelInitializationFailed)?;
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static(codec.subprotocol()),
+        );
+
+        let (stream, _response) = tokio_tungstenite::connect_async(request)
+            .await
+            .map_err(|_| CommunicationErrors::ChannelInitializationFailed)?;
+
+        Ok(NativeWebSocketChannel::from_stream(stream, codec))
+    }
+
+    /// Wraps a websocket that has already been set up, e.g. one the server accepted. The codec has to be the one negotiated during the upgrade.
+    ///
+    /// The tasks driving the websocket are spawned onto the current tokio runtime.
+    pub fn from_stream<S>(stream: WebSocketStream<S>, codec: Codec) -> NativeWebSocketChannel
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (mut sink, mut frames) = stream.split();
+        let (outgoing, mut to_send) = mpsc::unbounded_channel::<Frame>();
+        let (received, incoming) = mpsc::unbounded_channel::<Box<dyn Message>>();
+
+        tokio::spawn(async move {
+            while let Some(frame) = to_send.recv().await {
+                let closing = frame.is_close();
+                if sink.send(frame).await.is_err() || closing {
+                    break;
+                }
+            }
+        });
+
+        tokio::spawn(async move {
+            while let Some(Ok(frame)) = frames.next().await {
+                let decoded = match frame {
+                    Frame::Binary(bytes) => codec.decode::<RawMessage>(&bytes),
+                    Frame::Text(text) => Codec::Json.decode::<RawMessage>(text.as_bytes()),
+                    Frame::Close(_) => break,
+                    Frame::Ping(_) | Frame::Pong(_) => continue,
+                };
+
+                // Frames that aren't messages are skipped, the channel stays usable
+                if let Ok(message) = decoded {
+                    if received.send(Box::new(message)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        NativeWebSocketChannel {
+            identity: Uuid::new_v4(),
+            codec,
+            outgoing,
+            incoming: Arc::new(Mutex::new(incoming)),
+        }
+    }
+
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// Sends a close frame. Messages that are already on their way are still delivered on the other side.
+    pub fn close(&self) {
+        let _ = self.outgoing.send(Frame::Close(None));
+    }
+}
+
+#[async_trait]
+impl CommunicationChannel for NativeWebSocketChannel {
+    /// The websocket is already open once the channel exists, so the participant is accepted as is.
+    async fn initialize(
+        &self,
+        _setup_channel: impl Process + Send,
+        participant: Entity,
+        _keep_alive: PingTime,
+        _channel_type: ChannelType,
+    ) -> Result<(Entity, Self), CommunicationErrors> {
+        Ok((participant, self.clone()))
+    }
+
+    async fn send(
+        &self,
+        _sender: Entity,
+        _receiver: Entity,
+        message: Box<dyn Message>,
+    ) -> Result<(), CommunicationErrors> {
+        let encoded = encode_message(self.codec, message.as_ref())?;
+        let frame = if self.codec.is_binary() {
+            Frame::Binary(encoded)
+        } else {
+            Frame::Text(String::from_utf8(encoded).expect("json is always valid utf-8"))
+        };
+
+        self.outgoing
+            .send(frame)
+            .map_err(|_| CommunicationErrors::ChannelClosed)
+    }
+
+    async fn receive(&self) -> Option<Box<dyn Message>> {
+        self.incoming.lock().await.recv().await
+    }
+
+    fn identity(&self) -> Uuid {
+        self.identity
+    }
+}
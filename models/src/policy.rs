@@ -0,0 +1,89 @@
+//! Which entities may talk to each other. A communication manager checks every channel it adds and every message it sends against its ```rust CommunicationPolicy ```, and counts what it refused.
+//!
+//! The pairs of entity types are unordered: allowing (Client, Server) also allows (Server, Client). On top of the types, single pairs of entities can be permitted, e.g. two clients that the server paired up while client to client channels are forbidden otherwise.
+
+use crate::{CommunicationErrors, Entity, EntityTypes};
+
+use uuid::Uuid;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+#[derive(Debug, Default)]
+pub struct CommunicationPolicy {
+    allowed: BTreeSet<(EntityTypes, EntityTypes)>,
+    permitted: BTreeSet<(Uuid, Uuid)>,
+    violations: Mutex<BTreeMap<(EntityTypes, EntityTypes), u64>>,
+}
+
+fn ordered<T: Ord>(a: T, b: T) -> (T, T) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+impl CommunicationPolicy {
+    pub fn new(allowed: &[(EntityTypes, EntityTypes)]) -> CommunicationPolicy {
+        CommunicationPolicy {
+            allowed: allowed.iter().map(|(a, b)| ordered(*a, *b)).collect(),
+            ..CommunicationPolicy::default()
+        }
+    }
+
+    pub fn allow(&mut self, a: EntityTypes, b: EntityTypes) {
+        self.allowed.insert(ordered(a, b));
+    }
+
+    pub fn forbid(&mut self, a: EntityTypes, b: EntityTypes) {
+        self.allowed.remove(&ordered(a, b));
+    }
+
+    /// Lets the two entities talk even when their types aren't allowed to
+    pub fn permit(&mut self, a: Uuid, b: Uuid) {
+        self.permitted.insert(ordered(a, b));
+    }
+
+    pub fn revoke(&mut self, a: Uuid, b: Uuid) -> bool {
+        self.permitted.remove(&ordered(a, b))
+    }
+
+    /// The allowed pairs of types, each with the smaller type first
+    pub fn allowed(&self) -> Vec<(EntityTypes, EntityTypes)> {
+        self.allowed.iter().copied().collect()
+    }
+
+    pub fn allows(&self, a: &Entity, b: &Entity) -> bool {
+        self.allowed.contains(&ordered(a.entity_type, b.entity_type))
+            || self.permitted.contains(&ordered(a.uuid(), b.uuid()))
+    }
+
+    /// Like ```rust allows ```, but a refusal is counted as a violation
+    pub fn check(&self, a: &Entity, b: &Entity) -> Result<(), CommunicationErrors> {
+        if self.allows(a, b) {
+            return Ok(());
+        }
+
+        *self
+            .violations
+            .lock()
+            .unwrap()
+            .entry(ordered(a.entity_type, b.entity_type))
+            .or_insert(0) += 1;
+        Err(CommunicationErrors::DisallowedEntityType)
+    }
+
+    pub fn violations(&self) -> u64 {
+        self.violations.lock().unwrap().values().sum()
+    }
+
+    pub fn violations_between(&self, a: EntityTypes, b: EntityTypes) -> u64 {
+        self.violations
+            .lock()
+            .unwrap()
+            .get(&ordered(a, b))
+            .copied()
+            .unwrap_or(0)
+    }
+}
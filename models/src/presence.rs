@@ -0,0 +1,88 @@
+//! The lifecycle of a client's ```rust Status ```. Both the server and the frontends move a status only through ```rust transition ```, so they agree on what a client may do next. ```rust None ``` is a client that isn't connected.
+//!
+//! ```text
+//! None --Connected--> WaitingForPartner --CallStarted--> InCall --CallEnded--> AnsweringQuestionAboutLastPartner
+//!                       ^   |                                                     |            |
+//!                       |   +--WentIdle--> Idle <--------------WentIdle-----------+            |
+//!                       |                   |                                                  |
+//!                       +-----CameBack------+------------------AnsweredQuestions---------------+
+//! ```
+//!
+//! Every status goes back to None on Disconnected.
+
+use crate::Status;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::fmt;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PresenceEvent {
+    Connected,
+    /// The first uuid is the initiator of the call, the second uuid is the receiver
+    CallStarted(Uuid, Uuid),
+    CallEnded,
+    /// The client is done with the questions about its last partner
+    AnsweredQuestions,
+    /// The client doesn't want to be paired for now
+    WentIdle,
+    CameBack,
+    Disconnected,
+}
+
+impl PresenceEvent {
+    /// Events that only the client knows about and reports with ```rust Command::UpdatePresence ```. The server derives the others from the calls and connections it sees.
+    pub fn is_reported_by_client(&self) -> bool {
+        matches!(
+            self,
+            PresenceEvent::AnsweredQuestions | PresenceEvent::WentIdle | PresenceEvent::CameBack
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct IllegalTransition {
+    pub from: Option<Status>,
+    pub event: PresenceEvent,
+}
+
+impl fmt::Display for IllegalTransition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.from {
+            Some(status) => write!(f, "{:?} can't happen while {:?}", self.event, status),
+            None => write!(f, "{:?} can't happen while disconnected", self.event),
+        }
+    }
+}
+
+impl std::error::Error for IllegalTransition {}
+
+/// The status a client ends up in after the event, or an error when the event isn't allowed in the current status.
+pub fn transition(current: &Option<Status>, event: &PresenceEvent) -> Result<Option<Status>, IllegalTransition> {
+    let next = match (current, event) {
+        (Some(_), PresenceEvent::Disconnected) => None,
+        (None, PresenceEvent::Connected) => Some(Status::WaitingForPartner),
+        (Some(Status::WaitingForPartner), PresenceEvent::CallStarted(initiator, receiver)) if initiator != receiver => {
+            Some(Status::InCall(*initiator, *receiver))
+        }
+        (Some(Status::InCall(_, _)), PresenceEvent::CallEnded) => Some(Status::AnsweringQuestionAboutLastPartner),
+        (Some(Status::AnsweringQuestionAboutLastPartner), PresenceEvent::AnsweredQuestions) => Some(Status::WaitingForPartner),
+        (Some(Status::WaitingForPartner), PresenceEvent::WentIdle)
+        | (Some(Status::AnsweringQuestionAboutLastPartner), PresenceEvent::WentIdle) => Some(Status::Idle),
+        (Some(Status::Idle), PresenceEvent::CameBack) => Some(Status::WaitingForPartner),
+        _ => {
+            return Err(IllegalTransition {
+                from: current.clone(),
+                event: *event,
+            })
+        }
+    };
+
+    Ok(next)
+}
+
+/// Whether the event is allowed in the current status, e.g. for enabling buttons
+pub fn allowed(current: &Option<Status>, event: &PresenceEvent) -> bool {
+    transition(current, event).is_ok()
+}
@@ -0,0 +1,309 @@
+//! A runtime for processes that are completely described by the arguments of ```rust Process::new ```: who is involved and which entity type posts which message, in what order.
+//!
+//! The process keeps a 'focus token' on the step it expects next. Every message that is sent or received is matched against the steps by name:
+//! * a blocking process only accepts the step in focus, anything else is an error.
+//! * a process that isn't blocking looks ahead from the focus and skips to the first step that matches. Messages that don't match any of the remaining steps are ignored.
+//!
+//! Once the last step has been posted a looping process starts over at the first step, any other process is finished.
+
+use crate::{
+    Entities, Entity, EntityTypes, InternalMessage, InternalSystemComponents, Message, Process,
+    ProcessStatus,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::sync::Arc;
+
+/// One entry in the journal of a process.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProcessStep {
+    pub process: Uuid,
+    /// The position of the step in the ordered messages of the process
+    pub index: usize,
+    /// How many times a looping process has gone through all of its steps before this one
+    pub iteration: u64,
+    pub message_name: String,
+    pub status: ProcessStatus,
+    pub posted_by: Entity,
+    pub at: DateTime<Utc>,
+    /// Milliseconds since the process was started, only recorded for processes started with ```rust start_timed ```
+    pub elapsed_ms: Option<i64>,
+}
+
+/// Where a process writes its steps as they are logged, e.g. a table in the database. The process keeps its own ```rust journal ``` either way.
+#[async_trait]
+pub trait JournalSink: Send + Sync {
+    /// Failing to record a step is up to the sink to handle, it never stops the process.
+    async fn record(&self, step: &ProcessStep);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessErrors {
+    NotStarted,
+    Finished,
+    /// A blocking process only accepts the message that is in focus
+    UnexpectedMessage { expected: String, received: String },
+    /// The message in focus has to be posted by a different type of entity
+    WrongParty {
+        expected: EntityTypes,
+        posted_by: EntityTypes,
+    },
+}
+
+pub struct DeclarativeProcess {
+    uuid: Uuid,
+    involved_parties: Vec<Entities>,
+    ordered_messages: Vec<(EntityTypes, Box<dyn Message>)>,
+    name: String,
+    explanation: String,
+    blocking: bool,
+    looping: bool,
+    status: ProcessStatus,
+    focus: Option<usize>,
+    iteration: u64,
+    started_at: Option<DateTime<Utc>>,
+    timed: bool,
+    journal: Vec<ProcessStep>,
+    sink: Option<Arc<dyn JournalSink>>,
+    outbox: Vec<Box<dyn Message>>,
+}
+
+impl DeclarativeProcess {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn explanation(&self) -> &str {
+        &self.explanation
+    }
+
+    pub fn is_blocking(&self) -> bool {
+        self.blocking
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    pub fn status(&self) -> ProcessStatus {
+        self.status
+    }
+
+    /// The index of the step the process expects next. None before the process is started and after it is finished.
+    pub fn focus(&self) -> Option<usize> {
+        self.focus
+    }
+
+    /// The step in focus: which type of entity has to post it and what the message looks like.
+    pub fn focus_step(&self) -> Option<(&EntityTypes, &dyn Message)> {
+        self.focus.map(|index| {
+            let (entity_type, message) = &self.ordered_messages[index];
+            (entity_type, message.as_ref())
+        })
+    }
+
+    pub fn iteration(&self) -> u64 {
+        self.iteration
+    }
+
+    pub fn journal(&self) -> &[ProcessStep] {
+        &self.journal
+    }
+
+    /// Every step logged from now on is also recorded by the sink.
+    pub fn journal_to(&mut self, sink: Arc<dyn JournalSink>) {
+        self.sink = Some(sink);
+    }
+
+    /// Hands out the messages that were sent through the process since the last call, so that they can be passed on to the communication manager.
+    pub fn take_outgoing(&mut self) -> Vec<Box<dyn Message>> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    fn step_name(&self, index: usize) -> String {
+        self.ordered_messages[index].1.name()
+    }
+
+    /// Finds the step that the message is posting, or None when the process should keep waiting.
+    fn matching_step(&self, focus: usize, message_name: &str, posted_by: &EntityTypes) -> Result<Option<usize>, ProcessErrors> {
+        if self.blocking {
+            let (expected_party, expected) = &self.ordered_messages[focus];
+            if expected.name() != message_name {
+                return Err(ProcessErrors::UnexpectedMessage {
+                    expected: expected.name(),
+                    received: message_name.to_string(),
+                });
+            }
+            if expected_party != posted_by {
+                return Err(ProcessErrors::WrongParty {
+                    expected: *expected_party,
+                    posted_by: *posted_by,
+                });
+            }
+            return Ok(Some(focus));
+        }
+
+        Ok((focus..self.ordered_messages.len()).find(|index| {
+            let (party, _) = &self.ordered_messages[*index];
+            party == posted_by && self.step_name(*index) == message_name
+        }))
+    }
+
+    fn move_focus_past(&mut self, index: usize) {
+        let next = index + 1;
+        if next < self.ordered_messages.len() {
+            self.focus = Some(next);
+        } else if self.looping {
+            self.focus = Some(0);
+            self.iteration += 1;
+        } else {
+            self.focus = None;
+            self.status = ProcessStatus::Finished;
+        }
+    }
+
+    async fn post(&mut self, message: &dyn Message, posted_by: Entity, status: ProcessStatus) -> Result<ProcessStatus, ProcessErrors> {
+        let focus = match self.focus {
+            Some(focus) => focus,
+            None if self.status == ProcessStatus::Finished => return Err(ProcessErrors::Finished),
+            None => return Err(ProcessErrors::NotStarted),
+        };
+
+        match self.matching_step(focus, &message.name(), &posted_by.entity_type)? {
+            Some(index) => {
+                self.focus = Some(index);
+                self.log_step(message, status, posted_by).await;
+                self.move_focus_past(index);
+
+                match self.status {
+                    ProcessStatus::Finished => Ok(ProcessStatus::Finished),
+                    _ => Ok(status),
+                }
+            }
+            None => Ok(ProcessStatus::Waiting),
+        }
+    }
+
+    fn begin(&mut self, timed: bool) {
+        self.timed = timed;
+        self.started_at = Some(Utc::now());
+        self.iteration = 0;
+
+        if self.ordered_messages.is_empty() {
+            self.focus = None;
+            self.status = ProcessStatus::Finished;
+        } else {
+            self.focus = Some(0);
+            self.status = ProcessStatus::Running;
+        }
+    }
+}
+
+#[async_trait]
+impl Process for DeclarativeProcess {
+    fn new(
+        involved_parties: Vec<Entities>,
+        ordered_messages: Vec<(EntityTypes, Box<dyn Message>)>,
+        name: String,
+        explanation: String,
+        blocking: bool,
+        looping: bool,
+    ) -> Self {
+        DeclarativeProcess {
+            uuid: Uuid::new_v4(),
+            involved_parties,
+            ordered_messages,
+            name,
+            explanation,
+            blocking,
+            looping,
+            status: ProcessStatus::Waiting,
+            focus: None,
+            iteration: 0,
+            started_at: None,
+            timed: false,
+            journal: Vec::new(),
+            sink: None,
+            outbox: Vec::new(),
+        }
+    }
+
+    async fn log_step(&mut self, step: &dyn Message, status: ProcessStatus, posted_by: Entity) {
+        let at = Utc::now();
+        let elapsed_ms = match (self.timed, self.started_at) {
+            (true, Some(started_at)) => Some((at - started_at).num_milliseconds()),
+            _ => None,
+        };
+
+        let step = ProcessStep {
+            process: self.uuid,
+            index: self.focus.unwrap_or(0),
+            iteration: self.iteration,
+            message_name: step.name(),
+            status,
+            posted_by,
+            at,
+            elapsed_ms,
+        };
+
+        if let Some(sink) = &self.sink {
+            sink.record(&step).await;
+        }
+        self.journal.push(step);
+    }
+
+    fn involved_parties(&self) -> &[Entities] {
+        &self.involved_parties
+    }
+
+    fn waiting_for_message_type(&self) -> Option<String> {
+        self.focus.map(|index| self.step_name(index))
+    }
+
+    async fn receive_message(&mut self, message: Box<dyn Message>, posted_by: Entity) -> Result<ProcessStatus, ProcessErrors> {
+        self.post(message.as_ref(), posted_by, ProcessStatus::Received)
+            .await
+    }
+
+    async fn send_message(&mut self, message: Box<dyn Message>, posted_by: Entity) -> Result<ProcessStatus, ProcessErrors> {
+        let status = self
+            .post(message.as_ref(), posted_by, ProcessStatus::Sent)
+            .await?;
+        if status != ProcessStatus::Waiting {
+            self.outbox.push(message);
+        }
+        Ok(status)
+    }
+
+    async fn start(&mut self) {
+        self.begin(false);
+    }
+
+    async fn start_timed(&mut self) {
+        self.begin(true);
+    }
+}
+
+#[async_trait]
+impl InternalSystemComponents for DeclarativeProcess {
+    async fn send_receive_messages(&mut self, message: InternalMessage) {
+        match message {
+            InternalMessage::StartProcess(process) if process == self.uuid => self.start().await,
+            // Skips the step in focus without anyone posting it
+            InternalMessage::AdvanceProcess(process) if process == self.uuid => {
+                if let Some(focus) = self.focus {
+                    self.move_focus_past(focus);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn get_uuid(&self) -> Uuid {
+        self.uuid
+    }
+}
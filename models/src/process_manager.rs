@@ -0,0 +1,185 @@
+//! A process manager that keeps its processes in memory and only starts a process when the current topology can provide the parties the process involves.
+
+use crate::{
+    Entities, Entity, EntityTypes, InternalMessage, InternalSystemComponents, NetworkTopology,
+    Process, ProcessManager,
+};
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use std::collections::HashMap;
+
+/// One of the involved parties of a process that the topology can't satisfy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartyMismatch {
+    /// There are fewer entities of the type than required
+    Missing {
+        required: Entities,
+        available: u32,
+        missing: u32,
+    },
+    /// There are more entities of the type than allowed. Lists the entities past the limit, the ones with the highest uuids, in the order of their uuids.
+    Surplus {
+        required: Entities,
+        surplus: Vec<Entity>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessManagerErrors {
+    /// No process has been registered under this uuid
+    UnknownProcess(Uuid),
+    /// Every requirement of the process that isn't met, in the order the parties are listed in the process
+    PartiesUnavailable {
+        process: Uuid,
+        mismatches: Vec<PartyMismatch>,
+    },
+}
+
+/// Checks every one of the involved parties on its own against all of the entities of that type in the topology. Entity types that none of the parties mention are not looked at.
+pub fn check_parties(involved_parties: &[Entities], topology: &NetworkTopology) -> Result<(), Vec<PartyMismatch>> {
+    let mut by_type: HashMap<EntityTypes, Vec<Entity>> = HashMap::new();
+    for entity in topology.nodes() {
+        by_type.entry(entity.entity_type).or_default().push(entity);
+    }
+    // The topology loses the order the entities were added in as soon as one is removed
+    for entities in by_type.values_mut() {
+        entities.sort_by_key(Entity::uuid);
+    }
+
+    let mismatches: Vec<PartyMismatch> = involved_parties
+        .iter()
+        .filter_map(|required| {
+            let available = by_type
+                .get(&required.entity_type())
+                .map(|entities| entities.as_slice())
+                .unwrap_or(&[]);
+            let count = available.len() as u32;
+            let (min, max) = required.bounds();
+
+            if count < min {
+                return Some(PartyMismatch::Missing {
+                    required: required.clone(),
+                    available: count,
+                    missing: min - count,
+                });
+            }
+            match max {
+                Some(max) if count > max => Some(PartyMismatch::Surplus {
+                    required: required.clone(),
+                    surplus: available[max as usize..].to_vec(),
+                }),
+                _ => None,
+            }
+        })
+        .collect();
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(mismatches)
+    }
+}
+
+pub struct LocalProcessManager {
+    uuid: Uuid,
+    identity: Entity,
+    topology: NetworkTopology,
+    processes: HashMap<Uuid, Box<dyn Process + Send + Sync>>,
+    refused: Vec<ProcessManagerErrors>,
+}
+
+impl LocalProcessManager {
+    pub fn identity(&self) -> &Entity {
+        &self.identity
+    }
+
+    pub fn topology(&self) -> &NetworkTopology {
+        &self.topology
+    }
+
+    pub fn process(&self, process: &Uuid) -> Option<&(dyn Process + Send + Sync)> {
+        self.processes.get(process).map(|process| process.as_ref())
+    }
+
+    /// The processes that a ```rust InternalMessage::StartProcess ``` could not start, oldest first.
+    pub fn refused(&self) -> &[ProcessManagerErrors] {
+        &self.refused
+    }
+}
+
+#[async_trait]
+impl ProcessManager for LocalProcessManager {
+    async fn initialize(identity: Entity) -> Self {
+        // The environment itself is part of every topology it is in
+        let mut topology = NetworkTopology::new();
+        topology.add_node(identity);
+
+        LocalProcessManager {
+            uuid: Uuid::new_v4(),
+            identity,
+            topology,
+            processes: HashMap::new(),
+            refused: Vec::new(),
+        }
+    }
+
+    fn register_functionality(&mut self, process: impl Process + Send + Sync + 'static) -> Uuid {
+        let uuid = process.get_uuid();
+        self.processes.insert(uuid, Box::new(process));
+        uuid
+    }
+
+    fn update_topology(&mut self, topology: NetworkTopology) {
+        self.topology = topology;
+    }
+
+    async fn start_process(&mut self, process: Uuid) -> Result<(), ProcessManagerErrors> {
+        let registered = self
+            .processes
+            .get_mut(&process)
+            .ok_or(ProcessManagerErrors::UnknownProcess(process))?;
+
+        check_parties(registered.involved_parties(), &self.topology)
+            .map_err(|mismatches| ProcessManagerErrors::PartiesUnavailable { process, mismatches })?;
+
+        registered.start().await;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl InternalSystemComponents for LocalProcessManager {
+    async fn send_receive_messages(&mut self, message: InternalMessage) {
+        match message {
+            InternalMessage::StartProcess(process) => {
+                if let Err(refusal) = self.start_process(process).await {
+                    self.refused.push(refusal);
+                }
+            }
+            InternalMessage::AdvanceProcess(process) | InternalMessage::PolluteProcess(process) => {
+                if let Some(registered) = self.processes.get_mut(&process) {
+                    registered.send_receive_messages(message).await;
+                }
+            }
+            // Sending processes and handling channels is up to the communication manager
+            InternalMessage::SendProcess(_)
+            | InternalMessage::CloseChannel(_)
+            | InternalMessage::OpenChannel(_) => {}
+        }
+    }
+
+    fn get_uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    fn handles(&self, message: &InternalMessage) -> bool {
+        match message {
+            InternalMessage::StartProcess(process)
+            | InternalMessage::AdvanceProcess(process)
+            | InternalMessage::PolluteProcess(process) => self.processes.contains_key(process),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! The version handshake that follows ```rust Command::ServerInitiated ```. The client answers with a ```rust Command::Handshake ``` describing the versions and capabilities it speaks, and the server either settles on a version both sides understand or refuses the connection with a ```rust ProtocolError::VersionMismatch ```.
+
+use crate::IllegalTransition;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// The version of the protocol spoken by this build of the models crate
+pub const PROTOCOL_VERSION: u32 = 3;
+/// The oldest version this build can still talk to. Version 1 is the protocol from before the handshake existed.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+/// The first version whose ```rust Command::Error ``` carries a ```rust ProtocolError ```. Clients that negotiated an older version, or never sent a handshake, get the error's message as a plain string, see ```rust Envelope::encode_for ```.
+pub const CODED_ERRORS_VERSION: u32 = 3;
+
+/// Optional parts of the protocol. Both sides only use the capabilities they have in common.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Capability {
+    /// Answers ```rust Command::Ping ``` with ```rust Command::Pong ```
+    KeepAlive,
+    /// Sdp offers and answers relayed through the server
+    SdpRelay,
+    /// Ice candidates relayed through the server
+    IceRelay,
+    /// Receives ```rust Command::OnlineClients ``` every round
+    OnlineClients,
+    /// Takes part in the server's matchmaking and receives ```rust Command::Matched ```
+    Matchmaking,
+}
+
+impl Capability {
+    pub fn all() -> BTreeSet<Capability> {
+        [
+            Capability::KeepAlive,
+            Capability::SdpRelay,
+            Capability::IceRelay,
+            Capability::OnlineClients,
+            Capability::Matchmaking,
+        ]
+        .iter()
+        .copied()
+        .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Handshake {
+    /// The newest version the sender speaks
+    pub version: u32,
+    /// The oldest version the sender is willing to fall back to
+    pub min_version: u32,
+    pub capabilities: BTreeSet<Capability>,
+}
+
+/// What both sides settled on.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Negotiated {
+    pub version: u32,
+    pub capabilities: BTreeSet<Capability>,
+    /// True when the version is older than the newest one the server speaks
+    pub downgraded: bool,
+}
+
+/// Why the server won't talk to a client. The versions are (oldest, newest) ranges.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ProtocolMismatch {
+    pub client_versions: (u32, u32),
+    pub server_versions: (u32, u32),
+}
+
+impl fmt::Display for ProtocolMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (client_min, client_max) = self.client_versions;
+        let (server_min, server_max) = self.server_versions;
+
+        if client_max < server_min {
+            write!(
+                f,
+                "This page speaks protocol version {} but the server needs at least version {}. Please reload the page.",
+                client_max, server_min
+            )
+        } else {
+            write!(
+                f,
+                "This page needs protocol version {} or newer but the server only speaks up to version {}.",
+                client_min, server_max
+            )
+        }
+    }
+}
+
+/// What the server sends in ```rust Command::Error ```. The codes are part of the protocol: a code is never reused for another error, and new errors are only ever appended so that bincode keeps decoding the old ones.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// A message was addressed to a client that isn't online
+    UnknownRecipient(Uuid),
+    /// The client tried to end a call with someone it isn't in a call with
+    NotInCall(Uuid),
+    /// The client sent more than ```rust limit ``` messages in a single round, the rest of the round's messages are dropped
+    RateLimited { limit: u32 },
+    /// The handshake failed, the server closes the connection right after sending this
+    VersionMismatch(ProtocolMismatch),
+    /// A frame couldn't be decoded, with the decoder's complaint
+    MalformedPayload(String),
+    /// The client's status doesn't allow what it tried to do
+    IllegalTransition(IllegalTransition),
+    /// The client sent a command on behalf of another client, the one named here
+    Impersonation(Uuid),
+    /// The client tried to pass a command to the client named here that only the server sends
+    NotRelayable(Uuid),
+}
+
+impl ProtocolError {
+    pub fn code(&self) -> u16 {
+        match self {
+            ProtocolError::UnknownRecipient(_) => 1,
+            ProtocolError::NotInCall(_) => 2,
+            ProtocolError::RateLimited { .. } => 3,
+            ProtocolError::VersionMismatch(_) => 4,
+            ProtocolError::MalformedPayload(_) => 5,
+            ProtocolError::IllegalTransition(_) => 6,
+            ProtocolError::Impersonation(_) => 7,
+            ProtocolError::NotRelayable(_) => 8,
+        }
+    }
+
+    /// Errors after which the server doesn't talk to the client anymore
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, ProtocolError::VersionMismatch(_))
+    }
+
+    /// What to tell the person in front of the page
+    pub fn message(&self) -> String {
+        match self {
+            ProtocolError::UnknownRecipient(_) => {
+                "The person you tried to reach isn't online anymore.".to_string()
+            }
+            ProtocolError::NotInCall(_) => "You aren't in a call with that person.".to_string(),
+            ProtocolError::RateLimited { limit } => format!(
+                "Slow down! Only {} messages per round are allowed, try again next round.",
+                limit
+            ),
+            ProtocolError::VersionMismatch(mismatch) => mismatch.to_string(),
+            ProtocolError::MalformedPayload(_) => {
+                "The server couldn't understand the last message this page sent.".to_string()
+            }
+            ProtocolError::IllegalTransition(illegal) => format!("That isn't possible right now: {}.", illegal),
+            ProtocolError::Impersonation(_) => "You can only do that for yourself.".to_string(),
+            ProtocolError::NotRelayable(_) => "That can't be sent to another person.".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error {}: {}", self.code(), self.message())
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl Handshake {
+    /// The handshake of this build
+    pub fn current() -> Handshake {
+        Handshake {
+            version: PROTOCOL_VERSION,
+            min_version: MIN_PROTOCOL_VERSION,
+            capabilities: Capability::all(),
+        }
+    }
+
+    /// Run by the server with its own handshake. Settles on the newest version both sides speak.
+    pub fn negotiate(&self, client: &Handshake) -> Result<Negotiated, ProtocolMismatch> {
+        let version = self.version.min(client.version);
+
+        if version < self.min_version || version < client.min_version {
+            return Err(ProtocolMismatch {
+                client_versions: (client.min_version, client.version),
+                server_versions: (self.min_version, self.version),
+            });
+        }
+
+        Ok(Negotiated {
+            version,
+            capabilities: self
+                .capabilities
+                .intersection(&client.capabilities)
+                .copied()
+                .collect(),
+            downgraded: version < self.version,
+        })
+    }
+}
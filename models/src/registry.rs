@@ -0,0 +1,182 @@
+//! Maps the ```rust name() ``` of a message to the concrete serde type behind it, so that the bytes of a ```rust RawMessage ``` can be turned back into a typed message on the receiving side.
+
+use crate::{Correlation, Message};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::collections::HashMap;
+
+/// A message whose payload is the whole (serializable) struct. The name has to be unique amongst the messages registered in a ```rust MessageRegistry ```.
+pub trait TypedMessage: Message + Serialize + DeserializeOwned {
+    const NAME: &'static str;
+
+    /// Encodes the message with bincode. This is what ```rust Message::data ``` should return for typed messages.
+    fn encode(&self) -> Option<Vec<u8>> {
+        bincode::serialize(self).ok()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageErrors {
+    /// No type has been registered under this name
+    UnknownMessage(String),
+    /// The message with this name does not carry any data
+    MissingPayload(String),
+    /// The bytes could not be decoded into the registered type
+    MalformedPayload { name: String, reason: String },
+}
+
+/// The form a message takes while it travels between environments: the name says how to decode the data.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RawMessage {
+    pub identity: Uuid,
+    pub name: String,
+    pub description: String,
+    pub data: Vec<u8>,
+    #[serde(default)]
+    pub correlation: Option<Correlation>,
+}
+
+impl RawMessage {
+    pub fn from_message(message: &dyn Message) -> Result<RawMessage, MessageErrors> {
+        let data = message
+            .data()
+            .ok_or_else(|| MessageErrors::MissingPayload(message.name()))?;
+
+        Ok(RawMessage {
+            identity: message.identity(),
+            name: message.name(),
+            description: message.description(),
+            data,
+            correlation: message.correlation(),
+        })
+    }
+}
+
+impl Message for RawMessage {
+    fn identity(&self) -> Uuid {
+        self.identity
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    fn data(&self) -> Option<Vec<u8>> {
+        Some(self.data.clone())
+    }
+
+    fn correlation(&self) -> Option<Correlation> {
+        self.correlation
+    }
+}
+
+type Decoder = fn(&str, &[u8]) -> Result<Box<dyn Message>, MessageErrors>;
+
+/// Tells whether a message is of the type that was registered under its name
+type TypeCheck = fn(&dyn Message) -> bool;
+
+fn decode_into<T: TypedMessage>(name: &str, data: &[u8]) -> Result<Box<dyn Message>, MessageErrors> {
+    bincode::deserialize::<T>(data)
+        .map(|message| Box::new(message) as Box<dyn Message>)
+        .map_err(|err| MessageErrors::MalformedPayload {
+            name: name.to_string(),
+            reason: err.to_string(),
+        })
+}
+
+fn is_a<T: TypedMessage>(message: &dyn Message) -> bool {
+    message.is::<T>()
+}
+
+#[derive(Clone, Copy)]
+struct Registered {
+    decode: Decoder,
+    is_a: TypeCheck,
+}
+
+#[derive(Default, Clone)]
+pub struct MessageRegistry {
+    decoders: HashMap<String, Registered>,
+}
+
+impl MessageRegistry {
+    pub fn new() -> MessageRegistry {
+        MessageRegistry::default()
+    }
+
+    /// Registers the type under ```rust T::NAME ```. Registering the same name twice replaces the earlier type.
+    pub fn register<T: TypedMessage>(&mut self) -> &mut Self {
+        self.decoders.insert(
+            T::NAME.to_string(),
+            Registered {
+                decode: decode_into::<T>,
+                is_a: is_a::<T>,
+            },
+        );
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.decoders.contains_key(name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.decoders.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Decodes the data of a message with the type registered under the given name.
+    pub fn decode(&self, name: &str, data: &[u8]) -> Result<Box<dyn Message>, MessageErrors> {
+        let registered = self
+            .decoders
+            .get(name)
+            .ok_or_else(|| MessageErrors::UnknownMessage(name.to_string()))?;
+
+        (registered.decode)(name, data)
+    }
+
+    pub fn decode_raw(&self, raw: &RawMessage) -> Result<Box<dyn Message>, MessageErrors> {
+        self.decode(&raw.name, &raw.data)
+    }
+
+    /// Re-decodes any message (usually a ```rust RawMessage ``` that just came off the wire) into its registered type. Messages that already are of the type registered under their name come out as they went in, any other message is unknown.
+    pub fn resolve(&self, message: Box<dyn Message>) -> Result<Box<dyn Message>, MessageErrors> {
+        if !message.is::<RawMessage>() {
+            let name = message.name();
+            return match self.decoders.get(&name) {
+                Some(registered) if (registered.is_a)(message.as_ref()) => Ok(message),
+                _ => Err(MessageErrors::UnknownMessage(name)),
+            };
+        }
+        let data = message
+            .data()
+            .ok_or_else(|| MessageErrors::MissingPayload(message.name()))?;
+        self.decode(&message.name(), &data)
+    }
+
+    /// Decodes straight into the concrete type, checking that the name matches what the type was registered with.
+    pub fn decode_as<T: TypedMessage>(&self, raw: &RawMessage) -> Result<T, MessageErrors> {
+        if raw.name != T::NAME {
+            return Err(MessageErrors::MalformedPayload {
+                name: raw.name.clone(),
+                reason: format!("expected a {} message", T::NAME),
+            });
+        }
+
+        self.decode_raw(raw)?
+            .downcast::<T>()
+            .map(|message| *message)
+            .map_err(|message| MessageErrors::MalformedPayload {
+                name: message.name(),
+                reason: format!("{} is registered as a different type", T::NAME),
+            })
+    }
+}
@@ -0,0 +1,143 @@
+//! A state manager whose values are CRDTs that every peer changes locally. Each change produces a ```rust StateUpdate ``` that is sent to the other peers, who merge it into their own copy. There is no peer that owns the state, once all updates have been delivered every peer holds the same values.
+
+use crate::crdt::Crdt;
+use crate::registry::TypedMessage;
+use crate::state::{StateErrors, StateManager};
+use crate::Message;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::collections::BTreeMap;
+
+/// The complete value of one replicated key, as known by the peer that sent it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct StateUpdate {
+    pub identity: Uuid,
+    /// The replica that sent the update
+    pub origin: Uuid,
+    pub key: String,
+    pub data: Vec<u8>,
+}
+
+impl Message for StateUpdate {
+    fn identity(&self) -> Uuid {
+        self.identity
+    }
+
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        format!("The replicated value of {}", self.key)
+    }
+
+    fn data(&self) -> Option<Vec<u8>> {
+        self.encode()
+    }
+}
+
+impl TypedMessage for StateUpdate {
+    const NAME: &'static str = "StateUpdate";
+}
+
+/// Merges the encoded remote value into the local one, telling whether the local value changed.
+type Merger = fn(&mut StateManager, &str, &[u8]) -> Result<bool, StateErrors>;
+
+fn merge_into<C: Crdt>(state: &mut StateManager, key: &str, remote: &[u8]) -> Result<bool, StateErrors> {
+    let remote: C = bincode::deserialize(remote).map_err(|err| StateErrors::Decoding {
+        key: key.to_string(),
+        reason: err.to_string(),
+    })?;
+    let before = state.encoded(key).map(|encoded| encoded.to_vec());
+
+    let mut local: C = state.get(key)?.unwrap_or_default();
+    local.merge(&remote);
+    state.insert(key, &local)?;
+
+    Ok(state.encoded(key) != before.as_deref())
+}
+
+pub struct ReplicatedStateManager {
+    replica: Uuid,
+    state: StateManager,
+    mergers: BTreeMap<String, Merger>,
+}
+
+impl ReplicatedStateManager {
+    pub fn new(replica: Uuid) -> ReplicatedStateManager {
+        ReplicatedStateManager {
+            replica,
+            state: StateManager::new(),
+            mergers: BTreeMap::new(),
+        }
+    }
+
+    /// The uuid this peer uses for its own entries in the CRDTs
+    pub fn replica(&self) -> Uuid {
+        self.replica
+    }
+
+    /// Read access to the plain state, replicated values are stored under their key like any other value.
+    pub fn state(&self) -> &StateManager {
+        &self.state
+    }
+
+    /// Declares the type of the CRDT stored under the key and starts it out empty. Updates for keys that were never declared are refused.
+    pub fn replicate<C: Crdt>(&mut self, key: &str) -> Result<(), StateErrors> {
+        if !self.state.contains(key) {
+            self.state.insert(key, &C::default())?;
+        }
+        self.mergers.insert(key.to_string(), merge_into::<C>);
+        Ok(())
+    }
+
+    pub fn get<C: Crdt>(&self, key: &str) -> Result<C, StateErrors> {
+        if !self.mergers.contains_key(key) {
+            return Err(StateErrors::NotReplicated(key.to_string()));
+        }
+        Ok(self.state.get(key)?.unwrap_or_default())
+    }
+
+    /// Changes the local copy and returns the update that has to be sent to the other peers.
+    pub fn update<C: Crdt>(&mut self, key: &str, change: impl FnOnce(&mut C)) -> Result<StateUpdate, StateErrors> {
+        let mut value: C = self.get(key)?;
+        change(&mut value);
+        self.state.insert(key, &value)?;
+        self.state_update(key)
+    }
+
+    /// Merges an update from another peer. Returns true when it taught this peer something new, which is when it is worth passing the update on.
+    pub fn merge(&mut self, update: &StateUpdate) -> Result<bool, StateErrors> {
+        let merger = self
+            .mergers
+            .get(&update.key)
+            .ok_or_else(|| StateErrors::NotReplicated(update.key.clone()))?;
+
+        merger(&mut self.state, &update.key, &update.data)
+    }
+
+    /// One update per replicated key, for bringing a newly connected peer up to date.
+    pub fn full_sync(&self) -> Result<Vec<StateUpdate>, StateErrors> {
+        self.mergers
+            .keys()
+            .map(|key| self.state_update(key))
+            .collect()
+    }
+
+    fn state_update(&self, key: &str) -> Result<StateUpdate, StateErrors> {
+        let data = self
+            .state
+            .encoded(key)
+            .ok_or_else(|| StateErrors::NotReplicated(key.to_string()))?
+            .to_vec();
+
+        Ok(StateUpdate {
+            identity: Uuid::new_v4(),
+            origin: self.replica,
+            key: key.to_string(),
+            data,
+        })
+    }
+}
@@ -0,0 +1,614 @@
+//! The websocket server's bookkeeping without any of its I/O: which clients are online, their presence, the protocol each of them negotiated and how many messages they sent this round.
+//!
+//! The server hands every message it receives to ```rust ServerState::handle ``` and every new round to ```rust ServerState::tick ```, then carries out the returned ```rust Effect ```s in order. Follow-ups that the server used to send to itself, like the ```rust Command::BroadcastUpdate ``` after a call started, are handled on the spot and their effects appended, so a single message always yields everything it causes.
+
+use crate::{
+    transition, Capability, Client, Command, Handshake, IllegalTransition, KeepAlive,
+    KeepAliveAction, Matchmaker, Negotiated, PingStatus, PingTime, PresenceEvent, ProtocolError,
+    Status, Topology,
+};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use uuid::{Uuid, Variant, Version};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// How many rounds a matched pair has to get into their call before both of them can be matched again
+pub const MATCH_TIMEOUT_ROUNDS: u64 = 3;
+
+/// A message that reached the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inbound {
+    /// None when the server sends the message to itself
+    pub sender: Option<Uuid>,
+    /// None when the message is for the server, otherwise the server relays it to this client
+    pub receiver: Option<Uuid>,
+    pub command: Command,
+}
+
+impl Inbound {
+    pub fn from_client(sender: Uuid, command: Command) -> Inbound {
+        Inbound {
+            sender: Some(sender),
+            receiver: None,
+            command,
+        }
+    }
+
+    pub fn from_server(command: Command) -> Inbound {
+        Inbound {
+            sender: None,
+            receiver: None,
+            command,
+        }
+    }
+
+    /// A message between two clients that goes through the server
+    pub fn relay(sender: Uuid, receiver: Uuid, command: Command) -> Inbound {
+        Inbound {
+            sender: Some(sender),
+            receiver: Some(receiver),
+            command,
+        }
+    }
+}
+
+/// What the server has to do after handling a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Effect {
+    /// Send the command from the server to the client
+    Send { to: Uuid, command: Command },
+    /// Send the same command to each of the clients
+    Broadcast { to: Vec<Uuid>, command: Command },
+    /// Pass the message on unchanged over the connection of ```rust next_hop ```. A relay that can't be delivered should be answered with ```rust Command::ClosedConnection ``` for the next hop.
+    Relay { next_hop: Uuid, message: Inbound },
+    /// Close the connection of the client, after the effects before this one went out. The client is already gone from the state.
+    Close(Uuid),
+}
+
+/// A client the server considers online.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OnlineClient {
+    pub client: Client,
+    pub status: Option<Status>,
+    pub ping_status: PingStatus,
+}
+
+pub struct ServerState {
+    uuid: Uuid,
+    handshake: Handshake,
+    ping_time: PingTime,
+    max_messages_per_round: u32,
+    round: u64,
+    /// Ordered by uuid so that broadcasts always go out in the same order
+    online: BTreeMap<Uuid, OnlineClient>,
+    /// Clients that haven't sent a handshake yet are treated as speaking the oldest protocol version
+    negotiated: HashMap<Uuid, Negotiated>,
+    messages_this_round: HashMap<Uuid, u32>,
+    keep_alive: KeepAlive,
+    /// Every client has a single channel to the server, so relaying between clients is the one-hop case of routing over this topology
+    topology: Topology,
+    matchmaker: Option<Box<dyn Matchmaker>>,
+    /// Whom each client that is answering questions just ended its call with, until it rated them
+    last_partners: HashMap<Uuid, Uuid>,
+    /// The partner and the round of every match whose offer hasn't started the call yet
+    pending_matches: HashMap<Uuid, (Uuid, u64)>,
+}
+
+impl ServerState {
+    /// The clients are pinged at ```rust ping_time ```, counted in rounds
+    pub fn new(ping_time: PingTime, max_missed_replies: u32, max_messages_per_round: u32) -> ServerState {
+        ServerState::with_rng(ping_time, max_missed_replies, max_messages_per_round, StdRng::from_entropy())
+    }
+
+    /// Same as ```rust new ``` but the server's uuid and the random ping intervals are drawn from a seeded RNG.
+    pub fn seeded(ping_time: PingTime, max_missed_replies: u32, max_messages_per_round: u32, seed: u64) -> ServerState {
+        ServerState::with_rng(ping_time, max_missed_replies, max_messages_per_round, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(ping_time: PingTime, max_missed_replies: u32, max_messages_per_round: u32, mut rng: StdRng) -> ServerState {
+        ServerState {
+            uuid: uuid::Builder::from_bytes(rng.gen())
+                .set_variant(Variant::RFC4122)
+                .set_version(Version::Random)
+                .build(),
+            handshake: Handshake::current(),
+            ping_time,
+            max_messages_per_round,
+            round: 0,
+            online: BTreeMap::new(),
+            negotiated: HashMap::new(),
+            messages_this_round: HashMap::new(),
+            keep_alive: KeepAlive::seeded(max_missed_replies, rng.gen()),
+            topology: Topology::new(),
+            matchmaker: None,
+            last_partners: HashMap::new(),
+            pending_matches: HashMap::new(),
+        }
+    }
+
+    /// The server's own node in the topology
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    pub fn round(&self) -> u64 {
+        self.round
+    }
+
+    pub fn online(&self) -> &BTreeMap<Uuid, OnlineClient> {
+        &self.online
+    }
+
+    pub fn client(&self, uuid: &Uuid) -> Option<&OnlineClient> {
+        self.online.get(uuid)
+    }
+
+    pub fn negotiated(&self, uuid: &Uuid) -> Option<&Negotiated> {
+        self.negotiated.get(uuid)
+    }
+
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    /// From now on the clients that are waiting for a partner are paired up at every tick. Without a matchmaker the clients pick their partners themselves.
+    pub fn matchmake_with(&mut self, matchmaker: Box<dyn Matchmaker>) {
+        self.matchmaker = Some(matchmaker);
+    }
+
+    /// Starts a new round: the message counts start over, the keep-alive pings the clients that are due, dropping the ones that stopped answering, and the matchmaker pairs up the clients that are waiting.
+    pub fn tick(&mut self, round: u64) -> Vec<Effect> {
+        self.round = round;
+        self.messages_this_round.clear();
+
+        let mut effects = vec![];
+        let mut removed = false;
+        for action in self.keep_alive.tick(round) {
+            match action {
+                KeepAliveAction::Ping { channel, at } => {
+                    if let Some(online) = self.online.get_mut(&channel) {
+                        online.ping_status = PingStatus::Pinged(at);
+                        effects.push(Effect::Send {
+                            to: channel,
+                            command: Command::Ping(channel, at),
+                        });
+                    }
+                }
+                KeepAliveAction::Close(channel) => {
+                    if self.remove(&channel) {
+                        effects.push(Effect::Close(channel));
+                        removed = true;
+                    }
+                }
+            }
+        }
+
+        if removed {
+            effects.extend(self.broadcast_update());
+        }
+        effects.extend(self.matchmake(round));
+        effects
+    }
+
+    pub fn handle(&mut self, message: Inbound) -> Vec<Effect> {
+        let mut effects = vec![];
+
+        if let Some(sender) = message.sender {
+            // Closing the connection must always get through
+            if !matches!(message.command, Command::ClosedConnection(_)) {
+                let sent = self.messages_this_round.entry(sender).or_insert(0);
+                *sent += 1;
+                let sent = *sent;
+
+                if sent > self.max_messages_per_round {
+                    // Only the first dropped message of the round is answered
+                    if sent == self.max_messages_per_round + 1 && self.online.contains_key(&sender) {
+                        effects.push(error(
+                            sender,
+                            ProtocolError::RateLimited {
+                                limit: self.max_messages_per_round,
+                            },
+                        ));
+                    }
+                    return effects;
+                }
+            }
+        }
+
+        match message.receiver {
+            None => effects.extend(self.command(message.sender, message.command)),
+            Some(receiver) => effects.extend(self.relay(receiver, message)),
+        }
+        effects
+    }
+
+    fn command(&mut self, sender: Option<Uuid>, command: Command) -> Vec<Effect> {
+        // Clients only ever speak for themselves, the server may speak for anyone
+        if let Some(sender) = sender {
+            let parties = on_behalf_of(&command);
+            if !parties.is_empty() && !parties.contains(&sender) {
+                if !self.online.contains_key(&sender) {
+                    return vec![];
+                }
+                return vec![error(sender, ProtocolError::Impersonation(parties[0]))];
+            }
+        }
+
+        match command {
+            Command::BroadcastUpdate => self.broadcast_update(),
+            Command::InCall(initiator, receiver) => self.start_call(initiator, receiver),
+            Command::EndCall(person_a, person_b) => self.end_call(sender, person_a, person_b),
+            Command::UpdateClient(client) => match self.online.get_mut(&client.user_id) {
+                Some(online) => {
+                    online.client.update(client);
+                    self.broadcast_update()
+                }
+                None => vec![],
+            },
+            Command::Pong(client, round) => {
+                if self.keep_alive.pong(&client, round) {
+                    if let Some(online) = self.online.get_mut(&client) {
+                        online.ping_status = PingStatus::Ponged(round);
+                    }
+                }
+                vec![]
+            }
+            Command::Handshake(handshake) => match sender {
+                Some(client) if self.online.contains_key(&client) => self.handshake(client, &handshake),
+                _ => vec![],
+            },
+            Command::UpdatePresence(event) => match sender {
+                Some(client) if event.is_reported_by_client() => {
+                    let mut effects = vec![];
+                    if self.apply_presence(client, event, &mut effects) {
+                        effects.extend(self.broadcast_update());
+                    }
+                    effects
+                }
+                _ => vec![],
+            },
+            Command::RateLastPartner(rating) => {
+                if let Some(client) = sender {
+                    self.rate_last_partner(client, rating);
+                }
+                vec![]
+            }
+            Command::ServerInitiated(client) => self.join(client),
+            Command::ClosedConnection(client) => {
+                if self.remove(&client) {
+                    let mut effects = vec![Effect::Close(client)];
+                    effects.extend(self.broadcast_update());
+                    effects
+                } else {
+                    vec![]
+                }
+            }
+            // Only the server sends these, or they only make sense relayed to another client
+            Command::Ping(_, _)
+            | Command::HandshakeAccepted(_)
+            | Command::IceCandidate(_)
+            | Command::Error(_)
+            | Command::SdpRequest(_)
+            | Command::SdpResponse(_)
+            | Command::OnlineClients(_, _)
+            | Command::Matched(_, _) => vec![],
+        }
+    }
+
+    fn relay(&mut self, receiver: Uuid, message: Inbound) -> Vec<Effect> {
+        // Everything else is the server's to say
+        if !is_relayed(&message.command) {
+            return match message.sender.filter(|sender| self.online.contains_key(sender)) {
+                Some(sender) => vec![error(sender, ProtocolError::NotRelayable(receiver))],
+                None => vec![],
+            };
+        }
+
+        // Clients that aren't in the topology anymore end up in the not-online branch below
+        let next_hop = match self.topology.next_hop(&self.uuid, &receiver) {
+            Ok(Some(next_hop)) => next_hop,
+            _ => receiver,
+        };
+
+        if !self.online.contains_key(&next_hop) {
+            let mut effects = vec![];
+            if let Some(sender) = message.sender.filter(|sender| self.online.contains_key(sender)) {
+                effects.push(error(sender, ProtocolError::UnknownRecipient(receiver)));
+            }
+            effects.extend(self.command(None, Command::ClosedConnection(receiver)));
+            return effects;
+        }
+
+        let follow_up = match (&message.command, message.sender) {
+            (Command::EndCall(person_a, person_b), sender) => Some((sender, Command::EndCall(*person_a, *person_b))),
+            // An offer being passed on is what starts a call
+            (Command::SdpRequest(_), Some(sender)) => Some((Some(sender), Command::InCall(sender, receiver))),
+            _ => None,
+        };
+
+        let (sender, follow_up) = match follow_up {
+            Some(follow_up) => follow_up,
+            None => return vec![Effect::Relay { next_hop, message }],
+        };
+
+        // The message only goes out when it moved the receiver into or out of the call, a refused one stays with the server
+        let status = |state: &ServerState| state.online.get(&receiver).map(|online| online.status.clone());
+        let before = status(self);
+        let follow_up = self.command(sender, follow_up);
+        if status(self) == before {
+            return follow_up;
+        }
+
+        let mut effects = vec![Effect::Relay { next_hop, message }];
+        effects.extend(follow_up);
+        effects
+    }
+
+    fn join(&mut self, client: Client) -> Vec<Effect> {
+        let uuid = client.user_id;
+        if self.online.contains_key(&uuid) {
+            return vec![];
+        }
+
+        self.online.insert(
+            uuid,
+            OnlineClient {
+                client: client.clone(),
+                status: None,
+                ping_status: PingStatus::NeverPinged,
+            },
+        );
+        self.keep_alive.watch(uuid, self.ping_time, self.round);
+        self.topology.add_channel(self.uuid, uuid);
+
+        let mut effects = vec![Effect::Send {
+            to: uuid,
+            command: Command::ServerInitiated(client),
+        }];
+        self.apply_presence(uuid, PresenceEvent::Connected, &mut effects);
+        effects
+    }
+
+    fn handshake(&mut self, client: Uuid, handshake: &Handshake) -> Vec<Effect> {
+        match self.handshake.negotiate(handshake) {
+            Ok(negotiated) => {
+                if !negotiated.capabilities.contains(&Capability::KeepAlive) {
+                    self.keep_alive.forget(&client);
+                }
+                self.negotiated.insert(client, negotiated.clone());
+                vec![Effect::Send {
+                    to: client,
+                    command: Command::HandshakeAccepted(negotiated),
+                }]
+            }
+            Err(mismatch) => {
+                self.remove(&client);
+                let mut effects = vec![
+                    error(client, ProtocolError::VersionMismatch(mismatch)),
+                    Effect::Close(client),
+                ];
+                effects.extend(self.broadcast_update());
+                effects
+            }
+        }
+    }
+
+    fn start_call(&mut self, initiator: Uuid, receiver: Uuid) -> Vec<Effect> {
+        let call_started = PresenceEvent::CallStarted(initiator, receiver);
+
+        // Either both of them go into the call or neither does
+        let refusals: Vec<IllegalTransition> = [initiator, receiver]
+            .iter()
+            .filter_map(|person| {
+                let status = self.online.get(person).and_then(|online| online.status.clone());
+                transition(&status, &call_started).err()
+            })
+            .collect();
+
+        if !refusals.is_empty() {
+            if !self.online.contains_key(&initiator) {
+                return vec![];
+            }
+            return refusals
+                .into_iter()
+                .map(|refusal| error(initiator, ProtocolError::IllegalTransition(refusal)))
+                .collect();
+        }
+
+        let mut effects = vec![];
+        for person in [initiator, receiver].iter() {
+            self.apply_presence(*person, call_started, &mut effects);
+        }
+        effects.extend(self.broadcast_update());
+        effects
+    }
+
+    fn end_call(&mut self, sender: Option<Uuid>, person_a: Uuid, person_b: Uuid) -> Vec<Effect> {
+        let in_this_call = |uuid: &Uuid| match self.online.get(uuid) {
+            Some(online) => matches!(
+                online.status,
+                Some(Status::InCall(a, b)) if (a, b) == (person_a, person_b) || (a, b) == (person_b, person_a)
+            ),
+            None => false,
+        };
+
+        if !in_this_call(&person_a) || !in_this_call(&person_b) {
+            return match sender.filter(|sender| self.online.contains_key(sender)) {
+                Some(sender) => {
+                    let partner = if sender == person_a { person_b } else { person_a };
+                    vec![error(sender, ProtocolError::NotInCall(partner))]
+                }
+                None => vec![],
+            };
+        }
+
+        let mut effects = vec![];
+        for person in [person_a, person_b].iter() {
+            self.apply_presence(*person, PresenceEvent::CallEnded, &mut effects);
+        }
+        self.last_partners.insert(person_a, person_b);
+        self.last_partners.insert(person_b, person_a);
+        effects.extend(self.broadcast_update());
+        effects
+    }
+
+    /// Passes the rating on to the matchmaker, once per call. Ratings from clients that aren't answering questions about a partner are ignored.
+    fn rate_last_partner(&mut self, client: Uuid, rating: i32) {
+        let answering = self
+            .online
+            .get(&client)
+            .map(|online| online.status == Some(Status::AnsweringQuestionAboutLastPartner))
+            .unwrap_or(false);
+        if !answering {
+            return;
+        }
+
+        if let (Some(partner), Some(matchmaker)) = (self.last_partners.remove(&client), self.matchmaker.as_mut()) {
+            matchmaker.rated(client, partner, rating.clamp(-10, 10));
+        }
+    }
+
+    /// Tells both clients of every pair the matchmaker came up with. Only clients that asked for matchmaking in their handshake take part, and a matched pair sits out until their call starts or ```rust MATCH_TIMEOUT_ROUNDS ``` have passed.
+    fn matchmake(&mut self, round: u64) -> Vec<Effect> {
+        if self.matchmaker.is_none() {
+            return vec![];
+        }
+
+        let online = &self.online;
+        let still_waiting =
+            |uuid: &Uuid| matches!(online.get(uuid), Some(online) if online.status == Some(Status::WaitingForPartner));
+        self.pending_matches.retain(|client, (partner, matched_at)| {
+            round < *matched_at + MATCH_TIMEOUT_ROUNDS && still_waiting(client) && still_waiting(partner)
+        });
+
+        let waiting: Vec<Uuid> = self
+            .online
+            .iter()
+            .filter(|(uuid, online)| {
+                online.status == Some(Status::WaitingForPartner)
+                    && !self.pending_matches.contains_key(uuid)
+                    && self
+                        .negotiated
+                        .get(uuid)
+                        .map(|negotiated| negotiated.capabilities.contains(&Capability::Matchmaking))
+                        .unwrap_or(false)
+            })
+            .map(|(uuid, _)| *uuid)
+            .collect();
+
+        let pairs = match self.matchmaker.as_mut() {
+            Some(matchmaker) => matchmaker.pair(round, &waiting),
+            None => vec![],
+        };
+
+        let mut unpaired: BTreeSet<Uuid> = waiting.iter().copied().collect();
+        let mut effects = vec![];
+        for (initiator, receiver) in pairs {
+            if initiator == receiver || !unpaired.contains(&initiator) || !unpaired.contains(&receiver) {
+                continue;
+            }
+            unpaired.remove(&initiator);
+            unpaired.remove(&receiver);
+            self.pending_matches.insert(initiator, (receiver, round));
+            self.pending_matches.insert(receiver, (initiator, round));
+
+            for client in [initiator, receiver].iter() {
+                effects.push(Effect::Send {
+                    to: *client,
+                    command: Command::Matched(initiator, receiver),
+                });
+            }
+        }
+        effects
+    }
+
+    /// The online clients to everyone who asked for them in the handshake
+    fn broadcast_update(&self) -> Vec<Effect> {
+        let to: Vec<Uuid> = self
+            .online
+            .keys()
+            .filter(|uuid| {
+                self.negotiated
+                    .get(uuid)
+                    .map(|negotiated| negotiated.capabilities.contains(&Capability::OnlineClients))
+                    .unwrap_or(true)
+            })
+            .copied()
+            .collect();
+
+        if to.is_empty() {
+            return vec![];
+        }
+
+        let clients = self
+            .online
+            .iter()
+            .map(|(uuid, online)| (*uuid, online.client.clone()))
+            .collect();
+        vec![Effect::Broadcast {
+            to,
+            command: Command::OnlineClients(clients, self.round as u32),
+        }]
+    }
+
+    /// Moves the client's status along with ```rust transition ```. A client whose status doesn't allow the event is told so and keeps its status.
+    fn apply_presence(&mut self, client: Uuid, event: PresenceEvent, effects: &mut Vec<Effect>) -> bool {
+        let online = match self.online.get_mut(&client) {
+            Some(online) => online,
+            None => return false,
+        };
+
+        match transition(&online.status, &event) {
+            Ok(status) => {
+                online.status = status;
+                true
+            }
+            Err(illegal) => {
+                effects.push(error(client, ProtocolError::IllegalTransition(illegal)));
+                false
+            }
+        }
+    }
+
+    /// Returns false when the client wasn't online
+    fn remove(&mut self, client: &Uuid) -> bool {
+        self.keep_alive.forget(client);
+        self.negotiated.remove(client);
+        self.topology.remove_entity(client);
+        self.last_partners.remove(client);
+        self.pending_matches.remove(client);
+        if let Some(matchmaker) = self.matchmaker.as_mut() {
+            matchmaker.left(*client);
+        }
+        self.online.remove(client).is_some()
+    }
+}
+
+/// The clients a command acts for. A client may only send the command when it is one of them, the commands that don't name anyone are always fine.
+fn on_behalf_of(command: &Command) -> Vec<Uuid> {
+    match command {
+        // Only the initiator can put both of them in a call
+        Command::InCall(initiator, _) => vec![*initiator],
+        Command::EndCall(person_a, person_b) => vec![*person_a, *person_b],
+        Command::UpdateClient(client) | Command::ServerInitiated(client) => vec![client.user_id],
+        Command::Pong(client, _) | Command::ClosedConnection(client) => vec![*client],
+        _ => vec![],
+    }
+}
+
+/// The commands clients say to each other through the server
+fn is_relayed(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::SdpRequest(_) | Command::SdpResponse(_) | Command::IceCandidate(_) | Command::EndCall(_, _)
+    )
+}
+
+fn error(to: Uuid, error: ProtocolError) -> Effect {
+    Effect::Send {
+        to,
+        command: Command::Error(error),
+    }
+}
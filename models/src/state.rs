@@ -0,0 +1,174 @@
+//! The state of an environment: named values that are kept encoded, so that the whole state can be written out as a snapshot and two snapshots can be compared key by key.
+//!
+//! Values are compared by the bytes they encode to. bincode writes a ```rust HashMap ``` or ```rust HashSet ``` in iteration order, so two equal values holding one can differ in their bytes and show up as changed; values that are diffed should keep ordered collections such as ```rust BTreeMap ```.
+
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateErrors {
+    /// The value could not be encoded
+    Encoding { key: String, reason: String },
+    /// The stored bytes could not be decoded as the requested type. bincode doesn't record the type of a value, so bytes of another type with the same layout (a ```rust u64 ``` read as an ```rust i64 ```) decode without this error.
+    Decoding { key: String, reason: String },
+    /// The bytes handed to ```rust StateSnapshot::from_bytes ``` are not a snapshot
+    MalformedSnapshot(String),
+    /// No CRDT type has been declared for the key of a replicated state
+    NotReplicated(String),
+}
+
+/// The complete state at one moment, with the entries ordered by key.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct StateSnapshot {
+    pub taken_at: DateTime<Utc>,
+    pub entries: BTreeMap<String, Vec<u8>>,
+}
+
+/// What has to happen to an older snapshot to turn it into a newer one.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub added: BTreeMap<String, Vec<u8>>,
+    pub changed: BTreeMap<String, Vec<u8>>,
+    pub removed: BTreeSet<String>,
+}
+
+impl StateSnapshot {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, StateErrors> {
+        bincode::serialize(self).map_err(|err| StateErrors::MalformedSnapshot(err.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<StateSnapshot, StateErrors> {
+        bincode::deserialize(bytes).map_err(|err| StateErrors::MalformedSnapshot(err.to_string()))
+    }
+
+    /// Compares the entries of this snapshot with a newer one, the time the snapshots were taken is not part of the diff.
+    pub fn diff(&self, newer: &StateSnapshot) -> StateDiff {
+        let mut diff = StateDiff::default();
+
+        for (key, value) in &newer.entries {
+            match self.entries.get(key) {
+                None => {
+                    diff.added.insert(key.clone(), value.clone());
+                }
+                Some(old) if old != value => {
+                    diff.changed.insert(key.clone(), value.clone());
+                }
+                Some(_) => {}
+            }
+        }
+        for key in self.entries.keys() {
+            if !newer.entries.contains_key(key) {
+                diff.removed.insert(key.clone());
+            }
+        }
+
+        diff
+    }
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    /// Every key the diff touches
+    pub fn keys(&self) -> BTreeSet<String> {
+        self.added
+            .keys()
+            .chain(self.changed.keys())
+            .chain(self.removed.iter())
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateManager {
+    state: BTreeMap<String, Vec<u8>>,
+}
+
+impl StateManager {
+    pub fn new() -> StateManager {
+        StateManager::default()
+    }
+
+    /// Stores the value under the key and tells whether there already was a value.
+    pub fn insert<T: Serialize>(&mut self, key: &str, value: &T) -> Result<bool, StateErrors> {
+        let encoded = bincode::serialize(value).map_err(|err| StateErrors::Encoding {
+            key: key.to_string(),
+            reason: err.to_string(),
+        })?;
+        Ok(self.state.insert(key.to_string(), encoded).is_some())
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, StateErrors> {
+        self.state
+            .get(key)
+            .map(|encoded| decode(key, encoded))
+            .transpose()
+    }
+
+    /// Removes the value and hands it back, or leaves it in place when its bytes can't be decoded as the requested type. That doesn't catch every value of another type, see ```rust StateErrors::Decoding ```.
+    pub fn remove<T: DeserializeOwned>(&mut self, key: &str) -> Result<Option<T>, StateErrors> {
+        let value = self.get(key)?;
+        self.state.remove(key);
+        Ok(value)
+    }
+
+    /// The value as it is stored, without decoding it.
+    pub fn encoded(&self, key: &str) -> Option<&[u8]> {
+        self.state.get(key).map(|encoded| encoded.as_slice())
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.state.contains_key(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.state.keys()
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state.is_empty()
+    }
+
+    pub fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            taken_at: Utc::now(),
+            entries: self.state.clone(),
+        }
+    }
+
+    /// Replaces the whole state with the one in the snapshot.
+    pub fn restore(&mut self, snapshot: StateSnapshot) {
+        self.state = snapshot.entries;
+    }
+
+    /// The difference between the snapshot and the current state.
+    pub fn diff_since(&self, snapshot: &StateSnapshot) -> StateDiff {
+        snapshot.diff(&self.snapshot())
+    }
+
+    pub fn apply(&mut self, diff: &StateDiff) {
+        for key in &diff.removed {
+            self.state.remove(key);
+        }
+        for (key, value) in diff.added.iter().chain(diff.changed.iter()) {
+            self.state.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+fn decode<T: DeserializeOwned>(key: &str, encoded: &[u8]) -> Result<T, StateErrors> {
+    bincode::deserialize(encoded).map_err(|err| StateErrors::Decoding {
+        key: key.to_string(),
+        reason: err.to_string(),
+    })
+}
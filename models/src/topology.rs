@@ -0,0 +1,243 @@
+//! A routable view of the network: entities keyed by their uuid, connected by the channels that are open between them. Entities that don't share a channel can still reach each other by having the message ```rust Forwarded ``` by the entities in between.
+
+use crate::registry::TypedMessage;
+use crate::{CommunicationErrors, CommunicationManager, Entity, Message, MessageErrors, NetworkTopology, RawMessage};
+
+use petgraph::graphmap::UnGraphMap;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoutingErrors {
+    /// The uuid isn't part of the topology, or there is no entity known for it
+    UnknownEntity(Uuid),
+    /// No chain of channels leads from one to the other
+    NoRoute { from: Uuid, to: Uuid },
+    /// The forwarded message reached an entity that isn't on its path
+    NotOnPath(Uuid),
+    Message(MessageErrors),
+    Communication(CommunicationErrors),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+    channels: UnGraphMap<Uuid, ()>,
+    entities: BTreeMap<Uuid, Entity>,
+}
+
+impl Topology {
+    pub fn new() -> Topology {
+        Topology::default()
+    }
+
+    pub fn add_entity(&mut self, entity: Entity) {
+        self.channels.add_node(entity.uuid());
+        self.entities.insert(entity.uuid(), entity);
+    }
+
+    /// Removes the entity together with all of its channels
+    pub fn remove_entity(&mut self, uuid: &Uuid) -> Option<Entity> {
+        self.channels.remove_node(*uuid);
+        self.entities.remove(uuid)
+    }
+
+    pub fn entity(&self, uuid: &Uuid) -> Option<&Entity> {
+        self.entities.get(uuid)
+    }
+
+    pub fn contains(&self, uuid: &Uuid) -> bool {
+        self.channels.contains_node(*uuid)
+    }
+
+    /// Adds a channel between the two entities, adding the entities themselves when they are new
+    pub fn connect(&mut self, a: Entity, b: Entity) -> bool {
+        self.add_entity(a);
+        self.add_entity(b);
+        self.add_channel(a.uuid(), b.uuid())
+    }
+
+    /// Returns false when the channel already existed
+    pub fn add_channel(&mut self, a: Uuid, b: Uuid) -> bool {
+        self.channels.add_edge(a, b, ()).is_none()
+    }
+
+    pub fn remove_channel(&mut self, a: &Uuid, b: &Uuid) -> bool {
+        self.channels.remove_edge(*a, *b).is_some()
+    }
+
+    pub fn has_channel(&self, a: &Uuid, b: &Uuid) -> bool {
+        self.channels.contains_edge(*a, *b)
+    }
+
+    pub fn neighbours(&self, uuid: &Uuid) -> Vec<Uuid> {
+        let mut neighbours: Vec<Uuid> = self.channels.neighbors(*uuid).collect();
+        neighbours.sort();
+        neighbours
+    }
+
+    /// The entities a message passes through, both ends included. Among paths of the same length the one through the smallest uuids is picked, so that every entity computes the same path.
+    pub fn shortest_path(&self, from: &Uuid, to: &Uuid) -> Result<Vec<Uuid>, RoutingErrors> {
+        for uuid in [from, to].iter() {
+            if !self.contains(uuid) {
+                return Err(RoutingErrors::UnknownEntity(**uuid));
+            }
+        }
+
+        let mut came_from = HashMap::new();
+        let mut frontier = VecDeque::new();
+        came_from.insert(*from, *from);
+        frontier.push_back(*from);
+
+        while let Some(current) = frontier.pop_front() {
+            if current == *to {
+                let mut path = vec![current];
+                let mut step = current;
+                while step != *from {
+                    step = came_from[&step];
+                    path.push(step);
+                }
+                path.reverse();
+                return Ok(path);
+            }
+
+            for neighbour in self.neighbours(&current) {
+                if let Entry::Vacant(unvisited) = came_from.entry(neighbour) {
+                    unvisited.insert(current);
+                    frontier.push_back(neighbour);
+                }
+            }
+        }
+
+        Err(RoutingErrors::NoRoute { from: *from, to: *to })
+    }
+
+    /// The entity to hand a message to on its way from one to the other. None when they are the same entity.
+    pub fn next_hop(&self, from: &Uuid, to: &Uuid) -> Result<Option<Uuid>, RoutingErrors> {
+        Ok(self.shortest_path(from, to)?.get(1).copied())
+    }
+
+    /// What the entity ```rust at ``` has to do with a message that is being forwarded. The path is computed again from here when the planned next hop can't be reached anymore.
+    pub fn step(&self, at: &Uuid, mut forwarded: Forwarded) -> Result<ForwardStep, RoutingErrors> {
+        if *at == forwarded.destination {
+            return Ok(ForwardStep::Deliver(forwarded.message));
+        }
+
+        let position = forwarded
+            .path
+            .iter()
+            .position(|hop| hop == at)
+            .ok_or(RoutingErrors::NotOnPath(*at))?;
+
+        let planned = forwarded.path.get(position + 1).copied();
+        let next = match planned {
+            Some(next) if self.has_channel(at, &next) => next,
+            _ => {
+                let detour = self.shortest_path(at, &forwarded.destination)?;
+                forwarded.path.truncate(position);
+                forwarded.path.extend(detour);
+                forwarded.path[position + 1]
+            }
+        };
+
+        let to = *self.entity(&next).ok_or(RoutingErrors::UnknownEntity(next))?;
+        Ok(ForwardStep::Relay { to, forwarded })
+    }
+
+    /// Takes the next step of the message with the communication manager of ```rust at ```: sends it on, or hands it back when ```rust at ``` is the destination.
+    pub async fn forward(
+        &self,
+        at: &Uuid,
+        manager: &(impl CommunicationManager + Sync),
+        forwarded: Forwarded,
+    ) -> Result<Option<RawMessage>, RoutingErrors> {
+        match self.step(at, forwarded)? {
+            ForwardStep::Deliver(message) => Ok(Some(message)),
+            ForwardStep::Relay { to, forwarded } => {
+                manager
+                    .send(&to, Box::new(forwarded))
+                    .await
+                    .map_err(RoutingErrors::Communication)?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl From<&NetworkTopology> for Topology {
+    fn from(network: &NetworkTopology) -> Topology {
+        let mut topology = Topology::new();
+        for entity in network.nodes() {
+            topology.add_entity(entity);
+        }
+        for (a, b, _) in network.all_edges() {
+            topology.add_channel(a.uuid(), b.uuid());
+        }
+        topology
+    }
+}
+
+#[derive(Debug)]
+pub enum ForwardStep {
+    /// The message arrived at its destination
+    Deliver(RawMessage),
+    /// Send the message on to the entity
+    Relay { to: Entity, forwarded: Forwarded },
+}
+
+/// A message on its way to an entity that its origin has no channel to.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Forwarded {
+    pub identity: Uuid,
+    pub origin: Uuid,
+    pub destination: Uuid,
+    /// Every entity on the way, both ends included
+    pub path: Vec<Uuid>,
+    pub message: RawMessage,
+}
+
+impl Forwarded {
+    pub fn new(
+        topology: &Topology,
+        origin: Uuid,
+        destination: Uuid,
+        message: &dyn Message,
+    ) -> Result<Forwarded, RoutingErrors> {
+        Ok(Forwarded {
+            identity: Uuid::new_v4(),
+            origin,
+            destination,
+            path: topology.shortest_path(&origin, &destination)?,
+            message: RawMessage::from_message(message).map_err(RoutingErrors::Message)?,
+        })
+    }
+
+    /// How many entities pass the message on
+    pub fn intermediaries(&self) -> usize {
+        self.path.len().saturating_sub(2)
+    }
+}
+
+impl Message for Forwarded {
+    fn identity(&self) -> Uuid {
+        self.identity
+    }
+
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        format!("{} forwarded from {} to {}", self.message.name, self.origin, self.destination)
+    }
+
+    fn data(&self) -> Option<Vec<u8>> {
+        self.encode()
+    }
+}
+
+impl TypedMessage for Forwarded {
+    const NAME: &'static str = "Forwarded";
+}
@@ -0,0 +1,143 @@
+//! A ```rust CommunicationChannel ``` over the browser's WebSocket, for the frontends. Messages travel as ```rust RawMessage ```s encoded with the codec the channel was opened with. Only built for wasm32 with the "wasm" feature.
+
+use crate::codec::encode_message;
+use crate::{
+    ChannelType, Codec, CommunicationChannel, CommunicationErrors, Entity, Message, PingTime,
+    Process, RawMessage,
+};
+
+use async_trait::async_trait;
+use tokio::sync::{mpsc, Mutex};
+use uuid::Uuid;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{CloseEvent, MessageEvent, WebSocket};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// The callbacks have to live as long as the websocket does
+struct Callbacks {
+    _onmessage: Closure<dyn FnMut(MessageEvent)>,
+    _onclose: Closure<dyn FnMut(CloseEvent)>,
+}
+
+/// Clones share the same websocket.
+#[derive(Clone)]
+pub struct WasmWebSocketChannel {
+    identity: Uuid,
+    codec: Codec,
+    websocket: WebSocket,
+    incoming: Arc<Mutex<mpsc::UnboundedReceiver<Box<dyn Message>>>>,
+    _callbacks: Rc<Callbacks>,
+}
+
+// Javascript objects can't leave the thread they were created on. The module only exists on wasm32, and without the atomics target feature there is only ever the one thread to send them to. With threads the channel isn't Send and can't be a CommunicationChannel.
+#[cfg(not(target_feature = "atomics"))]
+unsafe impl Send for WasmWebSocketChannel {}
+#[cfg(not(target_feature = "atomics"))]
+unsafe impl Sync for WasmWebSocketChannel {}
+
+impl WasmWebSocketChannel {
+    /// Opens a websocket, offering the codec as the subprotocol, and waits until the browser reports it as open.
+    pub async fn connect(url: &str, codec: Codec) -> Result<WasmWebSocketChannel, CommunicationErrors> {
+        let websocket = WebSocket::new_with_str(url, codec.subprotocol())
+            .map_err(|_| CommunicationErrors::ChannelInitializationFailed)?;
+        websocket.set_binary_type(web_sys::BinaryType::Arraybuffer);
+
+        let (received, incoming) = mpsc::unbounded_channel::<Box<dyn Message>>();
+        // Closing the websocket drops the sender, which ends `receive`
+        let received = Rc::new(RefCell::new(Some(received)));
+
+        let on_message_received = received.clone();
+        let onmessage = Closure::wrap(Box::new(move |event: MessageEvent| {
+            let decoded = if let Ok(buffer) = event.data().dyn_into::<js_sys::ArrayBuffer>() {
+                codec.decode::<RawMessage>(&js_sys::Uint8Array::new(&buffer).to_vec())
+            } else if let Some(text) = event.data().as_string() {
+                Codec::Json.decode::<RawMessage>(text.as_bytes())
+            } else {
+                return;
+            };
+
+            if let (Ok(message), Some(received)) = (decoded, on_message_received.borrow().as_ref()) {
+                let _ = received.send(Box::new(message));
+            }
+        }) as Box<dyn FnMut(MessageEvent)>);
+        websocket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+
+        let onclose = Closure::wrap(Box::new(move |_event: CloseEvent| {
+            received.borrow_mut().take();
+        }) as Box<dyn FnMut(CloseEvent)>);
+        websocket.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+
+        let opened = js_sys::Promise::new(&mut |resolve, reject| {
+            websocket.set_onopen(Some(&resolve));
+            websocket.set_onerror(Some(&reject));
+        });
+        let opened = JsFuture::from(opened).await;
+        websocket.set_onopen(None);
+        websocket.set_onerror(None);
+        opened.map_err(|_: JsValue| CommunicationErrors::ChannelInitializationFailed)?;
+
+        Ok(WasmWebSocketChannel {
+            identity: Uuid::new_v4(),
+            codec,
+            websocket,
+            incoming: Arc::new(Mutex::new(incoming)),
+            _callbacks: Rc::new(Callbacks {
+                _onmessage: onmessage,
+                _onclose: onclose,
+            }),
+        })
+    }
+
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// Closes the websocket for every clone of the channel
+    pub fn close(&self) {
+        let _ = self.websocket.close();
+    }
+}
+
+#[async_trait]
+impl CommunicationChannel for WasmWebSocketChannel {
+    /// The websocket is already open once the channel exists, so the participant is accepted as is.
+    async fn initialize(
+        &self,
+        _setup_channel: impl Process + Send,
+        participant: Entity,
+        _keep_alive: PingTime,
+        _channel_type: ChannelType,
+    ) -> Result<(Entity, Self), CommunicationErrors> {
+        Ok((participant, self.clone()))
+    }
+
+    async fn send(
+        &self,
+        _sender: Entity,
+        _receiver: Entity,
+        message: Box<dyn Message>,
+    ) -> Result<(), CommunicationErrors> {
+        let encoded = encode_message(self.codec, message.as_ref())?;
+        let sent = if self.codec.is_binary() {
+            self.websocket.send_with_u8_array(&encoded)
+        } else {
+            self.websocket
+                .send_with_str(&String::from_utf8(encoded).expect("json is always valid utf-8"))
+        };
+
+        sent.map_err(|_| CommunicationErrors::ChannelClosed)
+    }
+
+    async fn receive(&self) -> Option<Box<dyn Message>> {
+        self.incoming.lock().await.recv().await
+    }
+
+    fn identity(&self) -> Uuid {
+        self.identity
+    }
+}
@@ -0,0 +1,133 @@
+use models::{
+    Client, Codec, CodecErrors, Command, EntityDetails, EntityTypes, Envelope, Handshake, PresenceEvent,
+    ProtocolError, ProtocolMismatch,
+};
+use uuid::Uuid;
+
+use std::collections::{BTreeSet, HashMap};
+
+/// Exhaustive on purpose: a new variant doesn't compile until it is added to ```every_command``` as well
+fn variant(command: &Command) -> &'static str {
+    match command {
+        Command::BroadcastUpdate => "BroadcastUpdate",
+        Command::InCall(_, _) => "InCall",
+        Command::EndCall(_, _) => "EndCall",
+        Command::UpdateClient(_) => "UpdateClient",
+        Command::Error(_) => "Error",
+        Command::ServerInitiated(_) => "ServerInitiated",
+        Command::OnlineClients(_, _) => "OnlineClients",
+        Command::SdpRequest(_) => "SdpRequest",
+        Command::SdpResponse(_) => "SdpResponse",
+        Command::ClosedConnection(_) => "ClosedConnection",
+        Command::IceCandidate(_) => "IceCandidate",
+        Command::Ping(_, _) => "Ping",
+        Command::Pong(_, _) => "Pong",
+        Command::Handshake(_) => "Handshake",
+        Command::HandshakeAccepted(_) => "HandshakeAccepted",
+        Command::UpdatePresence(_) => "UpdatePresence",
+        Command::Matched(_, _) => "Matched",
+        Command::RateLastPartner(_) => "RateLastPartner",
+    }
+}
+
+fn every_command() -> Vec<Command> {
+    let alice = Client {
+        username: Some("alice".to_string()),
+        email: None,
+        user_id: Uuid::new_v4(),
+    };
+    let bob = Uuid::new_v4();
+
+    let mut online = HashMap::new();
+    online.insert(alice.user_id, alice.clone());
+
+    vec![
+        Command::BroadcastUpdate,
+        Command::InCall(alice.user_id, bob),
+        Command::EndCall(bob, alice.user_id),
+        Command::UpdateClient(alice.clone()),
+        Command::Error(ProtocolError::MalformedPayload("expected \"value\"".to_string())),
+        Command::ServerInitiated(alice.clone()),
+        Command::OnlineClients(online, 7),
+        Command::SdpRequest("v=0\r\no=- 4611731400430051336 2 IN IP4 127.0.0.1".to_string()),
+        Command::SdpResponse(String::new()),
+        Command::ClosedConnection(bob),
+        Command::IceCandidate("candidate:1 1 UDP 2122252543 192.168.1.2 49203 typ host".to_string()),
+        Command::Ping(bob, u64::MAX),
+        Command::Pong(bob, 0),
+        Command::Handshake(Handshake::current()),
+        Command::HandshakeAccepted(Handshake::current().negotiate(&Handshake::current()).unwrap()),
+        Command::Error(ProtocolError::VersionMismatch(ProtocolMismatch {
+            client_versions: (1, 1),
+            server_versions: (2, 3),
+        })),
+        Command::Error(ProtocolError::RateLimited { limit: 50 }),
+        Command::UpdatePresence(PresenceEvent::CallStarted(bob, alice.user_id)),
+        Command::Matched(bob, alice.user_id),
+        Command::RateLastPartner(-10),
+    ]
+}
+
+#[test]
+fn every_command_survives_every_codec() {
+    let commands = every_command();
+    let covered: BTreeSet<&str> = commands.iter().map(variant).collect();
+    assert_eq!(covered.len(), 18);
+
+    for codec in Codec::all().iter() {
+        for command in &commands {
+            let encoded = codec.encode(command).unwrap();
+            let decoded: Command = codec.decode(&encoded).unwrap();
+            assert_eq!(&decoded, command, "{:?} changed the command", codec);
+        }
+    }
+}
+
+#[test]
+fn relayed_envelopes_survive_every_codec() {
+    let server = EntityDetails::Server(Uuid::new_v4(), "127.0.0.1:2096".parse().unwrap());
+    let envelope = Envelope::new(
+        EntityDetails::Client(Uuid::new_v4(), Some("[::1]:50000".parse().unwrap())),
+        EntityDetails::Client(Uuid::new_v4(), None),
+        Some(server),
+        Command::SdpRequest("offer".to_string()),
+    );
+    assert_eq!(envelope.intermediary.map(|server| server.entity_type), Some(EntityTypes::Server));
+
+    for codec in Codec::all().iter() {
+        let encoded = codec.encode(&envelope).unwrap();
+        assert_eq!(codec.decode::<Envelope>(&encoded).unwrap(), envelope, "{:?} changed the envelope", codec);
+    }
+}
+
+#[test]
+fn json_is_readable_text() {
+    let encoded = Codec::Json.encode(&Command::Ping(Uuid::nil(), 3)).unwrap();
+
+    assert!(!Codec::Json.is_binary());
+    assert_eq!(
+        String::from_utf8(encoded).unwrap(),
+        r#"{"Ping":["00000000-0000-0000-0000-000000000000",3]}"#
+    );
+}
+
+#[test]
+fn the_first_known_subprotocol_is_picked() {
+    assert_eq!(Codec::negotiate(None), Some(Codec::Bincode));
+    assert_eq!(Codec::negotiate(Some("chat, msgpack, json")), Some(Codec::MessagePack));
+    assert_eq!(Codec::negotiate(Some("chat")), None);
+
+    for codec in Codec::all().iter() {
+        assert_eq!(Codec::from_subprotocol(codec.subprotocol()), Some(*codec));
+    }
+}
+
+#[test]
+fn garbage_is_reported_with_the_codec() {
+    for codec in Codec::all().iter() {
+        match codec.decode::<Command>(&[0xff, 0xff, 0xff]) {
+            Err(CodecErrors::Decoding { codec: failed, .. }) => assert_eq!(failed, *codec),
+            other => panic!("{:?} decoded garbage into {:?}", codec, other),
+        }
+    }
+}
@@ -0,0 +1,33 @@
+//! Every test file only uses some of these
+#![allow(dead_code)]
+
+use models::{Entity, EntityDetails, Message};
+use uuid::Uuid;
+
+/// A client that nobody has seen before
+pub fn client() -> Entity {
+    Entity::new(EntityDetails::Client(Uuid::new_v4(), None))
+}
+
+/// A server that nobody has seen before
+pub fn server() -> Entity {
+    Entity::new(EntityDetails::Server(Uuid::new_v4(), "127.0.0.1:2096".parse().unwrap()))
+}
+
+/// A message without a payload, processes only look at its name
+pub struct Step(pub &'static str);
+
+impl Message for Step {
+    fn identity(&self) -> Uuid {
+        Uuid::nil()
+    }
+    fn name(&self) -> String {
+        self.0.to_string()
+    }
+    fn description(&self) -> String {
+        format!("the {} step", self.0)
+    }
+    fn data(&self) -> Option<Vec<u8>> {
+        None
+    }
+}
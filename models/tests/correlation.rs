@@ -0,0 +1,120 @@
+mod common;
+
+use common::client;
+use models::loopback::{LoopbackChannel, LoopbackManager};
+use models::{
+    Codec, CommunicationChannel, CommunicationManager, Correlation, EntityTypes, InOrder, Message, PendingReplies, RawMessage, Sequencer,
+};
+use uuid::Uuid;
+
+fn step(name: &str, correlation: Correlation) -> RawMessage {
+    RawMessage {
+        identity: Uuid::new_v4(),
+        name: name.to_string(),
+        description: String::new(),
+        data: vec![],
+        correlation: Some(correlation),
+    }
+}
+
+#[test]
+fn overtaking_messages_wait_and_duplicates_are_dropped() {
+    let mut alice = Sequencer::new(Uuid::new_v4());
+    let bob = Uuid::new_v4();
+    let sent: Vec<Correlation> = (0..4).map(|_| alice.request(bob)).collect();
+
+    let mut ordering = InOrder::new();
+    assert!(ordering.accept(&sent[1], 1).is_empty());
+    assert!(ordering.accept(&sent[2], 2).is_empty());
+    assert_eq!(ordering.held_back(&alice.sender(), &bob), 2);
+    assert!(ordering.accept(&sent[2], 2).is_empty());
+
+    assert_eq!(ordering.accept(&sent[0], 0), vec![0, 1, 2]);
+    assert!(ordering.accept(&sent[1], 1).is_empty());
+    assert_eq!(ordering.accept(&sent[3], 3), vec![3]);
+    assert_eq!(ordering.held_back(&alice.sender(), &bob), 0);
+}
+
+#[test]
+fn every_receiver_sees_its_own_unbroken_sequence() {
+    let mut alice = Sequencer::new(Uuid::new_v4());
+    let (bob, carol) = (Uuid::new_v4(), Uuid::new_v4());
+    let (mut at_bob, mut at_carol) = (InOrder::new(), InOrder::new());
+
+    // Alice takes turns talking to both of them, neither sees the messages for the other
+    for turn in 0..3 {
+        assert_eq!(at_bob.accept(&alice.request(bob), turn), vec![turn]);
+        assert_eq!(at_carol.accept(&alice.request(carol), turn), vec![turn]);
+    }
+    assert_eq!(at_bob.held_back(&alice.sender(), &bob), 0);
+    assert_eq!(at_carol.held_back(&alice.sender(), &carol), 0);
+}
+
+#[test]
+fn messages_that_never_arrive_are_given_up_on() {
+    let mut alice = Sequencer::new(Uuid::new_v4());
+    let bob = Uuid::new_v4();
+    let sent: Vec<Correlation> = (0..5).map(|_| alice.request(bob)).collect();
+    let mut ordering = InOrder::holding_back(2);
+
+    // The first message is lost
+    assert!(ordering.accept(&sent[1], 1).is_empty());
+    assert!(ordering.accept(&sent[2], 2).is_empty());
+    assert_eq!(ordering.accept(&sent[3], 3), vec![1, 2, 3]);
+    assert_eq!(ordering.held_back(&alice.sender(), &bob), 0);
+
+    // Showing up late doesn't put it out of order
+    assert!(ordering.accept(&sent[0], 0).is_empty());
+    assert_eq!(ordering.accept(&sent[4], 4), vec![4]);
+}
+
+#[test]
+fn replies_find_their_request_once() {
+    let (mut alice, mut bob) = (Sequencer::new(Uuid::new_v4()), Sequencer::new(Uuid::new_v4()));
+    let mut pending = PendingReplies::new();
+
+    let offer = step("SdpRequest", alice.request(bob.sender()));
+    pending.expect(&offer.correlation.unwrap(), "offer");
+
+    let answer = step("SdpResponse", bob.reply(offer.identity, &offer.correlation.unwrap()));
+    // The correlation survives the trip over the wire
+    let answer: RawMessage = Codec::MessagePack
+        .decode(&Codec::MessagePack.encode(&answer).unwrap())
+        .unwrap();
+    let correlation = answer.correlation().unwrap();
+
+    assert_eq!(correlation.reply_to, Some(offer.identity));
+    assert_eq!(pending.resolve(&correlation), Some("offer"));
+    assert_eq!(pending.resolve(&correlation), None);
+    assert_eq!(pending.resolve(&alice.request(bob.sender())), None);
+    assert!(pending.is_empty());
+}
+
+#[tokio::test]
+async fn process_steps_arriving_over_different_channels_come_out_in_order() {
+    let (me, them) = (client(), client());
+    let mut manager = LoopbackManager::new(me, vec![(EntityTypes::Client, EntityTypes::Client)]).await;
+
+    let (mine_a, theirs_a) = LoopbackChannel::pair();
+    let (mine_b, theirs_b) = LoopbackChannel::pair();
+    manager.add_channel(mine_a, them).await.unwrap();
+    manager.add_channel(mine_b, them).await.unwrap();
+
+    let mut sender = Sequencer::new(Uuid::new_v4());
+    let steps: Vec<RawMessage> = ["Offer", "Answer", "Hangup"]
+        .iter()
+        .map(|name| step(name, sender.request(me.uuid())))
+        .collect();
+
+    // The last step overtakes the others on the second channel, and the answer shows up twice
+    theirs_b.send(them, me, Box::new(steps[2].clone())).await.unwrap();
+    theirs_b.send(them, me, Box::new(steps[1].clone())).await.unwrap();
+    theirs_a.send(them, me, Box::new(steps[1].clone())).await.unwrap();
+    theirs_a.send(them, me, Box::new(steps[0].clone())).await.unwrap();
+
+    let mut names = vec![];
+    for _ in 0..3 {
+        names.push(manager.pop_queue().await.unwrap().1.name());
+    }
+    assert_eq!(names, vec!["Offer", "Answer", "Hangup"]);
+}
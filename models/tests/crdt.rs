@@ -0,0 +1,233 @@
+use models::loopback::{LoopbackChannel, LoopbackManager};
+use models::{
+    Client, CommunicationManager, Crdt, CrdtMap, Entity, EntityDetails, EntityTypes, GCounter,
+    LwwRegister, Message, OrSet, ReplicatedStateManager, StateErrors, StateUpdate,
+};
+use uuid::Uuid;
+
+const PRESENCE: &str = "presence";
+
+/// Who is online, by user id
+type Presence = CrdtMap<Uuid, LwwRegister<Client>>;
+
+#[test]
+fn counters_and_registers_converge_in_any_order() {
+    let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+
+    let mut left = GCounter::default();
+    let mut right = GCounter::default();
+    left.increment(a, 2);
+    right.increment(b, 3);
+    right.increment(a, 1);
+
+    let mut merged_left = left.clone();
+    merged_left.merge(&right);
+    merged_left.merge(&right);
+    let mut merged_right = right.clone();
+    merged_right.merge(&left);
+    assert_eq!(merged_left, merged_right);
+    assert_eq!(merged_left.value(), 5);
+
+    let mut first = LwwRegister::default();
+    let mut second = LwwRegister::default();
+    first.set("first".to_string(), 10, a);
+    second.set("second".to_string(), 10, b);
+    second.set("stale".to_string(), 9, b);
+
+    let expected = if a > b { "first" } else { "second" };
+    first.merge(&second);
+    second.merge(&first);
+    assert_eq!(first.get().map(String::as_str), Some(expected));
+    assert_eq!(first, second);
+}
+
+#[test]
+fn or_set_keeps_concurrent_adds_over_removes() {
+    let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+    let mut left = OrSet::default();
+    left.add(a, "alice".to_string());
+    let mut right = left.clone();
+
+    // Right removes alice while left adds her again without having seen the remove
+    right.remove(&"alice".to_string());
+    left.add(a, "alice".to_string());
+    right.add(b, "bob".to_string());
+
+    left.merge(&right);
+    right.merge(&left);
+    assert_eq!(left, right);
+    assert_eq!(left.elements().collect::<Vec<_>>(), vec!["alice", "bob"]);
+
+    right.remove(&"alice".to_string());
+    left.merge(&right);
+    assert!(!left.contains(&"alice".to_string()));
+}
+
+#[test]
+fn or_set_removes_leave_nothing_behind() {
+    let replica = Uuid::new_v4();
+    let alice = "alice".to_string();
+    let mut set = OrSet::default();
+    set.add(replica, alice.clone());
+    let stale = set.clone();
+    set.remove(&alice);
+    let size = bincode::serialize(&set).unwrap().len();
+
+    for _ in 0..100 {
+        set.add(replica, alice.clone());
+        set.remove(&alice);
+    }
+    assert_eq!(bincode::serialize(&set).unwrap().len(), size);
+
+    // A late copy of the first add doesn't bring her back
+    set.merge(&stale);
+    assert!(set.is_empty());
+}
+
+struct Peer {
+    entity: Entity,
+    manager: LoopbackManager,
+    state: ReplicatedStateManager,
+}
+
+async fn peer() -> Peer {
+    let entity = Entity::new(EntityDetails::Client(Uuid::new_v4(), None));
+    let manager = LoopbackManager::new(entity, vec![(EntityTypes::Client, EntityTypes::Client)]).await;
+    let mut state = ReplicatedStateManager::new(Uuid::new_v4());
+    state.replicate::<Presence>(PRESENCE).unwrap();
+
+    Peer {
+        entity,
+        manager,
+        state,
+    }
+}
+
+async fn connect(a: &mut Peer, b: &mut Peer) {
+    let (a_end, b_end) = LoopbackChannel::pair();
+    a.manager.add_channel(a_end, b.entity).await.unwrap();
+    b.manager.add_channel(b_end, a.entity).await.unwrap();
+}
+
+async fn broadcast(manager: &LoopbackManager, update: &StateUpdate, except: Option<&Entity>) {
+    for (_, participant) in manager.open_channels().await {
+        if Some(&participant) != except {
+            manager
+                .send(&participant, Box::new(update.clone()))
+                .await
+                .unwrap();
+        }
+    }
+}
+
+/// Merges the next update and passes it on when it was news to this peer.
+async fn gossip(peer: &mut Peer) {
+    let (sender, message) = peer.manager.pop_queue().await.unwrap();
+    let update = *message.downcast::<StateUpdate>().ok().unwrap();
+    if peer.state.merge(&update).unwrap() {
+        broadcast(&peer.manager, &update, Some(&sender)).await;
+    }
+}
+
+async fn announce(peer: &mut Peer, client: &Client, at: u64) {
+    let writer = peer.state.replica();
+    let update = peer
+        .state
+        .update::<Presence>(PRESENCE, |online| {
+            online.update(writer, client.user_id, |register| register.set(client.clone(), at, writer))
+        })
+        .unwrap();
+    broadcast(&peer.manager, &update, None).await;
+}
+
+fn presence(peer: &Peer) -> Vec<Client> {
+    peer.state
+        .get::<Presence>(PRESENCE)
+        .unwrap()
+        .iter()
+        .filter_map(|(_, client)| client.get().cloned())
+        .collect()
+}
+
+fn online(peer: &Peer) -> Vec<Uuid> {
+    presence(peer).iter().map(|client| client.user_id).collect()
+}
+
+#[tokio::test]
+async fn presence_converges_without_a_server() {
+    // alice - bob - carol: alice and carol only hear about each other through bob
+    let (mut alice, mut bob, mut carol) = (peer().await, peer().await, peer().await);
+    connect(&mut alice, &mut bob).await;
+    connect(&mut bob, &mut carol).await;
+
+    let clients: Vec<Client> = (0..3).map(|_| Client::from_user_id(Uuid::new_v4())).collect();
+    announce(&mut alice, &clients[0], 1).await;
+    announce(&mut bob, &clients[1], 1).await;
+    announce(&mut carol, &clients[2], 1).await;
+
+    // bob hears from both sides and relays, alice and carol each hear bob's update, then the relayed one
+    gossip(&mut bob).await;
+    gossip(&mut bob).await;
+    for peer in [&mut alice, &mut carol].iter_mut() {
+        gossip(peer).await;
+        gossip(peer).await;
+    }
+
+    let mut everyone: Vec<Uuid> = clients.iter().map(|client| client.user_id).collect();
+    everyone.sort();
+    for peer in [&alice, &bob, &carol].iter() {
+        assert_eq!(online(peer), everyone);
+    }
+
+    // alice picks a name, which replaces her entry instead of adding a second one
+    let mut named = clients[0].clone();
+    named.username = Some("alice".to_string());
+    announce(&mut alice, &named, 2).await;
+    gossip(&mut bob).await;
+    gossip(&mut carol).await;
+    assert_eq!(online(&carol), everyone);
+    assert!(presence(&carol).contains(&named));
+
+    // carol goes offline, the remove reaches alice through bob
+    let update = carol
+        .state
+        .update::<Presence>(PRESENCE, |online| online.remove(&clients[2].user_id))
+        .unwrap();
+    broadcast(&carol.manager, &update, None).await;
+    gossip(&mut bob).await;
+    gossip(&mut alice).await;
+
+    assert!(!online(&alice).contains(&clients[2].user_id));
+    assert_eq!(presence(&alice), presence(&bob));
+    assert_eq!(presence(&bob), presence(&carol));
+    // Nothing of her is left behind
+    for peer in [&alice, &bob, &carol].iter() {
+        assert_eq!(peer.state.get::<Presence>(PRESENCE).unwrap().len(), 2);
+    }
+}
+
+#[test]
+fn undeclared_keys_are_refused() {
+    let mut state = ReplicatedStateManager::new(Uuid::new_v4());
+    let update = StateUpdate {
+        identity: Uuid::new_v4(),
+        origin: Uuid::new_v4(),
+        key: "votes".to_string(),
+        data: Vec::new(),
+    };
+
+    assert_eq!(state.merge(&update), Err(StateErrors::NotReplicated("votes".to_string())));
+    assert_eq!(
+        state.get::<GCounter>("votes"),
+        Err(StateErrors::NotReplicated("votes".to_string()))
+    );
+
+    state.replicate::<GCounter>("votes").unwrap();
+    let replica = state.replica();
+    let update = state
+        .update::<GCounter>("votes", |votes| votes.increment(replica, 1))
+        .unwrap();
+    assert_eq!(update.name(), "StateUpdate");
+    assert!(!state.merge(&update).unwrap());
+    assert_eq!(state.full_sync().unwrap().len(), 1);
+}
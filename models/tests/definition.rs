@@ -0,0 +1,177 @@
+use models::{
+    DeclarativeProcess, Entities, EntityTypes, Message, MessageRegistry, Process, ProcessDefinition,
+    ProcessDefinitionErrors, TypedMessage,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::path::PathBuf;
+
+macro_rules! typed_message {
+    ($name:ident) => {
+        #[derive(Debug, Serialize, Deserialize)]
+        struct $name {
+            identity: Uuid,
+        }
+
+        impl Message for $name {
+            fn identity(&self) -> Uuid {
+                self.identity
+            }
+            fn name(&self) -> String {
+                Self::NAME.to_string()
+            }
+            fn description(&self) -> String {
+                String::new()
+            }
+            fn data(&self) -> Option<Vec<u8>> {
+                self.encode()
+            }
+        }
+
+        impl TypedMessage for $name {
+            const NAME: &'static str = stringify!($name);
+        }
+    };
+}
+
+typed_message!(SdpOffer);
+typed_message!(SdpAnswer);
+typed_message!(Questionnaire);
+typed_message!(Rating);
+typed_message!(ThankYou);
+
+fn registry() -> MessageRegistry {
+    let mut registry = MessageRegistry::new();
+    registry
+        .register::<SdpOffer>()
+        .register::<SdpAnswer>()
+        .register::<Questionnaire>()
+        .register::<Rating>()
+        .register::<ThankYou>();
+    registry
+}
+
+fn example(file: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("processes")
+        .join(file)
+}
+
+#[test]
+fn example_definitions_become_processes() {
+    let handshake = ProcessDefinition::from_file(example("signaling_handshake.toml")).unwrap();
+    let process: DeclarativeProcess = handshake.to_process(&registry()).unwrap();
+    assert_eq!(process.name(), "Signaling handshake");
+    assert!(process.is_blocking());
+    assert_eq!(
+        process.involved_parties(),
+        &[
+            Entities::Exactly(2, EntityTypes::Client),
+            Entities::One(EntityTypes::Server)
+        ]
+    );
+
+    let questionnaire = ProcessDefinition::from_file(example("post_call_questionnaire.json")).unwrap();
+    let process: DeclarativeProcess = questionnaire.to_process(&registry()).unwrap();
+    assert!(!process.is_blocking());
+    assert_eq!(questionnaire.steps.len(), 3);
+    assert_eq!(questionnaire.steps[1].message, "Rating");
+}
+
+#[test]
+fn validation_points_at_the_offending_steps() {
+    let definition = ProcessDefinition::from_toml_str(
+        r#"
+        name = "Broken"
+
+        [[involved_parties]]
+        entity_type = "Client"
+        exactly = 2
+        at_least = 1
+
+        [[steps]]
+        posted_by = "Client"
+        message = "SdpOffer"
+
+        [[steps]]
+        posted_by = "Server"
+        message = "Farewell"
+        "#,
+    )
+    .unwrap();
+
+    let errors = definition
+        .to_process::<DeclarativeProcess>(&registry())
+        .err()
+        .unwrap();
+    assert_eq!(
+        errors,
+        vec![
+            ProcessDefinitionErrors::AmbiguousParty { party: 0 },
+            ProcessDefinitionErrors::UnknownMessage {
+                step: 1,
+                message: "Farewell".to_string()
+            },
+            ProcessDefinitionErrors::PartyNotInvolved {
+                step: 1,
+                posted_by: EntityTypes::Server
+            },
+        ]
+    );
+    assert_eq!(
+        errors[1].to_string(),
+        "step 1: no message named 'Farewell' is registered"
+    );
+}
+
+#[test]
+fn malformed_files_are_parse_errors() {
+    assert!(matches!(
+        ProcessDefinition::from_json_str(r#"{ "name": "No steps" }"#),
+        Err(ProcessDefinitionErrors::Parse(_))
+    ));
+    assert!(matches!(
+        ProcessDefinition::from_file(example("missing.toml")),
+        Err(ProcessDefinitionErrors::Io(_))
+    ));
+    // The format is refused before anything is read
+    assert_eq!(
+        ProcessDefinition::from_file(example("missing.yaml")),
+        Err(ProcessDefinitionErrors::UnsupportedFormat("yaml".to_string()))
+    );
+
+    let empty = ProcessDefinition::from_json_str(
+        r#"{ "name": " ", "involved_parties": [], "steps": [] }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        empty.validate(&registry()),
+        Err(vec![
+            ProcessDefinitionErrors::MissingName,
+            ProcessDefinitionErrors::NoSteps
+        ])
+    );
+}
+
+#[test]
+fn misspelled_keys_are_parse_errors() {
+    let misspelled = [
+        // At the top, in a party and in a step
+        "name = \"Handshake\"\nblockng = true\ninvolved_parties = []\nsteps = []\n",
+        "name = \"Handshake\"\nsteps = []\n\n[[involved_parties]]\nentity_type = \"Client\"\nexacty = 2\n",
+        "name = \"Handshake\"\ninvolved_parties = []\n\n[[steps]]\nposted_by = \"Client\"\nmessage = \"SdpOffer\"\ndescripton = \"Send the offer\"\n",
+    ];
+
+    for text in misspelled.iter() {
+        assert!(
+            matches!(ProcessDefinition::from_toml_str(text), Err(ProcessDefinitionErrors::Parse(_))),
+            "{:?} was accepted",
+            text
+        );
+    }
+    assert!(matches!(
+        ProcessDefinition::from_json_str(r#"{ "name": "Handshake", "involved_parties": [], "steps": [], "looping ": true }"#),
+        Err(ProcessDefinitionErrors::Parse(_))
+    ));
+}
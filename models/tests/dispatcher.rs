@@ -0,0 +1,155 @@
+mod common;
+
+use common::{client, Step};
+use models::loopback::{LoopbackChannel, LoopbackManager};
+use models::{
+    CommunicationManager, DeclarativeProcess, Dispatched, DispatchErrors, Dispatcher, Entities,
+    EntityTypes, InternalMessage, InternalSystemComponents,
+    LocalProcessManager, Message, Process, ProcessManager,
+};
+use uuid::Uuid;
+
+fn greeting_process() -> DeclarativeProcess {
+    DeclarativeProcess::new(
+        vec![Entities::One(EntityTypes::Client)],
+        vec![
+            (EntityTypes::Client, Box::new(Step("Hello")) as Box<dyn Message>),
+            (EntityTypes::Client, Box::new(Step("Goodbye")) as Box<dyn Message>),
+        ],
+        "greeting".to_string(),
+        String::new(),
+        true,
+        false,
+    )
+}
+
+#[tokio::test]
+async fn messages_reach_the_component_they_are_addressed_to() {
+    let me = client();
+    let mut dispatcher = Dispatcher::new();
+    let mut observed = dispatcher.observe();
+
+    let mut process_manager = LocalProcessManager::initialize(me).await;
+    let managed = process_manager.register_functionality(greeting_process());
+    let process_manager = dispatcher.register(process_manager);
+    let process_manager_uuid = process_manager.lock().await.get_uuid();
+
+    let mut communication_manager =
+        LoopbackManager::new(me, vec![(EntityTypes::Client, EntityTypes::Client)]).await;
+    let (my_end, _their_end) = LoopbackChannel::pair();
+    communication_manager.add_channel(my_end, client()).await.unwrap();
+    let channel = communication_manager.open_channels().await[0].0;
+    let communication_manager = dispatcher.register(communication_manager);
+    let communication_manager_uuid = communication_manager.lock().await.get_uuid();
+
+    dispatcher
+        .dispatch(None, process_manager_uuid, InternalMessage::StartProcess(managed))
+        .await
+        .unwrap();
+    dispatcher
+        .dispatch(
+            Some(process_manager_uuid),
+            communication_manager_uuid,
+            InternalMessage::CloseChannel(channel),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        process_manager
+            .lock()
+            .await
+            .process(&managed)
+            .unwrap()
+            .waiting_for_message_type(),
+        Some("Hello".to_string())
+    );
+    assert!(communication_manager.lock().await.open_channels().await.is_empty());
+
+    assert_eq!(
+        observed.recv().await.unwrap(),
+        Dispatched {
+            from: None,
+            to: process_manager_uuid,
+            message: InternalMessage::StartProcess(managed),
+            delivered: true,
+        }
+    );
+    assert_eq!(observed.recv().await.unwrap().from, Some(process_manager_uuid));
+}
+
+#[tokio::test]
+async fn route_uses_the_subject_and_unknown_targets_are_reported() {
+    let mut dispatcher = Dispatcher::new();
+    let mut observed = dispatcher.observe();
+    let process = dispatcher.register(greeting_process());
+    let uuid = process.lock().await.get_uuid();
+
+    dispatcher.route(None, InternalMessage::StartProcess(uuid)).await.unwrap();
+    dispatcher.route(None, InternalMessage::AdvanceProcess(uuid)).await.unwrap();
+    assert_eq!(
+        process.lock().await.waiting_for_message_type(),
+        Some("Goodbye".to_string())
+    );
+
+    let nobody = Uuid::new_v4();
+    assert_eq!(
+        dispatcher.route(None, InternalMessage::OpenChannel(nobody)).await,
+        Err(DispatchErrors::UnknownComponent {
+            target: nobody,
+            message: InternalMessage::OpenChannel(nobody),
+        })
+    );
+
+    let deliveries: Vec<bool> = (0..3)
+        .map(|_| observed.try_recv().unwrap().delivered)
+        .collect();
+    assert_eq!(deliveries, vec![true, true, false]);
+
+    assert!(dispatcher.unregister(&uuid));
+    assert!(dispatcher.route(None, InternalMessage::StartProcess(uuid)).await.is_err());
+}
+
+#[tokio::test]
+async fn routed_messages_reach_the_manager_that_owns_their_subject() {
+    let me = client();
+    let mut dispatcher = Dispatcher::new();
+    let mut observed = dispatcher.observe();
+
+    let mut process_manager = LocalProcessManager::initialize(me).await;
+    let managed = process_manager.register_functionality(greeting_process());
+    let process_manager = dispatcher.register(process_manager);
+    let process_manager_uuid = process_manager.lock().await.get_uuid();
+
+    let mut communication_manager =
+        LoopbackManager::new(me, vec![(EntityTypes::Client, EntityTypes::Client)]).await;
+    let (my_end, _their_end) = LoopbackChannel::pair();
+    communication_manager.add_channel(my_end, client()).await.unwrap();
+    let channel = communication_manager.open_channels().await[0].0;
+    let communication_manager = dispatcher.register(communication_manager);
+    let communication_manager_uuid = communication_manager.lock().await.get_uuid();
+
+    dispatcher.route(None, InternalMessage::StartProcess(managed)).await.unwrap();
+    dispatcher.route(None, InternalMessage::AdvanceProcess(managed)).await.unwrap();
+    dispatcher.route(None, InternalMessage::CloseChannel(channel)).await.unwrap();
+
+    assert_eq!(
+        process_manager
+            .lock()
+            .await
+            .process(&managed)
+            .unwrap()
+            .waiting_for_message_type(),
+        Some("Goodbye".to_string())
+    );
+    assert!(communication_manager.lock().await.open_channels().await.is_empty());
+
+    let targets: Vec<Uuid> = (0..3).map(|_| observed.try_recv().unwrap().to).collect();
+    assert_eq!(
+        targets,
+        vec![process_manager_uuid, process_manager_uuid, communication_manager_uuid]
+    );
+
+    // Once closed, nobody owns the channel anymore
+    assert!(dispatcher.route(None, InternalMessage::CloseChannel(channel)).await.is_err());
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use common::client;
+use models::loopback::LoopbackChannel;
+use models::{
+    CommunicationChannel, CommunicationErrors, FaultStats, FaultyChannel,
+    Faults, InternalMessage, InternalSystemComponents, Message, RawMessage,
+};
+use tokio::time::{advance, Duration};
+use uuid::Uuid;
+
+fn numbered(number: u8) -> Box<dyn Message> {
+    Box::new(RawMessage {
+        identity: Uuid::new_v4(),
+        name: "Numbered".to_string(),
+        description: number.to_string(),
+        data: vec![number],
+        correlation: None,
+    })
+}
+
+/// Sends the numbers 0 to 99 through a polluted channel and collects the data of whatever arrives
+async fn run(faults: Faults, seed: u64) -> (Vec<u8>, FaultStats) {
+    let (alice, bob) = (client(), client());
+    let (alice_end, bob_end) = LoopbackChannel::pair();
+    let faulty = FaultyChannel::new(alice_end, faults, seed);
+
+    for number in 0..100 {
+        faulty.send(alice, bob, numbered(number)).await.unwrap();
+    }
+    let stats = faulty.stats();
+    // Delayed messages keep the channel open until they are delivered
+    drop(faulty);
+
+    let mut arrived = vec![];
+    while let Some(message) = bob_end.receive().await {
+        arrived.push(message.data().unwrap()[0]);
+    }
+    (arrived, stats)
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_same_seed_misbehaves_the_same_way() {
+    let faults = Faults {
+        latency: (Duration::from_millis(10), Duration::from_millis(500)),
+        drop: 0.1,
+        duplicate: 0.1,
+        reorder: 0.1,
+        corrupt: 0.1,
+        sever_after: None,
+    };
+
+    let (first, first_stats) = run(faults.clone(), 7).await;
+    let (second, second_stats) = run(faults.clone(), 7).await;
+    assert_eq!(first, second);
+    assert_eq!(first_stats, second_stats);
+
+    assert_eq!(first_stats.sent, 100);
+    assert!(first_stats.dropped > 0 && first_stats.duplicated > 0);
+    assert!(first_stats.reordered > 0 && first_stats.corrupted > 0);
+    assert_eq!(
+        first.len() as u64,
+        100 - first_stats.dropped + first_stats.duplicated
+    );
+    assert_ne!(first, (0..100).collect::<Vec<u8>>());
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_dormant_channel_is_polluted_by_its_identity() {
+    let (alice, bob) = (client(), client());
+    let (alice_end, bob_end) = LoopbackChannel::pair();
+    let mut faulty = FaultyChannel::dormant(
+        alice_end,
+        Faults {
+            drop: 1.0,
+            ..Faults::none()
+        },
+        1,
+    );
+
+    faulty.send(alice, bob, numbered(1)).await.unwrap();
+    assert_eq!(bob_end.receive().await.unwrap().data(), Some(vec![1]));
+
+    faulty
+        .send_receive_messages(InternalMessage::PolluteProcess(Uuid::new_v4()))
+        .await;
+    assert!(!faulty.is_polluted());
+    let identity = faulty.identity();
+    faulty
+        .send_receive_messages(InternalMessage::PolluteProcess(identity))
+        .await;
+    assert!(faulty.is_polluted());
+
+    faulty.send(alice, bob, numbered(2)).await.unwrap();
+    faulty.clean();
+    faulty.send(alice, bob, numbered(3)).await.unwrap();
+    assert_eq!(bob_end.receive().await.unwrap().data(), Some(vec![3]));
+    assert_eq!(faulty.stats().dropped, 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_scheduled_sever_cuts_off_both_directions() {
+    let (alice, bob) = (client(), client());
+    let (alice_end, bob_end) = LoopbackChannel::pair();
+    let faulty = FaultyChannel::new(
+        alice_end,
+        Faults {
+            latency: (Duration::from_secs(2), Duration::from_secs(2)),
+            sever_after: Some(Duration::from_secs(5)),
+            ..Faults::none()
+        },
+        3,
+    );
+
+    faulty.send(alice, bob, numbered(1)).await.unwrap();
+    advance(Duration::from_secs(3)).await;
+    faulty.send(alice, bob, numbered(2)).await.unwrap();
+    assert_eq!(bob_end.receive().await.unwrap().data(), Some(vec![1]));
+
+    // Waiting for a message ends with the sever, the second message was still in flight and is lost
+    assert!(faulty.receive().await.is_none());
+    assert_eq!(
+        faulty.send(alice, bob, numbered(3)).await,
+        Err(CommunicationErrors::ChannelClosed)
+    );
+    advance(Duration::from_secs(5)).await;
+    drop(faulty);
+    assert!(bob_end.receive().await.is_none());
+}
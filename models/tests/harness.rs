@@ -0,0 +1,465 @@
+//! Runs the websocket server's ```rust ServerState ``` against many clients without a single real socket or a real second passing. Every round does what the websocket server does: the messages that arrived are handed to ```rust ServerState::handle ```, the round is started with ```rust ServerState::tick ```, and the returned ```rust Effect ```s are carried out over ```rust FaultyChannel ```s on loopback channels. The simulated clients answer pings, go idle and come back, or stop answering for good.
+//!
+//! Every random decision, from the uuids of the clients to the faults on their channels, is drawn from the seed of the ```rust HarnessConfig ```. The tests run on a current thread runtime with paused tokio time: the rounds are skipped through instead of waited for, and the same config always produces the same ```rust HarnessEvent ```s.
+
+use models::loopback::LoopbackChannel;
+use models::{
+    Client, Codec, Command, CommunicationChannel, Effect, Entity, EntityDetails, FaultyChannel,
+    Faults, Handshake, Inbound, Message, PingStatus, PingTime, PresenceEvent, RawMessage, ServerState, Status,
+};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::time::{sleep_until, timeout, Duration, Instant};
+use uuid::{Uuid, Variant, Version};
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+
+#[derive(Debug, Clone, PartialEq)]
+struct HarnessConfig {
+    seed: u64,
+    clients: usize,
+    /// Clients join at random during the first rounds
+    joining_rounds: u64,
+    rounds: u64,
+    /// Virtual time between two rounds
+    round: Duration,
+    /// Counted in rounds
+    ping_time: PingTime,
+    max_missed_replies: u32,
+    max_messages_per_round: u32,
+    /// On the channels in both directions
+    faults: Faults,
+    /// The chance per round that a client stops answering for good
+    dropout: f64,
+    /// The chance per round that a client goes idle, or comes back when it is idle
+    idling: f64,
+}
+
+impl Default for HarnessConfig {
+    /// The timing of the websocket server
+    fn default() -> Self {
+        HarnessConfig {
+            seed: 0,
+            clients: 10,
+            joining_rounds: 3,
+            rounds: 30,
+            round: Duration::from_secs(20),
+            ping_time: PingTime::Every(2),
+            max_missed_replies: 2,
+            max_messages_per_round: 50,
+            faults: Faults::none(),
+            dropout: 0.0,
+            idling: 0.0,
+        }
+    }
+}
+
+/// What happened during a run, as seen by the server unless noted otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HarnessEvent {
+    Joined(Uuid),
+    Pinged { client: Uuid, at: u64 },
+    /// The server took the pong as the reply to its outstanding ping
+    Ponged { client: Uuid, at: u64 },
+    Presence { client: Uuid, status: Option<Status> },
+    /// The client stopped answering, the server only learns about it through the missing pongs
+    WentSilent(Uuid),
+    /// The server closed the connection
+    Removed(Uuid),
+}
+
+/// Commands travel the way the websocket server sends them: encoded, so that corrupted ones fail to decode or decode into something else
+fn command_message(command: &Command) -> Box<dyn Message> {
+    Box::new(RawMessage {
+        identity: Uuid::nil(),
+        name: "Command".to_string(),
+        description: String::new(),
+        data: Codec::Bincode.encode(command).expect("commands always encode"),
+        correlation: None,
+    })
+}
+
+/// Messages that don't decode are dropped, the websocket server would only answer them with an error
+fn command_of(message: &dyn Message) -> Option<Command> {
+    Codec::Bincode.decode(&message.data()?).ok()
+}
+
+/// Everything that arrived on the channel by now, without waiting for more
+async fn drain(channel: &impl CommunicationChannel) -> Vec<Command> {
+    // Once a task used up its budget tokio makes the receive wait, and the zero timeout would end the drain early
+    tokio::task::yield_now().await;
+    let mut arrived = vec![];
+    while let Ok(Some(message)) = timeout(Duration::ZERO, channel.receive()).await {
+        arrived.extend(command_of(message.as_ref()));
+    }
+    arrived
+}
+
+struct SimulatedClient {
+    entity: Entity,
+    joins_at: u64,
+    connected: bool,
+    silent: bool,
+    /// What the client believes, the server may have refused to let it go idle
+    idle: bool,
+    server_end: FaultyChannel<LoopbackChannel>,
+    client_end: FaultyChannel<LoopbackChannel>,
+}
+
+impl SimulatedClient {
+    async fn send(&self, server: Entity, command: Command) {
+        // Sends only fail on severed channels, which the server notices through the missing pongs
+        let _ = self.client_end.send(self.entity, server, command_message(&command)).await;
+    }
+}
+
+struct Harness {
+    config: HarnessConfig,
+    rng: StdRng,
+    state: ServerState,
+    server: Entity,
+    /// By uuid, so that every round handles the clients in the same order
+    clients: BTreeMap<Uuid, SimulatedClient>,
+    /// The presence of every client the server knows about, to report the changes
+    statuses: BTreeMap<Uuid, Option<Status>>,
+    events: Vec<(u64, HarnessEvent)>,
+}
+
+impl Harness {
+    fn new(config: HarnessConfig) -> Harness {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let state = ServerState::seeded(
+            config.ping_time,
+            config.max_missed_replies,
+            config.max_messages_per_round,
+            rng.gen(),
+        );
+        let address = SocketAddr::from(([127, 0, 0, 1], 2096));
+        let server = Entity::new(EntityDetails::Server(state.uuid(), address));
+
+        let mut clients = BTreeMap::new();
+        for _ in 0..config.clients {
+            let entity = Entity::new(EntityDetails::Client(seeded_uuid(&mut rng), None));
+            let (server_end, client_end) = LoopbackChannel::pair();
+            let client = SimulatedClient {
+                entity,
+                joins_at: rng.gen_range(0..=config.joining_rounds),
+                connected: false,
+                silent: false,
+                idle: false,
+                server_end: FaultyChannel::new(server_end, config.faults.clone(), rng.gen()),
+                client_end: FaultyChannel::new(client_end, config.faults.clone(), rng.gen()),
+            };
+            clients.insert(entity.uuid(), client);
+        }
+
+        Harness {
+            config,
+            rng,
+            state,
+            server,
+            clients,
+            statuses: BTreeMap::new(),
+            events: vec![],
+        }
+    }
+
+    fn state(&self) -> &ServerState {
+        &self.state
+    }
+
+    /// The clients the server still considers online
+    fn online(&self) -> Vec<Uuid> {
+        self.state.online().keys().copied().collect()
+    }
+
+    /// What happened so far, by round
+    fn events(&self) -> &[(u64, HarnessEvent)] {
+        &self.events
+    }
+
+    /// Runs every round of the config, starting now. A harness is meant to be run once.
+    async fn run(&mut self) -> &[(u64, HarnessEvent)] {
+        let start = Instant::now();
+        for round in 0..self.config.rounds {
+            sleep_until(start + self.config.round * round as u32).await;
+            self.round(round).await;
+        }
+        &self.events
+    }
+
+    async fn round(&mut self, round: u64) {
+        self.join(round).await;
+        self.answer_pings().await;
+        self.receive(round).await;
+        self.churn(round).await;
+        let effects = self.state.tick(round);
+        self.apply(round, effects).await;
+    }
+
+    /// Accepting the connection makes a client online, its handshake goes over the channel like everything after it
+    async fn join(&mut self, round: u64) {
+        let joining: Vec<Uuid> = self
+            .clients
+            .values()
+            .filter(|client| client.joins_at == round)
+            .map(|client| client.entity.uuid())
+            .collect();
+
+        for uuid in joining {
+            self.clients.get_mut(&uuid).unwrap().connected = true;
+            self.events.push((round, HarnessEvent::Joined(uuid)));
+            let effects = self.state.handle(Inbound::from_client(
+                uuid,
+                Command::ServerInitiated(Client::from_user_id(uuid)),
+            ));
+            self.apply(round, effects).await;
+            self.clients[&uuid]
+                .send(self.server, Command::Handshake(Handshake::current()))
+                .await;
+        }
+    }
+
+    async fn answer_pings(&self) {
+        for client in self.clients.values().filter(|client| client.connected && !client.silent) {
+            for command in drain(&client.client_end).await {
+                if let Command::Ping(uuid, at) = command {
+                    client.send(self.server, Command::Pong(uuid, at)).await;
+                }
+            }
+        }
+    }
+
+    async fn receive(&mut self, round: u64) {
+        let connected: Vec<Uuid> = self
+            .clients
+            .values()
+            .filter(|client| client.connected)
+            .map(|client| client.entity.uuid())
+            .collect();
+
+        for uuid in connected {
+            for command in drain(&self.clients[&uuid].server_end).await {
+                let ping_status = |state: &ServerState| state.client(&uuid).map(|online| online.ping_status);
+                let pong = match command {
+                    Command::Pong(_, at) if ping_status(&self.state) == Some(PingStatus::Pinged(at)) => Some(at),
+                    _ => None,
+                };
+                let effects = self.state.handle(Inbound::from_client(uuid, command));
+                if let Some(at) = pong.filter(|at| ping_status(&self.state) == Some(PingStatus::Ponged(*at))) {
+                    self.events.push((round, HarnessEvent::Ponged { client: uuid, at }));
+                }
+                self.apply(round, effects).await;
+            }
+        }
+    }
+
+    async fn churn(&mut self, round: u64) {
+        let connected: Vec<Uuid> = self
+            .clients
+            .values()
+            .filter(|client| client.connected && !client.silent)
+            .map(|client| client.entity.uuid())
+            .collect();
+
+        for uuid in connected {
+            if chance(&mut self.rng, self.config.dropout) {
+                self.clients.get_mut(&uuid).unwrap().silent = true;
+                self.events.push((round, HarnessEvent::WentSilent(uuid)));
+                continue;
+            }
+
+            if chance(&mut self.rng, self.config.idling) {
+                let client = self.clients.get_mut(&uuid).unwrap();
+                let event = if client.idle {
+                    PresenceEvent::CameBack
+                } else {
+                    PresenceEvent::WentIdle
+                };
+                client.idle = !client.idle;
+                self.clients[&uuid]
+                    .send(self.server, Command::UpdatePresence(event))
+                    .await;
+            }
+        }
+    }
+
+    async fn apply(&mut self, round: u64, effects: Vec<Effect>) {
+        for effect in effects {
+            match effect {
+                Effect::Send { to, command } => self.send(round, to, command).await,
+                Effect::Broadcast { to, command } => {
+                    for client in to {
+                        self.send(round, client, command.clone()).await;
+                    }
+                }
+                Effect::Relay { next_hop, message } => self.send(round, next_hop, message.command).await,
+                Effect::Close(uuid) => {
+                    if let Some(client) = self.clients.get_mut(&uuid) {
+                        client.connected = false;
+                        client.server_end.sever();
+                        client.client_end.sever();
+                    }
+                    self.events.push((round, HarnessEvent::Removed(uuid)));
+                }
+            }
+        }
+        self.presence_changes(round);
+    }
+
+    async fn send(&mut self, round: u64, to: Uuid, command: Command) {
+        if let Command::Ping(_, at) = command {
+            self.events.push((round, HarnessEvent::Pinged { client: to, at }));
+        }
+        if let Some(client) = self.clients.get(&to) {
+            let _ = client
+                .server_end
+                .send(self.server, client.entity, command_message(&command))
+                .await;
+        }
+    }
+
+    fn presence_changes(&mut self, round: u64) {
+        let mut now: BTreeMap<Uuid, Option<Status>> = self
+            .state
+            .online()
+            .iter()
+            .map(|(uuid, online)| (*uuid, online.status.clone()))
+            .collect();
+        // Clients that went offline have no status anymore
+        for uuid in self.statuses.keys() {
+            now.entry(*uuid).or_insert(None);
+        }
+
+        for (uuid, status) in &now {
+            if self.statuses.get(uuid).cloned().flatten() != *status {
+                self.events.push((round, HarnessEvent::Presence { client: *uuid, status: status.clone() }));
+            }
+        }
+        now.retain(|_, status| status.is_some());
+        self.statuses = now;
+    }
+}
+
+fn chance(rng: &mut StdRng, probability: f64) -> bool {
+    probability > 0.0 && rng.gen_bool(probability.min(1.0))
+}
+
+fn seeded_uuid(rng: &mut StdRng) -> Uuid {
+    uuid::Builder::from_bytes(rng.gen())
+        .set_variant(Variant::RFC4122)
+        .set_version(Version::Random)
+        .build()
+}
+
+fn rough_network(seed: u64) -> HarnessConfig {
+    HarnessConfig {
+        seed,
+        clients: 40,
+        rounds: 30,
+        faults: Faults {
+            latency: (Duration::from_millis(20), Duration::from_secs(3)),
+            drop: 0.05,
+            duplicate: 0.05,
+            reorder: 0.05,
+            corrupt: 0.02,
+            sever_after: None,
+        },
+        dropout: 0.01,
+        idling: 0.05,
+        ..HarnessConfig::default()
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_ten_minute_scenario_replays_identically() {
+    let started = Instant::now();
+    let mut first = Harness::new(rough_network(42));
+    first.run().await;
+    // 30 rounds of 20 seconds, the last one starting after 29 of them
+    assert!(started.elapsed() >= Duration::from_secs(29 * 20));
+
+    let mut second = Harness::new(rough_network(42));
+    assert_eq!(second.run().await, first.events());
+    assert_eq!(second.online(), first.online());
+
+    let mut other = Harness::new(rough_network(43));
+    assert_ne!(other.run().await, first.events());
+
+    let removed = first
+        .events()
+        .iter()
+        .filter(|(_, event)| matches!(event, HarnessEvent::Removed(_)))
+        .count();
+    assert!(removed > 0);
+    assert_eq!(first.online().len() + removed, 40);
+}
+
+#[tokio::test(start_paused = true)]
+async fn silent_clients_are_removed_after_their_missed_pings() {
+    let mut harness = Harness::new(HarnessConfig {
+        clients: 3,
+        joining_rounds: 0,
+        rounds: 10,
+        dropout: 1.0,
+        ..HarnessConfig::default()
+    });
+    let events = harness.run().await.to_vec();
+
+    let joined: Vec<_> = events
+        .iter()
+        .filter_map(|(_, event)| match event {
+            HarnessEvent::Joined(client) => Some(*client),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(joined.len(), 3);
+
+    // Pinged at rounds 0 and 2, the ping at round 4 would have been the third unanswered one
+    for client in joined {
+        assert!(events.contains(&(0, HarnessEvent::WentSilent(client))));
+        assert!(events.contains(&(4, HarnessEvent::Removed(client))));
+        assert!(events.contains(&(4, HarnessEvent::Presence { client, status: None })));
+    }
+    assert!(harness.online().is_empty());
+    let state = harness.state();
+    assert_eq!(state.topology().neighbours(&state.uuid()), vec![]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn on_a_clean_network_every_ping_is_answered() {
+    let mut harness = Harness::new(HarnessConfig {
+        clients: 12,
+        idling: 0.2,
+        ..HarnessConfig::default()
+    });
+    let events = harness.run().await.to_vec();
+
+    let pinged = events
+        .iter()
+        .filter(|(_, event)| matches!(event, HarnessEvent::Pinged { .. }))
+        .count();
+    let ponged: Vec<_> = events
+        .iter()
+        .filter_map(|(round, event)| match event {
+            HarnessEvent::Ponged { at, .. } => Some((*round, *at)),
+            _ => None,
+        })
+        .collect();
+
+    // Pings of the last round are still on their way when the run ends
+    assert!(pinged > ponged.len() && ponged.len() > 12 * 10);
+    assert!(ponged.iter().all(|(round, at)| *round == at + 1));
+    assert_eq!(harness.online().len(), 12);
+
+    // Going idle and coming back goes through the server like it does for real clients
+    let idle = |status: &Option<Status>| *status == Some(Status::Idle);
+    assert!(events.iter().any(|(_, event)| matches!(event, HarnessEvent::Presence { status, .. } if idle(status))));
+    assert!(harness
+        .state()
+        .online()
+        .values()
+        .all(|online| matches!(online.status, Some(Status::Idle) | Some(Status::WaitingForPartner))));
+}
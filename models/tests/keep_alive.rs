@@ -0,0 +1,103 @@
+use models::{KeepAlive, KeepAliveAction, PingStatus, PingTime};
+use uuid::Uuid;
+
+fn pinged(actions: &[KeepAliveAction]) -> Vec<Uuid> {
+    actions
+        .iter()
+        .filter_map(|action| match action {
+            KeepAliveAction::Ping { channel, .. } => Some(*channel),
+            KeepAliveAction::Close(_) => None,
+        })
+        .collect()
+}
+
+#[test]
+fn every_pings_on_schedule_and_never_stays_quiet() {
+    let mut keep_alive = KeepAlive::new(3);
+    let (regular, quiet) = (Uuid::new_v4(), Uuid::new_v4());
+    keep_alive.watch(regular, PingTime::Every(2), 0);
+    keep_alive.watch(quiet, PingTime::Never, 0);
+
+    let mut pinged_at = Vec::new();
+    for now in 0..7 {
+        let actions = keep_alive.tick(now);
+        if !actions.is_empty() {
+            assert_eq!(actions, vec![KeepAliveAction::Ping { channel: regular, at: now }]);
+            keep_alive.pong(&regular, now);
+            pinged_at.push(now);
+        }
+    }
+
+    assert_eq!(pinged_at, vec![0, 2, 4, 6]);
+    assert_eq!(keep_alive.status(&regular), Some(&PingStatus::Ponged(6)));
+    assert_eq!(keep_alive.status(&quiet), Some(&PingStatus::NeverPinged));
+}
+
+#[test]
+fn channels_are_closed_after_missing_replies() {
+    let mut keep_alive = KeepAlive::new(2);
+    let (answering, silent) = (Uuid::new_v4(), Uuid::new_v4());
+    keep_alive.watch(answering, PingTime::Every(1), 0);
+    keep_alive.watch(silent, PingTime::Every(1), 0);
+
+    let mut closed_at = None;
+    for now in 0..5 {
+        for action in keep_alive.tick(now) {
+            match action {
+                KeepAliveAction::Ping { channel, at } if channel == answering => {
+                    assert!(keep_alive.pong(&channel, at))
+                }
+                KeepAliveAction::Close(channel) => {
+                    assert_eq!(channel, silent);
+                    closed_at = Some(now);
+                }
+                KeepAliveAction::Ping { .. } => {}
+            }
+        }
+    }
+
+    // Pinged at 0, missed at 1, missed again at 2
+    assert_eq!(closed_at, Some(2));
+    assert!(!keep_alive.is_watching(&silent));
+    assert_eq!(keep_alive.status(&answering), Some(&PingStatus::Ponged(4)));
+}
+
+#[test]
+fn random_intervals_repeat_with_the_same_seed() {
+    let channel = Uuid::new_v4();
+    let schedule = |seed: u64| {
+        let mut keep_alive = KeepAlive::seeded(u32::MAX, seed);
+        keep_alive.watch(channel, PingTime::RandomBetween(2, 5), 0);
+        (0..100)
+            .filter(|now| !pinged(&keep_alive.tick(*now)).is_empty())
+            .collect::<Vec<u64>>()
+    };
+
+    let first = schedule(7);
+    assert_eq!(first, schedule(7));
+    for gap in first.windows(2).map(|pair| pair[1] - pair[0]) {
+        assert!((2..=5).contains(&gap), "gap of {}", gap);
+    }
+}
+
+#[test]
+fn only_the_reply_to_the_outstanding_ping_counts() {
+    let mut keep_alive = KeepAlive::new(2);
+    let channel = Uuid::new_v4();
+    keep_alive.watch(channel, PingTime::Every(1), 0);
+
+    // Nothing has been sent yet, so nothing can be answered
+    assert!(!keep_alive.pong(&channel, 0));
+    keep_alive.tick(0);
+    keep_alive.tick(1);
+
+    // A late reply to the ping at 0 and a reply to a ping that was never sent don't keep the channel alive
+    assert!(!keep_alive.pong(&channel, 0));
+    assert!(!keep_alive.pong(&channel, 7));
+    assert_eq!(keep_alive.status(&channel), Some(&PingStatus::Pinged(1)));
+
+    assert!(keep_alive.pong(&channel, 1));
+    assert!(!keep_alive.pong(&channel, 1));
+    assert_eq!(keep_alive.status(&channel), Some(&PingStatus::Ponged(1)));
+    assert!(!keep_alive.pong(&Uuid::new_v4(), 1));
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use common::client;
+use models::loopback::{LoopbackChannel, LoopbackManager};
+use models::{
+    CommunicationErrors, CommunicationManager, Entity, EntityTypes, InternalMessage,
+    InternalSystemComponents, Message,
+};
+use uuid::Uuid;
+
+struct Greeting {
+    identity: Uuid,
+    text: String,
+}
+
+fn greeting(text: &str) -> Box<dyn Message> {
+    Box::new(Greeting {
+        identity: Uuid::new_v4(),
+        text: text.to_string(),
+    })
+}
+
+impl Message for Greeting {
+    fn identity(&self) -> Uuid {
+        self.identity
+    }
+    fn name(&self) -> String {
+        "Greeting".to_string()
+    }
+    fn description(&self) -> String {
+        self.text.clone()
+    }
+    fn data(&self) -> Option<Vec<u8>> {
+        Some(self.text.as_bytes().to_vec())
+    }
+}
+
+async fn manager(identity: Entity) -> LoopbackManager {
+    LoopbackManager::new(identity, vec![(EntityTypes::Client, EntityTypes::Client)]).await
+}
+
+#[tokio::test]
+async fn messages_travel_between_three_entities() {
+    let (alice, bob, carol) = (client(), client(), client());
+    let mut alice_manager = manager(alice).await;
+    let mut bob_manager = manager(bob).await;
+    let mut carol_manager = manager(carol).await;
+
+    let (alice_end, bob_end) = LoopbackChannel::pair();
+    alice_manager.add_channel(alice_end, bob).await.unwrap();
+    bob_manager.add_channel(bob_end, alice).await.unwrap();
+
+    let (bob_end, carol_end) = LoopbackChannel::pair();
+    bob_manager.add_channel(bob_end, carol).await.unwrap();
+    carol_manager.add_channel(carol_end, bob).await.unwrap();
+
+    alice_manager.send(&bob, greeting("hi bob")).await.unwrap();
+    let (sender, message) = bob_manager.pop_queue().await.unwrap();
+    assert_eq!(sender, alice);
+    assert_eq!(message.description(), "hi bob");
+
+    bob_manager.send(&carol, greeting("hi carol")).await.unwrap();
+    let (sender, message) = carol_manager.pop_queue().await.unwrap();
+    assert_eq!(sender, bob);
+    assert_eq!(message.data(), Some(b"hi carol".to_vec()));
+
+    assert_eq!(bob_manager.open_channels().await.len(), 2);
+    assert_eq!(
+        alice_manager.send(&carol, greeting("unreachable")).await,
+        Err(CommunicationErrors::UnknownParticipant)
+    );
+}
+
+#[tokio::test]
+async fn queued_messages_are_popped_before_the_inbox() {
+    let (alice, bob) = (client(), client());
+    let mut alice_manager = manager(alice).await;
+    let mut bob_manager = manager(bob).await;
+
+    let (alice_end, bob_end) = LoopbackChannel::pair();
+    alice_manager.add_channel(alice_end, bob).await.unwrap();
+    bob_manager.add_channel(bob_end, alice).await.unwrap();
+
+    alice_manager.send(&bob, greeting("from the wire")).await.unwrap();
+    bob_manager.add_to_queue(bob, greeting("from myself")).await;
+
+    let (_, first) = bob_manager.pop_queue().await.unwrap();
+    let (_, second) = bob_manager.pop_queue().await.unwrap();
+    assert_eq!(first.description(), "from myself");
+    assert_eq!(second.description(), "from the wire");
+}
+
+#[tokio::test]
+async fn close_channel_removes_the_participant() {
+    let (alice, bob) = (client(), client());
+    let mut alice_manager = manager(alice).await;
+
+    let (alice_end, _bob_end) = LoopbackChannel::pair();
+    alice_manager.add_channel(alice_end, bob).await.unwrap();
+
+    let (channel, participant) = alice_manager.open_channels().await.remove(0);
+    assert_eq!(participant, bob);
+
+    alice_manager
+        .send_receive_messages(InternalMessage::CloseChannel(channel))
+        .await;
+
+    assert!(alice_manager.open_channels().await.is_empty());
+    assert_eq!(
+        alice_manager.send(&bob, greeting("too late")).await,
+        Err(CommunicationErrors::UnknownParticipant)
+    );
+}
+
+#[tokio::test]
+async fn register_process_message_keeps_registration_order() {
+    let mut alice_manager = manager(client()).await;
+    let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+
+    alice_manager
+        .register_process_message(first, "Greeting".to_string())
+        .await;
+    alice_manager
+        .register_process_message(second, "Greeting".to_string())
+        .await;
+
+    assert_eq!(alice_manager.processes_waiting_for("Greeting"), &[first, second]);
+    assert!(alice_manager.processes_waiting_for("Farewell").is_empty());
+}
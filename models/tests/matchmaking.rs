@@ -0,0 +1,204 @@
+use models::{
+    Client, Command, Effect, Handshake, Inbound, Matchmaker, PingTime, PresenceEvent, RandomPairs,
+    ServerState, MATCH_TIMEOUT_ROUNDS,
+};
+use pairing::FriendSpace;
+use uuid::Uuid;
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+/// Clients that are online and done with their handshake, sorted
+fn join(state: &mut ServerState, clients: usize) -> Vec<Uuid> {
+    let mut uuids: Vec<Uuid> = (0..clients).map(|_| Uuid::new_v4()).collect();
+    uuids.sort();
+    for uuid in &uuids {
+        state.handle(Inbound::from_client(*uuid, Command::ServerInitiated(Client::from_user_id(*uuid))));
+        state.handle(Inbound::from_client(*uuid, Command::Handshake(Handshake::current())));
+    }
+    uuids
+}
+
+fn matches(effects: &[Effect]) -> Vec<(Uuid, Uuid, Uuid)> {
+    effects
+        .iter()
+        .filter_map(|effect| match effect {
+            Effect::Send {
+                to,
+                command: Command::Matched(initiator, receiver),
+            } => Some((*to, *initiator, *receiver)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Hands out the same pairs every round, whatever they are
+struct Fixed(Vec<(Uuid, Uuid)>);
+
+impl Matchmaker for Fixed {
+    fn pair(&mut self, _round: u64, _waiting: &[Uuid]) -> Vec<(Uuid, Uuid)> {
+        self.0.clone()
+    }
+}
+
+/// Pairs nobody, but remembers every rating it is told about
+struct Ratings(Arc<Mutex<Vec<(Uuid, Uuid, i32)>>>);
+
+impl Matchmaker for Ratings {
+    fn pair(&mut self, _round: u64, _waiting: &[Uuid]) -> Vec<(Uuid, Uuid)> {
+        vec![]
+    }
+
+    fn rated(&mut self, client: Uuid, partner: Uuid, rating: i32) {
+        self.0.lock().unwrap().push((client, partner, rating));
+    }
+}
+
+/// Pairs nobody, but remembers who left
+struct Departures(Arc<Mutex<Vec<Uuid>>>);
+
+impl Matchmaker for Departures {
+    fn pair(&mut self, _round: u64, _waiting: &[Uuid]) -> Vec<(Uuid, Uuid)> {
+        vec![]
+    }
+
+    fn left(&mut self, client: Uuid) {
+        self.0.lock().unwrap().push(client);
+    }
+}
+
+#[test]
+fn random_pairs_are_reproducible_and_leave_the_odd_one_out() {
+    let waiting: Vec<Uuid> = (0..5).map(|_| Uuid::new_v4()).collect();
+
+    let pairs = RandomPairs::seeded(7).pair(0, &waiting);
+    assert_eq!(RandomPairs::seeded(7).pair(0, &waiting), pairs);
+    assert_eq!(pairs.len(), 2);
+
+    let paired: BTreeSet<Uuid> = pairs.iter().flat_map(|(a, b)| vec![*a, *b]).collect();
+    assert_eq!(paired.len(), 4);
+    assert!(paired.iter().all(|uuid| waiting.contains(uuid)));
+}
+
+#[test]
+fn both_sides_learn_who_sends_the_offer() {
+    let mut state = ServerState::new(PingTime::Never, 2, 50);
+    let clients = join(&mut state, 3);
+    assert_eq!(matches(&state.tick(1)), vec![]);
+
+    state.matchmake_with(Box::new(RandomPairs::seeded(1)));
+    // Idle clients sit it out
+    state.handle(Inbound::from_client(clients[2], Command::UpdatePresence(PresenceEvent::WentIdle)));
+
+    let matched = matches(&state.tick(2));
+    let (initiator, receiver) = match matched.first() {
+        Some((_, initiator, receiver)) => (*initiator, *receiver),
+        None => panic!("nobody was matched"),
+    };
+    assert_eq!(
+        [initiator, receiver].iter().copied().collect::<BTreeSet<_>>(),
+        clients[..2].iter().copied().collect()
+    );
+    assert_eq!(
+        matched,
+        vec![(initiator, initiator, receiver), (receiver, initiator, receiver)]
+    );
+
+    // The offer is what puts them in the call, after which they aren't matched anymore
+    state.handle(Inbound::relay(initiator, receiver, Command::SdpRequest("offer".to_string())));
+    assert_eq!(matches(&state.tick(3)), vec![]);
+}
+
+#[test]
+fn matched_pairs_sit_out_until_their_match_times_out() {
+    let mut state = ServerState::new(PingTime::Never, 2, 50);
+    let clients = join(&mut state, 4);
+    state.matchmake_with(Box::new(RandomPairs::seeded(3)));
+
+    assert_eq!(matches(&state.tick(1)).len(), 4);
+    // Shuffling everyone again would send a second partner before the first offer arrived
+    assert_eq!(matches(&state.tick(2)), vec![]);
+    assert_eq!(matches(&state.tick(MATCH_TIMEOUT_ROUNDS)), vec![]);
+
+    // Nobody sent an offer in time, so they are all up for matching again
+    let rematched = matches(&state.tick(1 + MATCH_TIMEOUT_ROUNDS));
+    assert_eq!(rematched.len(), 4);
+    assert!(rematched.iter().all(|(to, _, _)| clients.contains(to)));
+}
+
+#[test]
+fn pairs_with_clients_that_are_not_waiting_are_ignored() {
+    let mut state = ServerState::new(PingTime::Never, 2, 50);
+    let clients = join(&mut state, 3);
+
+    // Never shook hands, so it can't be told about a match
+    let old_client = Uuid::new_v4();
+    state.handle(Inbound::from_client(old_client, Command::ServerInitiated(Client::from_user_id(old_client))));
+
+    state.matchmake_with(Box::new(Fixed(vec![
+        (clients[0], clients[0]),
+        (clients[0], old_client),
+        (clients[0], Uuid::new_v4()),
+        (clients[1], clients[0]),
+        (clients[0], clients[2]),
+    ])));
+
+    assert_eq!(
+        matches(&state.tick(1)),
+        vec![
+            (clients[1], clients[1], clients[0]),
+            (clients[0], clients[1], clients[0]),
+        ]
+    );
+}
+
+#[test]
+fn friend_space_doesnt_match_the_same_pair_twice_in_a_row() {
+    let mut state = ServerState::new(PingTime::Never, 2, 50);
+    join(&mut state, 4);
+    state.matchmake_with(Box::new(FriendSpace::seeded(5)));
+
+    let pairs = |effects: Vec<Effect>| -> BTreeSet<(Uuid, Uuid)> {
+        matches(&effects)
+            .into_iter()
+            .map(|(_, initiator, receiver)| (initiator.min(receiver), initiator.max(receiver)))
+            .collect()
+    };
+
+    // Nobody sends their offer, so everyone is waiting again once the matches timed out
+    let first = pairs(state.tick(1));
+    let second = pairs(state.tick(1 + MATCH_TIMEOUT_ROUNDS));
+    assert_eq!((first.len(), second.len()), (2, 2));
+    assert!(first.is_disjoint(&second));
+}
+
+#[test]
+fn ratings_after_a_call_reach_the_matchmaker_once() {
+    let mut state = ServerState::new(PingTime::Never, 2, 50);
+    let clients = join(&mut state, 3);
+    let (alice, bob, carol) = (clients[0], clients[1], clients[2]);
+    let ratings = Arc::new(Mutex::new(vec![]));
+    state.matchmake_with(Box::new(Ratings(ratings.clone())));
+
+    // Still waiting for a partner, so there is nobody to rate
+    state.handle(Inbound::from_client(carol, Command::RateLastPartner(5)));
+
+    state.handle(Inbound::relay(alice, bob, Command::SdpRequest("offer".to_string())));
+    state.handle(Inbound::from_client(alice, Command::EndCall(alice, bob)));
+    state.handle(Inbound::from_client(alice, Command::RateLastPartner(42)));
+    state.handle(Inbound::from_client(alice, Command::RateLastPartner(3)));
+    state.handle(Inbound::from_client(bob, Command::RateLastPartner(-2)));
+
+    assert_eq!(*ratings.lock().unwrap(), vec![(alice, bob, 10), (bob, alice, -2)]);
+}
+
+#[test]
+fn the_matchmaker_forgets_clients_that_go_offline() {
+    let mut state = ServerState::new(PingTime::Never, 2, 50);
+    let clients = join(&mut state, 2);
+    let departures = Arc::new(Mutex::new(vec![]));
+    state.matchmake_with(Box::new(Departures(departures.clone())));
+
+    state.handle(Inbound::from_client(clients[1], Command::ClosedConnection(clients[1])));
+    assert_eq!(*departures.lock().unwrap(), vec![clients[1]]);
+}
@@ -0,0 +1,81 @@
+#![cfg(feature = "native")]
+
+mod common;
+
+use common::client;
+use models::native_websocket::NativeWebSocketChannel;
+use models::{
+    Codec, CommunicationChannel, CommunicationErrors, Message, RawMessage,
+};
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+fn note(text: &str) -> RawMessage {
+    RawMessage {
+        identity: Uuid::new_v4(),
+        name: "Note".to_string(),
+        description: "a note".to_string(),
+        data: text.as_bytes().to_vec(),
+        correlation: None,
+    }
+}
+
+/// A server accepting a single websocket on localhost, and a client connected to it
+async fn connected(codec: Codec) -> (NativeWebSocketChannel, NativeWebSocketChannel) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+
+    let accepting = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let stream = tokio_tungstenite::accept_async(stream).await.unwrap();
+        NativeWebSocketChannel::from_stream(stream, codec)
+    });
+
+    let client = NativeWebSocketChannel::connect(&url, codec).await.unwrap();
+    (accepting.await.unwrap(), client)
+}
+
+#[tokio::test]
+async fn messages_cross_the_socket_in_every_codec() {
+    for codec in Codec::all().iter() {
+        let (server, client_channel) = connected(*codec).await;
+        let (alice, bob) = (client(), client());
+
+        client_channel.send(alice, bob, Box::new(note("hi"))).await.unwrap();
+        server.send(bob, alice, Box::new(note("hello"))).await.unwrap();
+
+        let received = server.receive().await.unwrap();
+        assert_eq!(received.name(), "Note");
+        assert_eq!(received.data(), Some(b"hi".to_vec()), "{:?}", codec);
+        assert_eq!(client_channel.receive().await.unwrap().data(), Some(b"hello".to_vec()));
+    }
+}
+
+#[tokio::test]
+async fn closing_ends_the_other_side_and_empty_messages_are_refused() {
+    struct Empty;
+    impl Message for Empty {
+        fn identity(&self) -> Uuid {
+            Uuid::nil()
+        }
+        fn name(&self) -> String {
+            "Empty".to_string()
+        }
+        fn description(&self) -> String {
+            String::new()
+        }
+        fn data(&self) -> Option<Vec<u8>> {
+            None
+        }
+    }
+
+    let (server, client_channel) = connected(Codec::Bincode).await;
+
+    assert!(matches!(
+        client_channel.send(client(), client(), Box::new(Empty)).await,
+        Err(CommunicationErrors::Unencodable(_))
+    ));
+
+    server.close();
+    assert!(client_channel.receive().await.is_none());
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::{client, server};
+use models::loopback::{LoopbackChannel, LoopbackManager};
+use models::{
+    CommunicationErrors, CommunicationManager, CommunicationPolicy, Entity,
+    EntityTypes, RawMessage,
+};
+use uuid::Uuid;
+
+fn note(text: &str) -> Box<RawMessage> {
+    Box::new(RawMessage {
+        identity: Uuid::new_v4(),
+        name: "Note".to_string(),
+        description: text.to_string(),
+        data: text.as_bytes().to_vec(),
+        correlation: None,
+    })
+}
+
+async fn client_manager(identity: Entity) -> LoopbackManager {
+    LoopbackManager::new(identity, vec![(EntityTypes::Server, EntityTypes::Client)]).await
+}
+
+#[test]
+fn type_pairs_are_unordered() {
+    let policy = CommunicationPolicy::new(&[(EntityTypes::Server, EntityTypes::Client)]);
+
+    assert!(policy.allows(&client(), &server()));
+    assert!(policy.allows(&server(), &client()));
+    assert!(!policy.allows(&client(), &client()));
+    assert_eq!(policy.allowed(), vec![(EntityTypes::Client, EntityTypes::Server)]);
+}
+
+#[tokio::test]
+async fn disallowed_channels_and_sends_are_refused_and_counted() {
+    let (alice, bob) = (client(), client());
+    let mut alice_manager = client_manager(alice).await;
+
+    let (alice_end, _bob_end) = LoopbackChannel::pair();
+    assert_eq!(
+        alice_manager.add_channel(alice_end, bob).await,
+        Err(CommunicationErrors::DisallowedEntityType)
+    );
+    assert!(alice_manager.open_channels().await.is_empty());
+
+    assert_eq!(
+        alice_manager.send(&bob, note("hi")).await,
+        Err(CommunicationErrors::DisallowedEntityType)
+    );
+
+    let policy = alice_manager.policy();
+    assert_eq!(policy.violations(), 2);
+    assert_eq!(policy.violations_between(EntityTypes::Client, EntityTypes::Client), 2);
+    assert_eq!(policy.violations_between(EntityTypes::Client, EntityTypes::Server), 0);
+}
+
+#[tokio::test]
+async fn paired_clients_may_talk_until_revoked() {
+    let (alice, bob) = (client(), client());
+    let mut alice_manager = client_manager(alice).await;
+    let mut bob_manager = client_manager(bob).await;
+
+    alice_manager.policy_mut().permit(alice.uuid(), bob.uuid());
+    bob_manager.policy_mut().permit(bob.uuid(), alice.uuid());
+
+    let (alice_end, bob_end) = LoopbackChannel::pair();
+    alice_manager.add_channel(alice_end, bob).await.unwrap();
+    bob_manager.add_channel(bob_end, alice).await.unwrap();
+
+    alice_manager.send(&bob, note("hi bob")).await.unwrap();
+    let (sender, message) = bob_manager.pop_queue().await.unwrap();
+    assert_eq!(sender, alice);
+    assert_eq!(message.description(), "hi bob");
+
+    assert!(alice_manager.policy_mut().revoke(bob.uuid(), alice.uuid()));
+    assert_eq!(
+        alice_manager.send(&bob, note("still there?")).await,
+        Err(CommunicationErrors::DisallowedEntityType)
+    );
+    assert_eq!(alice_manager.policy().violations(), 1);
+}
@@ -0,0 +1,83 @@
+use models::{allowed, transition, IllegalTransition, PresenceEvent, Status};
+use uuid::Uuid;
+
+#[test]
+fn a_whole_call_goes_back_to_waiting() {
+    let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+
+    let events = vec![
+        PresenceEvent::Connected,
+        PresenceEvent::CallStarted(alice, bob),
+        PresenceEvent::CallEnded,
+        PresenceEvent::AnsweredQuestions,
+        PresenceEvent::WentIdle,
+        PresenceEvent::CameBack,
+        PresenceEvent::Disconnected,
+    ];
+
+    let mut status = None;
+    let mut visited = vec![];
+    for event in &events {
+        status = transition(&status, event).unwrap();
+        visited.push(status.clone());
+    }
+
+    assert_eq!(
+        visited,
+        vec![
+            Some(Status::WaitingForPartner),
+            Some(Status::InCall(alice, bob)),
+            Some(Status::AnsweringQuestionAboutLastPartner),
+            Some(Status::WaitingForPartner),
+            Some(Status::Idle),
+            Some(Status::WaitingForPartner),
+            None,
+        ]
+    );
+}
+
+#[test]
+fn illegal_transitions_are_rejected() {
+    let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+    let in_call = Some(Status::InCall(alice, bob));
+    let carol_calls = PresenceEvent::CallStarted(Uuid::new_v4(), alice);
+
+    assert_eq!(
+        transition(&in_call, &carol_calls),
+        Err(IllegalTransition {
+            from: in_call.clone(),
+            event: carol_calls,
+        })
+    );
+
+    assert!(!allowed(&None, &PresenceEvent::CallEnded));
+    assert!(!allowed(&Some(Status::Idle), &PresenceEvent::CallStarted(alice, bob)));
+    assert!(!allowed(&Some(Status::WaitingForPartner), &PresenceEvent::CallStarted(alice, alice)));
+    assert!(!allowed(&Some(Status::WaitingForPartner), &PresenceEvent::Connected));
+    assert!(allowed(&in_call, &PresenceEvent::Disconnected));
+}
+
+#[test]
+fn clients_only_report_what_the_server_can_not_see() {
+    let reported: Vec<PresenceEvent> = vec![
+        PresenceEvent::Connected,
+        PresenceEvent::CallStarted(Uuid::nil(), Uuid::nil()),
+        PresenceEvent::CallEnded,
+        PresenceEvent::AnsweredQuestions,
+        PresenceEvent::WentIdle,
+        PresenceEvent::CameBack,
+        PresenceEvent::Disconnected,
+    ]
+    .into_iter()
+    .filter(PresenceEvent::is_reported_by_client)
+    .collect();
+
+    assert_eq!(
+        reported,
+        vec![
+            PresenceEvent::AnsweredQuestions,
+            PresenceEvent::WentIdle,
+            PresenceEvent::CameBack
+        ]
+    );
+}
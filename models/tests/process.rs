@@ -0,0 +1,174 @@
+mod common;
+
+use common::{client, server, Step};
+use async_trait::async_trait;
+use models::{
+    DeclarativeProcess, Entities, EntityTypes, InternalMessage,
+    InternalSystemComponents, JournalSink, Message, Process, ProcessErrors, ProcessStatus,
+    ProcessStep,
+};
+
+use std::sync::{Arc, Mutex};
+
+fn step(name: &'static str) -> Box<dyn Message> {
+    Box::new(Step(name))
+}
+
+/// Client offers, server relays, client answers.
+fn handshake(blocking: bool, looping: bool) -> DeclarativeProcess {
+    DeclarativeProcess::new(
+        vec![Entities::Exactly(2, EntityTypes::Client), Entities::One(EntityTypes::Server)],
+        vec![
+            (EntityTypes::Client, step("SdpRequest")),
+            (EntityTypes::Server, step("Relay")),
+            (EntityTypes::Client, step("SdpResponse")),
+        ],
+        "handshake".to_string(),
+        "sets up a call between two clients".to_string(),
+        blocking,
+        looping,
+    )
+}
+
+#[tokio::test]
+async fn blocking_process_walks_its_steps_in_order() {
+    let mut process = handshake(true, false);
+    let (caller, relay) = (client(), server());
+
+    assert_eq!(
+        process.receive_message(step("SdpRequest"), caller).await,
+        Err(ProcessErrors::NotStarted)
+    );
+
+    process.start().await;
+    assert_eq!(process.focus(), Some(0));
+    assert_eq!(process.waiting_for_message_type(), Some("SdpRequest".to_string()));
+
+    assert_eq!(
+        process.send_message(step("SdpRequest"), caller).await,
+        Ok(ProcessStatus::Sent)
+    );
+    assert_eq!(
+        process.receive_message(step("SdpResponse"), caller).await,
+        Err(ProcessErrors::UnexpectedMessage {
+            expected: "Relay".to_string(),
+            received: "SdpResponse".to_string()
+        })
+    );
+    assert_eq!(
+        process.receive_message(step("Relay"), caller).await,
+        Err(ProcessErrors::WrongParty {
+            expected: EntityTypes::Server,
+            posted_by: EntityTypes::Client
+        })
+    );
+
+    let (party, focused) = process.focus_step().unwrap();
+    assert_eq!((party, focused.name()), (&EntityTypes::Server, "Relay".to_string()));
+
+    assert_eq!(
+        process.receive_message(step("Relay"), relay).await,
+        Ok(ProcessStatus::Received)
+    );
+    assert_eq!(
+        process.receive_message(step("SdpResponse"), client()).await,
+        Ok(ProcessStatus::Finished)
+    );
+    assert_eq!(process.status(), ProcessStatus::Finished);
+    assert_eq!(process.focus(), None);
+    assert_eq!(
+        process.receive_message(step("SdpRequest"), caller).await,
+        Err(ProcessErrors::Finished)
+    );
+
+    let journal: Vec<(usize, String, ProcessStatus)> = process
+        .journal()
+        .iter()
+        .map(|entry| (entry.index, entry.message_name.clone(), entry.status))
+        .collect();
+    assert_eq!(
+        journal,
+        vec![
+            (0, "SdpRequest".to_string(), ProcessStatus::Sent),
+            (1, "Relay".to_string(), ProcessStatus::Received),
+            (2, "SdpResponse".to_string(), ProcessStatus::Received),
+        ]
+    );
+    assert_eq!(process.journal()[1].posted_by, relay);
+    assert_eq!(process.take_outgoing().len(), 1);
+}
+
+#[tokio::test]
+async fn non_blocking_process_skips_ahead_and_ignores_strangers() {
+    let mut process = handshake(false, false);
+    process.start_timed().await;
+
+    assert_eq!(
+        process.receive_message(step("Farewell"), client()).await,
+        Ok(ProcessStatus::Waiting)
+    );
+    assert_eq!(process.focus(), Some(0));
+
+    assert_eq!(
+        process.receive_message(step("Relay"), server()).await,
+        Ok(ProcessStatus::Received)
+    );
+    assert_eq!(process.focus(), Some(2));
+    assert_eq!(process.journal()[0].index, 1);
+    assert!(process.journal()[0].elapsed_ms.is_some());
+}
+
+#[tokio::test]
+async fn looping_process_starts_over() {
+    let mut process = handshake(true, true);
+    process
+        .send_receive_messages(InternalMessage::StartProcess(process.get_uuid()))
+        .await;
+
+    for _ in 0..2 {
+        process.receive_message(step("SdpRequest"), client()).await.unwrap();
+        process.receive_message(step("Relay"), server()).await.unwrap();
+        assert_eq!(
+            process.receive_message(step("SdpResponse"), client()).await,
+            Ok(ProcessStatus::Received)
+        );
+    }
+
+    assert_eq!(process.focus(), Some(0));
+    assert_eq!(process.iteration(), 2);
+    assert_eq!(process.status(), ProcessStatus::Running);
+    assert_eq!(process.journal().last().unwrap().iteration, 1);
+
+    process
+        .send_receive_messages(InternalMessage::AdvanceProcess(process.get_uuid()))
+        .await;
+    assert_eq!(process.waiting_for_message_type(), Some("Relay".to_string()));
+}
+
+#[derive(Default)]
+struct Recorded(Mutex<Vec<ProcessStep>>);
+
+#[async_trait]
+impl JournalSink for Recorded {
+    async fn record(&self, step: &ProcessStep) {
+        self.0.lock().unwrap().push(step.clone());
+    }
+}
+
+#[tokio::test]
+async fn logged_steps_are_recorded_by_the_sink() {
+    let mut process = handshake(true, false);
+    let recorded = Arc::new(Recorded::default());
+    process.journal_to(recorded.clone());
+    process.start().await;
+
+    process.send_message(step("SdpRequest"), client()).await.unwrap();
+    process.receive_message(step("Relay"), server()).await.unwrap();
+    // Refused steps aren't logged
+    assert!(process.receive_message(step("Relay"), server()).await.is_err());
+
+    let recorded = recorded.0.lock().unwrap();
+    assert_eq!(recorded.as_slice(), process.journal());
+    assert_eq!(recorded.len(), 2);
+    assert!(recorded.iter().all(|entry| entry.process == process.get_uuid()));
+}
@@ -0,0 +1,123 @@
+mod common;
+
+use common::{client, server, Step};
+use models::{
+    check_parties, DeclarativeProcess, Entities, Entity, EntityTypes,
+    InternalMessage, InternalSystemComponents, LocalProcessManager, Message, NetworkTopology,
+    PartyMismatch, Process, ProcessManager, ProcessManagerErrors,
+};
+use uuid::Uuid;
+
+/// A server connected to every one of the clients
+fn star(server: Entity, clients: &[Entity]) -> NetworkTopology {
+    let mut topology = NetworkTopology::new();
+    topology.add_node(server);
+    for client in clients {
+        topology.add_edge(server, *client, (server, *client));
+    }
+    topology
+}
+
+fn call(involved_parties: Vec<Entities>) -> DeclarativeProcess {
+    DeclarativeProcess::new(
+        involved_parties,
+        vec![(EntityTypes::Client, Box::new(Step("SdpRequest")) as Box<dyn Message>)],
+        "call".to_string(),
+        String::new(),
+        true,
+        false,
+    )
+}
+
+#[test]
+fn check_parties_lists_missing_and_surplus_entities() {
+    let relay = server();
+    let mut clients = [client(), client(), client()];
+    clients.sort_by_key(Entity::uuid);
+    // Adding them in reverse shows that the surplus doesn't depend on the order they came in
+    let mut added = clients;
+    added.reverse();
+    let topology = star(relay, &added);
+
+    assert_eq!(
+        check_parties(
+            &[Entities::AtLeast(2, EntityTypes::Client), Entities::One(EntityTypes::Server)],
+            &topology
+        ),
+        Ok(())
+    );
+
+    assert_eq!(
+        check_parties(
+            &[
+                Entities::Exactly(2, EntityTypes::Client),
+                Entities::AtLeast(2, EntityTypes::Server),
+                Entities::UpTo(3, EntityTypes::Client),
+            ],
+            &topology
+        ),
+        Err(vec![
+            PartyMismatch::Surplus {
+                required: Entities::Exactly(2, EntityTypes::Client),
+                surplus: vec![clients[2]],
+            },
+            PartyMismatch::Missing {
+                required: Entities::AtLeast(2, EntityTypes::Server),
+                available: 1,
+                missing: 1,
+            },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn processes_only_start_when_the_topology_has_their_parties() {
+    let relay = server();
+    let mut manager = LocalProcessManager::initialize(relay).await;
+    let process = manager.register_functionality(call(vec![
+        Entities::Exactly(2, EntityTypes::Client),
+        Entities::One(EntityTypes::Server),
+    ]));
+
+    assert_eq!(
+        manager.start_process(process).await,
+        Err(ProcessManagerErrors::PartiesUnavailable {
+            process,
+            mismatches: vec![PartyMismatch::Missing {
+                required: Entities::Exactly(2, EntityTypes::Client),
+                available: 0,
+                missing: 2,
+            }],
+        })
+    );
+    assert_eq!(manager.process(&process).unwrap().waiting_for_message_type(), None);
+
+    manager.update_topology(star(relay, &[client(), client()]));
+    manager.start_process(process).await.unwrap();
+    assert_eq!(
+        manager.process(&process).unwrap().waiting_for_message_type(),
+        Some("SdpRequest".to_string())
+    );
+
+    let unknown = Uuid::new_v4();
+    assert_eq!(
+        manager.start_process(unknown).await,
+        Err(ProcessManagerErrors::UnknownProcess(unknown))
+    );
+}
+
+#[tokio::test]
+async fn refused_start_messages_are_kept() {
+    let mut manager = LocalProcessManager::initialize(client()).await;
+    let process = manager.register_functionality(call(vec![Entities::Exactly(2, EntityTypes::Client)]));
+
+    manager
+        .send_receive_messages(InternalMessage::StartProcess(process))
+        .await;
+
+    assert_eq!(manager.refused().len(), 1);
+    assert!(matches!(
+        &manager.refused()[0],
+        ProcessManagerErrors::PartiesUnavailable { mismatches, .. } if mismatches.len() == 1
+    ));
+}
@@ -0,0 +1,148 @@
+use models::{
+    Capability, Client, Codec, Command, Entity, EntityDetails, Envelope, Handshake, IllegalTransition,
+    PresenceEvent, ProtocolError, ProtocolMismatch, CODED_ERRORS_VERSION, MIN_PROTOCOL_VERSION,
+    PROTOCOL_VERSION,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use std::collections::BTreeSet;
+
+fn handshake(min_version: u32, version: u32, capabilities: &[Capability]) -> Handshake {
+    Handshake {
+        version,
+        min_version,
+        capabilities: capabilities.iter().copied().collect(),
+    }
+}
+
+#[test]
+fn matching_builds_agree_on_everything() {
+    let negotiated = Handshake::current().negotiate(&Handshake::current()).unwrap();
+
+    assert_eq!(negotiated.version, PROTOCOL_VERSION);
+    assert_eq!(negotiated.capabilities, Capability::all());
+    assert!(!negotiated.downgraded);
+}
+
+#[test]
+fn older_clients_are_downgraded_to_what_they_speak() {
+    let server = handshake(1, 3, &[Capability::KeepAlive, Capability::SdpRelay]);
+    let client = handshake(1, 2, &[Capability::SdpRelay, Capability::IceRelay]);
+
+    let negotiated = server.negotiate(&client).unwrap();
+    assert_eq!(negotiated.version, 2);
+    assert!(negotiated.downgraded);
+    assert_eq!(
+        negotiated.capabilities,
+        [Capability::SdpRelay].iter().copied().collect::<BTreeSet<_>>()
+    );
+}
+
+#[test]
+fn clients_outside_of_the_supported_range_are_rejected() {
+    let server = handshake(3, 4, &[]);
+
+    let stale_tab = server.negotiate(&handshake(1, 2, &[])).unwrap_err();
+    assert_eq!(
+        stale_tab,
+        ProtocolMismatch {
+            client_versions: (1, 2),
+            server_versions: (3, 4),
+        }
+    );
+    assert!(stale_tab.to_string().contains("reload"));
+
+    let too_new = server.negotiate(&handshake(5, 6, &[])).unwrap_err();
+    assert_eq!(too_new.client_versions, (5, 6));
+    assert!(too_new.to_string().contains("up to version 4"));
+}
+
+#[test]
+fn handshake_commands_survive_bincode() {
+    let command = Command::Handshake(Handshake::current());
+    let decoded: Command = bincode::deserialize(&bincode::serialize(&command).unwrap()).unwrap();
+
+    match decoded {
+        Command::Handshake(handshake) => assert_eq!(handshake, Handshake::current()),
+        other => panic!("decoded into {:?}", other),
+    }
+}
+
+#[test]
+fn error_codes_are_stable() {
+    let mismatch = ProtocolMismatch {
+        client_versions: (1, 1),
+        server_versions: (2, 2),
+    };
+    let errors = vec![
+        ProtocolError::UnknownRecipient(Uuid::nil()),
+        ProtocolError::NotInCall(Uuid::nil()),
+        ProtocolError::RateLimited { limit: 10 },
+        ProtocolError::VersionMismatch(mismatch.clone()),
+        ProtocolError::MalformedPayload("eof".to_string()),
+        ProtocolError::IllegalTransition(IllegalTransition {
+            from: None,
+            event: PresenceEvent::CallEnded,
+        }),
+        ProtocolError::Impersonation(Uuid::nil()),
+        ProtocolError::NotRelayable(Uuid::nil()),
+    ];
+
+    let codes: Vec<u16> = errors.iter().map(ProtocolError::code).collect();
+    assert_eq!(codes, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+
+    // The code travels as the bincode variant index, so both have to line up
+    for error in &errors {
+        let encoded = bincode::serialize(error).unwrap();
+        assert_eq!(u32::from_le_bytes([encoded[0], encoded[1], encoded[2], encoded[3]]), u32::from(error.code()) - 1);
+    }
+
+    assert!(ProtocolError::VersionMismatch(mismatch.clone()).is_fatal());
+    assert!(!ProtocolError::RateLimited { limit: 10 }.is_fatal());
+    assert_eq!(
+        ProtocolError::VersionMismatch(mismatch.clone()).to_string(),
+        format!("error 4: {}", mismatch)
+    );
+}
+
+/// The start of ```Command``` as clients from before the coded errors know it
+#[derive(Debug, Deserialize, PartialEq)]
+enum LegacyCommand {
+    BroadcastUpdate,
+    InCall(Uuid, Uuid),
+    EndCall(Uuid, Uuid),
+    UpdateClient(Client),
+    Error(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct LegacyEnvelope {
+    sender: Entity,
+    receiver: Entity,
+    intermediary: Option<Entity>,
+    command: LegacyCommand,
+}
+
+#[test]
+fn older_clients_get_errors_as_plain_strings() {
+    let server = EntityDetails::Server(Uuid::new_v4(), "127.0.0.1:2096".parse().unwrap());
+    let client = EntityDetails::Client(Uuid::new_v4(), None);
+    let error = ProtocolError::RateLimited { limit: 50 };
+    let envelope = Envelope::new(server, client, None, Command::Error(error.clone()));
+
+    for codec in Codec::all().iter() {
+        for version in MIN_PROTOCOL_VERSION..CODED_ERRORS_VERSION {
+            let legacy: LegacyEnvelope = codec.decode(&envelope.encode_for(*codec, version).unwrap()).unwrap();
+            assert_eq!(legacy.command, LegacyCommand::Error(error.message()));
+            assert_eq!((legacy.sender, legacy.receiver, legacy.intermediary), (envelope.sender, envelope.receiver, None));
+        }
+
+        let current: Envelope = codec.decode(&envelope.encode_for(*codec, PROTOCOL_VERSION).unwrap()).unwrap();
+        assert_eq!(current, envelope);
+
+        // Everything but errors looks the same to every version
+        let greeting = Envelope::new(server, client, None, Command::ServerInitiated(Client::from_user_id(Uuid::nil())));
+        assert_eq!(greeting.encode_for(*codec, MIN_PROTOCOL_VERSION).unwrap(), codec.encode(&greeting).unwrap());
+    }
+}
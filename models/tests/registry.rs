@@ -0,0 +1,125 @@
+use models::{Message, MessageErrors, MessageRegistry, RawMessage, TypedMessage};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+struct SdpOffer {
+    identity: Uuid,
+    sdp: String,
+}
+
+impl Message for SdpOffer {
+    fn identity(&self) -> Uuid {
+        self.identity
+    }
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+    fn description(&self) -> String {
+        "An sdp offer made by the initiator of a call".to_string()
+    }
+    fn data(&self) -> Option<Vec<u8>> {
+        self.encode()
+    }
+}
+
+impl TypedMessage for SdpOffer {
+    const NAME: &'static str = "SdpOffer";
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+struct Rating {
+    identity: Uuid,
+    partner: Uuid,
+    score: i32,
+}
+
+impl Message for Rating {
+    fn identity(&self) -> Uuid {
+        self.identity
+    }
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+    fn description(&self) -> String {
+        "How the last call went".to_string()
+    }
+    fn data(&self) -> Option<Vec<u8>> {
+        self.encode()
+    }
+}
+
+impl TypedMessage for Rating {
+    const NAME: &'static str = "Rating";
+}
+
+fn registry() -> MessageRegistry {
+    let mut registry = MessageRegistry::new();
+    registry.register::<SdpOffer>().register::<Rating>();
+    registry
+}
+
+fn offer() -> SdpOffer {
+    SdpOffer {
+        identity: Uuid::new_v4(),
+        sdp: "v=0".to_string(),
+    }
+}
+
+#[test]
+fn raw_messages_decode_back_into_their_type() {
+    let offer = offer();
+    let raw = RawMessage::from_message(&offer).unwrap();
+    assert_eq!(raw.name, "SdpOffer");
+    assert_eq!(raw.identity, offer.identity);
+
+    let decoded = registry().decode_raw(&raw).unwrap();
+    assert!(decoded.is::<SdpOffer>());
+    assert_eq!(decoded.downcast_ref::<SdpOffer>(), Some(&offer));
+    assert_eq!(registry().decode_as::<SdpOffer>(&raw), Ok(offer));
+}
+
+#[test]
+fn resolve_only_decodes_raw_messages() {
+    let rating = Rating {
+        identity: Uuid::new_v4(),
+        partner: Uuid::new_v4(),
+        score: 7,
+    };
+    let raw: Box<dyn Message> = Box::new(RawMessage::from_message(&rating).unwrap());
+
+    let resolved = registry().resolve(raw).unwrap();
+    assert_eq!(*resolved.downcast::<Rating>().ok().unwrap(), rating);
+
+    let untouched = registry().resolve(Box::new(offer())).unwrap();
+    assert!(untouched.is::<SdpOffer>());
+
+    // Typed messages only pass when their type is the one registered under their name
+    let mut offers_only = MessageRegistry::new();
+    offers_only.register::<SdpOffer>();
+    assert_eq!(
+        offers_only.resolve(Box::new(rating)).err(),
+        Some(MessageErrors::UnknownMessage("Rating".to_string()))
+    );
+}
+
+#[test]
+fn decoding_errors_name_the_message() {
+    let mut raw = RawMessage::from_message(&offer()).unwrap();
+
+    raw.name = "Farewell".to_string();
+    assert_eq!(
+        registry().decode_raw(&raw).err(),
+        Some(MessageErrors::UnknownMessage("Farewell".to_string()))
+    );
+
+    raw.name = "Rating".to_string();
+    raw.data = vec![1];
+    match registry().decode_raw(&raw).err() {
+        Some(MessageErrors::MalformedPayload { name, .. }) => assert_eq!(name, "Rating"),
+        other => panic!("expected a malformed payload, got {:?}", other),
+    }
+
+    assert!(registry().decode_as::<SdpOffer>(&raw).is_err());
+    assert_eq!(registry().names(), vec!["Rating".to_string(), "SdpOffer".to_string()]);
+}
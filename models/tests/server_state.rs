@@ -0,0 +1,345 @@
+use models::{
+    Client, Command, Effect, Handshake, IllegalTransition, Inbound, PingStatus, PingTime,
+    PresenceEvent, ProtocolError, ServerState, Status,
+};
+use uuid::Uuid;
+
+use std::collections::HashMap;
+
+fn online_clients(state: &ServerState) -> Command {
+    let clients = state
+        .online()
+        .iter()
+        .map(|(uuid, online)| (*uuid, online.client.clone()))
+        .collect();
+    Command::OnlineClients(clients, state.round() as u32)
+}
+
+fn broadcast(state: &ServerState) -> Effect {
+    Effect::Broadcast {
+        to: state.online().keys().copied().collect(),
+        command: online_clients(state),
+    }
+}
+
+/// A server with the clients online, every one of them waiting for a partner
+fn server_with(clients: usize) -> (ServerState, Vec<Uuid>) {
+    let mut state = ServerState::new(PingTime::Every(2), 2, 5);
+    let mut uuids = vec![];
+    for _ in 0..clients {
+        let client = Client::from_user_id(Uuid::new_v4());
+        let effects = state.handle(Inbound::from_client(client.user_id, Command::ServerInitiated(client.clone())));
+        assert_eq!(
+            effects,
+            vec![Effect::Send {
+                to: client.user_id,
+                command: Command::ServerInitiated(client.clone()),
+            }]
+        );
+        uuids.push(client.user_id);
+    }
+    (state, uuids)
+}
+
+fn status(state: &ServerState, uuid: &Uuid) -> Option<Status> {
+    state.client(uuid).unwrap().status.clone()
+}
+
+#[test]
+fn broadcasts_and_client_updates_reach_everyone_online() {
+    let (mut state, clients) = server_with(3);
+    assert_eq!(state.handle(Inbound::from_server(Command::BroadcastUpdate)), vec![broadcast(&state)]);
+
+    let renamed = Client {
+        username: Some("alice".to_string()),
+        ..Client::from_user_id(clients[0])
+    };
+    let effects = state.handle(Inbound::from_client(clients[0], Command::UpdateClient(renamed.clone())));
+    assert_eq!(state.client(&clients[0]).unwrap().client, renamed);
+    assert_eq!(effects, vec![broadcast(&state)]);
+
+    // Updates for clients that aren't online change nothing
+    let stranger = Client::from_user_id(Uuid::new_v4());
+    assert_eq!(state.handle(Inbound::from_server(Command::UpdateClient(stranger))), vec![]);
+}
+
+#[test]
+fn relayed_offers_start_calls_and_relayed_hangups_end_them() {
+    let (mut state, clients) = server_with(3);
+    let (alice, bob, carol) = (clients[0], clients[1], clients[2]);
+
+    let offer = Inbound::relay(alice, bob, Command::SdpRequest("offer".to_string()));
+    let effects = state.handle(offer.clone());
+    assert_eq!(effects, vec![Effect::Relay { next_hop: bob, message: offer }, broadcast(&state)]);
+    assert_eq!(status(&state, &alice), Some(Status::InCall(alice, bob)));
+    assert_eq!(status(&state, &bob), Some(Status::InCall(alice, bob)));
+
+    let answer = Inbound::relay(bob, alice, Command::SdpResponse("answer".to_string()));
+    assert_eq!(state.handle(answer.clone()), vec![Effect::Relay { next_hop: alice, message: answer }]);
+
+    // Carol is in no call with alice
+    assert_eq!(
+        state.handle(Inbound::from_client(carol, Command::EndCall(carol, alice))),
+        vec![Effect::Send {
+            to: carol,
+            command: Command::Error(ProtocolError::NotInCall(alice)),
+        }]
+    );
+
+    let hangup = Inbound::relay(bob, alice, Command::EndCall(alice, bob));
+    let effects = state.handle(hangup.clone());
+    assert_eq!(effects, vec![Effect::Relay { next_hop: alice, message: hangup }, broadcast(&state)]);
+    for person in [alice, bob].iter() {
+        assert_eq!(status(&state, person), Some(Status::AnsweringQuestionAboutLastPartner));
+    }
+}
+
+#[test]
+fn calls_start_for_both_or_for_neither() {
+    let (mut state, clients) = server_with(3);
+    let (alice, bob, carol) = (clients[0], clients[1], clients[2]);
+
+    assert_eq!(state.handle(Inbound::from_server(Command::InCall(alice, bob))), vec![broadcast(&state)]);
+
+    let effects = state.handle(Inbound::from_server(Command::InCall(carol, bob)));
+    assert_eq!(
+        effects,
+        vec![Effect::Send {
+            to: carol,
+            command: Command::Error(ProtocolError::IllegalTransition(IllegalTransition {
+                from: Some(Status::InCall(alice, bob)),
+                event: PresenceEvent::CallStarted(carol, bob),
+            })),
+        }]
+    );
+    assert_eq!(status(&state, &carol), Some(Status::WaitingForPartner));
+
+    // Only the events the client knows about can be reported by it
+    assert_eq!(state.handle(Inbound::from_client(carol, Command::UpdatePresence(PresenceEvent::CallEnded))), vec![]);
+    let effects = state.handle(Inbound::from_client(carol, Command::UpdatePresence(PresenceEvent::WentIdle)));
+    assert_eq!(effects, vec![broadcast(&state)]);
+    assert_eq!(status(&state, &carol), Some(Status::Idle));
+}
+
+#[test]
+fn only_what_clients_say_to_each_other_is_relayed() {
+    let (mut state, clients) = server_with(3);
+    let (alice, bob, carol) = (clients[0], clients[1], clients[2]);
+    let not_relayable = |to| Effect::Send {
+        to: alice,
+        command: Command::Error(ProtocolError::NotRelayable(to)),
+    };
+
+    // Spread over two rounds, so that they all stay under the limit
+    let server_only = [
+        Command::Matched(alice, bob),
+        Command::Error(ProtocolError::NotInCall(alice)),
+        Command::HandshakeAccepted(Handshake::current().negotiate(&Handshake::current()).unwrap()),
+        Command::OnlineClients(HashMap::new(), 0),
+        Command::Ping(bob, 0),
+        Command::ServerInitiated(Client::from_user_id(bob)),
+    ];
+    for (round, commands) in server_only.chunks(3).enumerate() {
+        state.tick(round as u64);
+        for command in commands {
+            assert_eq!(state.handle(Inbound::relay(alice, bob, command.clone())), vec![not_relayable(bob)]);
+        }
+    }
+
+    // An offer to someone who can't take the call is refused before it reaches them
+    state.handle(Inbound::from_client(carol, Command::UpdatePresence(PresenceEvent::WentIdle)));
+    let effects = state.handle(Inbound::relay(alice, carol, Command::SdpRequest("offer".to_string())));
+    assert_eq!(
+        effects,
+        vec![Effect::Send {
+            to: alice,
+            command: Command::Error(ProtocolError::IllegalTransition(IllegalTransition {
+                from: Some(Status::Idle),
+                event: PresenceEvent::CallStarted(alice, carol),
+            })),
+        }]
+    );
+    assert_eq!(status(&state, &alice), Some(Status::WaitingForPartner));
+}
+
+#[test]
+fn relays_to_clients_that_are_gone_are_refused() {
+    let (mut state, clients) = server_with(2);
+    let (alice, bob) = (clients[0], clients[1]);
+
+    let effects = state.handle(Inbound::from_client(bob, Command::ClosedConnection(bob)));
+    assert_eq!(effects, vec![Effect::Close(bob), broadcast(&state)]);
+    assert!(state.client(&bob).is_none());
+    assert_eq!(state.topology().neighbours(&state.uuid()), vec![alice]);
+
+    // Closing twice is harmless
+    assert_eq!(state.handle(Inbound::from_client(bob, Command::ClosedConnection(bob))), vec![]);
+
+    let effects = state.handle(Inbound::relay(alice, bob, Command::IceCandidate("candidate".to_string())));
+    assert_eq!(
+        effects,
+        vec![Effect::Send {
+            to: alice,
+            command: Command::Error(ProtocolError::UnknownRecipient(bob)),
+        }]
+    );
+}
+
+#[test]
+fn unanswered_pings_remove_the_client() {
+    let (mut state, clients) = server_with(2);
+    let (alice, bob) = (clients[0], clients[1]);
+
+    let pings = |round| {
+        vec![
+            Effect::Send {
+                to: alice.min(bob),
+                command: Command::Ping(alice.min(bob), round),
+            },
+            Effect::Send {
+                to: alice.max(bob),
+                command: Command::Ping(alice.max(bob), round),
+            },
+        ]
+    };
+    assert_eq!(state.tick(0), pings(0));
+    assert_eq!(state.tick(1), vec![]);
+
+    assert_eq!(state.handle(Inbound::from_client(alice, Command::Pong(alice, 0))), vec![]);
+    assert_eq!(state.client(&alice).unwrap().ping_status, PingStatus::Ponged(0));
+    assert_eq!(state.tick(2), pings(2));
+
+    state.handle(Inbound::from_client(alice, Command::Pong(alice, 2)));
+    let effects = state.tick(4);
+    // The keep-alive goes through the clients by uuid, the broadcast comes after all of them
+    assert_eq!(effects.len(), 3);
+    assert!(effects.contains(&Effect::Send {
+        to: alice,
+        command: Command::Ping(alice, 4),
+    }));
+    assert!(effects.contains(&Effect::Close(bob)));
+    assert_eq!(effects[2], broadcast(&state));
+    assert_eq!(state.online().keys().collect::<Vec<_>>(), vec![&alice]);
+}
+
+#[test]
+fn handshakes_are_negotiated_and_mismatches_closed() {
+    let (mut state, clients) = server_with(2);
+    let (alice, bob) = (clients[0], clients[1]);
+
+    let effects = state.handle(Inbound::from_client(alice, Command::Handshake(Handshake::current())));
+    let negotiated = state.negotiated(&alice).cloned().unwrap();
+    assert_eq!(
+        effects,
+        vec![Effect::Send {
+            to: alice,
+            command: Command::HandshakeAccepted(negotiated),
+        }]
+    );
+
+    let future = Handshake {
+        version: 1000,
+        min_version: 1000,
+        ..Handshake::current()
+    };
+    let effects = state.handle(Inbound::from_client(bob, Command::Handshake(future.clone())));
+    let mismatch = Handshake::current().negotiate(&future).unwrap_err();
+    assert_eq!(
+        effects,
+        vec![
+            Effect::Send {
+                to: bob,
+                command: Command::Error(ProtocolError::VersionMismatch(mismatch)),
+            },
+            Effect::Close(bob),
+            broadcast(&state),
+        ]
+    );
+    assert!(state.client(&bob).is_none());
+}
+
+#[test]
+fn messages_over_the_limit_are_dropped_until_the_next_round() {
+    let (mut state, clients) = server_with(1);
+    let alice = clients[0];
+    let update = || Inbound::from_client(alice, Command::UpdateClient(Client::from_user_id(alice)));
+
+    let limited = Effect::Send {
+        to: alice,
+        command: Command::Error(ProtocolError::RateLimited { limit: 5 }),
+    };
+
+    // Joining counted as the first message
+    for _ in 0..4 {
+        assert_eq!(state.handle(update()), vec![broadcast(&state)]);
+    }
+    assert_eq!(state.handle(update()), vec![limited.clone()]);
+    assert_eq!(state.handle(update()), vec![]);
+
+    state.tick(1);
+    for _ in 0..5 {
+        assert_eq!(state.handle(update()), vec![broadcast(&state)]);
+    }
+    assert_eq!(state.handle(update()), vec![limited]);
+
+    // Closing the connection always gets through
+    assert_eq!(
+        state.handle(Inbound::from_client(alice, Command::ClosedConnection(alice))),
+        vec![Effect::Close(alice)]
+    );
+}
+
+#[test]
+fn clients_cant_start_or_end_other_peoples_calls() {
+    let (mut state, clients) = server_with(3);
+    let (alice, bob, carol) = (clients[0], clients[1], clients[2]);
+    let impersonating = |claimed| Effect::Send {
+        to: carol,
+        command: Command::Error(ProtocolError::Impersonation(claimed)),
+    };
+
+    assert_eq!(state.handle(Inbound::from_client(carol, Command::InCall(alice, bob))), vec![impersonating(alice)]);
+    assert_eq!(status(&state, &alice), Some(Status::WaitingForPartner));
+
+    assert_eq!(state.handle(Inbound::from_client(alice, Command::InCall(alice, bob))), vec![broadcast(&state)]);
+    assert_eq!(state.handle(Inbound::from_client(carol, Command::EndCall(alice, bob))), vec![impersonating(alice)]);
+
+    // Relaying the hangup doesn't make it carol's call either, nor does it reach bob
+    let hangup = Inbound::relay(carol, bob, Command::EndCall(alice, bob));
+    assert_eq!(state.handle(hangup), vec![impersonating(alice)]);
+    assert_eq!(status(&state, &bob), Some(Status::InCall(alice, bob)));
+
+    // Either of the two can end it
+    state.handle(Inbound::from_client(bob, Command::EndCall(alice, bob)));
+    assert_eq!(status(&state, &alice), Some(Status::AnsweringQuestionAboutLastPartner));
+}
+
+#[test]
+fn clients_cant_update_answer_for_or_disconnect_someone_else() {
+    let (mut state, clients) = server_with(2);
+    let (alice, bob) = (clients[0], clients[1]);
+    let impersonating = Effect::Send {
+        to: alice,
+        command: Command::Error(ProtocolError::Impersonation(bob)),
+    };
+
+    let renamed = Client {
+        username: Some("mallory".to_string()),
+        ..Client::from_user_id(bob)
+    };
+    assert_eq!(state.handle(Inbound::from_client(alice, Command::UpdateClient(renamed))), vec![impersonating.clone()]);
+    assert_eq!(state.client(&bob).unwrap().client, Client::from_user_id(bob));
+
+    assert_eq!(state.handle(Inbound::from_client(alice, Command::ClosedConnection(bob))), vec![impersonating.clone()]);
+    assert!(state.client(&bob).is_some());
+
+    // Alice can't keep bob alive by answering his pings
+    state.tick(0);
+    assert_eq!(state.handle(Inbound::from_client(alice, Command::Pong(bob, 0))), vec![impersonating.clone()]);
+    assert_eq!(state.client(&bob).unwrap().ping_status, PingStatus::Pinged(0));
+    state.handle(Inbound::from_client(alice, Command::Pong(alice, 0)));
+    state.tick(2);
+    state.handle(Inbound::from_client(alice, Command::Pong(alice, 2)));
+    assert!(state.tick(4).contains(&Effect::Close(bob)));
+    assert!(state.client(&alice).is_some());
+}
@@ -0,0 +1,73 @@
+use models::{Client, StateErrors, StateManager, StateSnapshot};
+use uuid::Uuid;
+
+use std::collections::HashMap;
+
+#[test]
+fn typed_values_round_trip() {
+    let mut state = StateManager::new();
+    let client = Client::from_user_id(Uuid::new_v4());
+
+    assert_eq!(state.insert("round", &3_u64), Ok(false));
+    assert_eq!(state.insert("round", &4_u64), Ok(true));
+    state.insert("host", &client).unwrap();
+
+    assert_eq!(state.get::<u64>("round"), Ok(Some(4)));
+    assert_eq!(state.get::<Client>("host"), Ok(Some(client.clone())));
+    assert_eq!(state.get::<u64>("missing"), Ok(None));
+    assert!(matches!(
+        state.get::<Client>("round"),
+        Err(StateErrors::Decoding { key, .. }) if key == "round"
+    ));
+
+    assert_eq!(state.remove::<Client>("host"), Ok(Some(client)));
+    assert!(!state.contains("host"));
+    assert_eq!(state.keys().collect::<Vec<_>>(), vec!["round"]);
+}
+
+#[test]
+fn snapshots_restore_the_whole_state() {
+    let mut state = StateManager::new();
+    let mut ratings = HashMap::new();
+    ratings.insert(Uuid::new_v4(), 5_i32);
+    state.insert("ratings", &ratings).unwrap();
+    state.insert("round", &1_u64).unwrap();
+
+    let bytes = state.snapshot().to_bytes().unwrap();
+
+    let mut restored = StateManager::new();
+    restored.insert("stale", &true).unwrap();
+    restored.restore(StateSnapshot::from_bytes(&bytes).unwrap());
+
+    assert_eq!(restored, state);
+    assert_eq!(restored.get::<HashMap<Uuid, i32>>("ratings"), Ok(Some(ratings)));
+    assert!(matches!(
+        StateSnapshot::from_bytes(&[1, 2, 3]),
+        Err(StateErrors::MalformedSnapshot(_))
+    ));
+}
+
+#[test]
+fn diffs_turn_an_older_state_into_a_newer_one() {
+    let mut state = StateManager::new();
+    state.insert("round", &1_u64).unwrap();
+    state.insert("host", &"alice".to_string()).unwrap();
+    state.insert("paused", &false).unwrap();
+    let before = state.snapshot();
+
+    state.insert("round", &2_u64).unwrap();
+    state.insert("paused", &false).unwrap();
+    state.remove::<String>("host").unwrap();
+    state.insert("guest", &"bob".to_string()).unwrap();
+
+    let diff = state.diff_since(&before);
+    assert_eq!(diff.added.keys().collect::<Vec<_>>(), vec!["guest"]);
+    assert_eq!(diff.changed.keys().collect::<Vec<_>>(), vec!["round"]);
+    assert_eq!(diff.removed.iter().collect::<Vec<_>>(), vec!["host"]);
+
+    let mut replica = StateManager::new();
+    replica.restore(before);
+    replica.apply(&diff);
+    assert_eq!(replica, state);
+    assert!(replica.diff_since(&state.snapshot()).is_empty());
+}
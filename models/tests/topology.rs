@@ -0,0 +1,110 @@
+mod common;
+
+use common::client;
+use models::loopback::{LoopbackChannel, LoopbackManager};
+use models::{
+    CommunicationManager, EntityTypes, ForwardStep, Forwarded,
+    RawMessage, RoutingErrors, Topology,
+};
+use uuid::Uuid;
+
+fn note(text: &str) -> RawMessage {
+    RawMessage {
+        identity: Uuid::new_v4(),
+        name: "Note".to_string(),
+        description: String::new(),
+        data: text.as_bytes().to_vec(),
+        correlation: None,
+    }
+}
+
+#[test]
+fn shortest_paths_follow_the_channels() {
+    let (alice, bob, carol, dave) = (client(), client(), client(), client());
+    let mut topology = Topology::new();
+    topology.connect(alice, bob);
+    topology.connect(bob, carol);
+    topology.connect(carol, dave);
+
+    assert_eq!(
+        topology.shortest_path(&alice.uuid(), &dave.uuid()),
+        Ok(vec![alice.uuid(), bob.uuid(), carol.uuid(), dave.uuid()])
+    );
+    assert_eq!(topology.next_hop(&alice.uuid(), &alice.uuid()), Ok(None));
+
+    // A shortcut wins
+    assert!(topology.add_channel(alice.uuid(), dave.uuid()));
+    assert!(!topology.add_channel(dave.uuid(), alice.uuid()));
+    assert_eq!(topology.next_hop(&alice.uuid(), &dave.uuid()), Ok(Some(dave.uuid())));
+
+    topology.remove_entity(&dave.uuid());
+    assert!(topology.remove_channel(&bob.uuid(), &carol.uuid()));
+    assert_eq!(
+        topology.shortest_path(&alice.uuid(), &carol.uuid()),
+        Err(RoutingErrors::NoRoute {
+            from: alice.uuid(),
+            to: carol.uuid()
+        })
+    );
+    assert_eq!(
+        topology.shortest_path(&alice.uuid(), &dave.uuid()),
+        Err(RoutingErrors::UnknownEntity(dave.uuid()))
+    );
+}
+
+#[test]
+fn forwarded_messages_take_a_detour_when_a_channel_goes_away() {
+    let (alice, bob, carol, dave) = (client(), client(), client(), client());
+    let mut topology = Topology::new();
+    topology.connect(alice, bob);
+    topology.connect(bob, carol);
+    topology.connect(bob, dave);
+    topology.connect(dave, carol);
+
+    let forwarded = Forwarded::new(&topology, alice.uuid(), carol.uuid(), &note("hi")).unwrap();
+    assert_eq!(forwarded.intermediaries(), 1);
+
+    topology.remove_channel(&bob.uuid(), &carol.uuid());
+    match topology.step(&bob.uuid(), forwarded).unwrap() {
+        ForwardStep::Relay { to, forwarded } => {
+            assert_eq!(to, dave);
+            assert_eq!(forwarded.path, vec![alice.uuid(), bob.uuid(), dave.uuid(), carol.uuid()]);
+        }
+        other => panic!("expected a relay, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn messages_hop_over_intermediaries() {
+    let (alice, bob, carol) = (client(), client(), client());
+    let allowed = vec![(EntityTypes::Client, EntityTypes::Client)];
+    let mut alices = LoopbackManager::new(alice, allowed.clone()).await;
+    let mut bobs = LoopbackManager::new(bob, allowed.clone()).await;
+    let mut carols = LoopbackManager::new(carol, allowed).await;
+
+    let (alice_end, bob_end) = LoopbackChannel::pair();
+    alices.add_channel(alice_end, bob).await.unwrap();
+    bobs.add_channel(bob_end, alice).await.unwrap();
+    let (bob_end, carol_end) = LoopbackChannel::pair();
+    bobs.add_channel(bob_end, carol).await.unwrap();
+    carols.add_channel(carol_end, bob).await.unwrap();
+
+    let mut topology = Topology::new();
+    topology.connect(alice, bob);
+    topology.connect(bob, carol);
+
+    let forwarded = Forwarded::new(&topology, alice.uuid(), carol.uuid(), &note("hi")).unwrap();
+    assert_eq!(topology.forward(&alice.uuid(), &alices, forwarded).await, Ok(None));
+
+    let (from, relayed) = bobs.pop_queue().await.unwrap();
+    assert_eq!(from, alice);
+    let relayed: Forwarded = bincode::deserialize(&relayed.data().unwrap()).unwrap();
+    assert_eq!(topology.forward(&bob.uuid(), &bobs, relayed).await, Ok(None));
+
+    let (from, arrived) = carols.pop_queue().await.unwrap();
+    assert_eq!(from, bob);
+    let arrived: Forwarded = bincode::deserialize(&arrived.data().unwrap()).unwrap();
+    let delivered = topology.forward(&carol.uuid(), &carols, arrived).await.unwrap().unwrap();
+    assert_eq!(delivered.name, "Note");
+    assert_eq!(delivered.data, b"hi".to_vec());
+}
@@ -0,0 +1,22 @@
+//! Runs in a browser: wasm-pack test --headless --firefox -- --features wasm --test wasm_websocket
+#![cfg(all(feature = "wasm", target_arch = "wasm32"))]
+
+use models::wasm_websocket::WasmWebSocketChannel;
+use models::{Codec, CommunicationErrors};
+
+use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+#[wasm_bindgen_test]
+async fn a_url_the_browser_refuses_fails_the_connect() {
+    let connected = WasmWebSocketChannel::connect("not a websocket url", Codec::Bincode).await;
+    assert_eq!(connected.err(), Some(CommunicationErrors::ChannelInitializationFailed));
+}
+
+#[wasm_bindgen_test]
+async fn a_server_that_isnt_there_fails_the_connect() {
+    // Nothing listens on the discard port
+    let connected = WasmWebSocketChannel::connect("ws://127.0.0.1:9", Codec::Json).await;
+    assert_eq!(connected.err(), Some(CommunicationErrors::ChannelInitializationFailed));
+}
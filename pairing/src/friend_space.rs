@@ -0,0 +1,327 @@
+//! The people in friend-space, where they have been and whom they have met.
+
+use crate::space::{least_crowded_quadrant, BoundingBox, Point};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use uuid::Uuid;
+
+use std::collections::BTreeMap;
+
+/// How two people rated their conversation, from -10 (extremely bad) to 10 (extremely good).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rating {
+    pub person_a: Uuid,
+    pub person_b: Uuid,
+    pub a_rates_b: i32,
+    pub b_rates_a: i32,
+}
+
+impl Rating {
+    pub fn new(person_a: Uuid, person_b: Uuid, a_rates_b: i32, b_rates_a: i32) -> Rating {
+        Rating {
+            person_a,
+            person_b,
+            a_rates_b,
+            b_rates_a,
+        }
+    }
+
+    /// They met but nobody rated the other. Counts as a recent partner without moving anyone.
+    pub fn unrated(person_a: Uuid, person_b: Uuid) -> Rating {
+        Rating::new(person_a, person_b, 0, 0)
+    }
+
+    pub fn other_person(&self, you: &Uuid) -> Uuid {
+        if *you == self.person_a {
+            self.person_b
+        } else {
+            self.person_a
+        }
+    }
+
+    pub fn you_rate_other_person(&self, you: &Uuid) -> i32 {
+        if *you == self.person_a {
+            self.a_rates_b
+        } else {
+            self.b_rates_a
+        }
+    }
+
+    pub fn other_person_rates_you(&self, you: &Uuid) -> i32 {
+        if *you == self.person_a {
+            self.b_rates_a
+        } else {
+            self.a_rates_b
+        }
+    }
+
+    pub fn combined(&self) -> i32 {
+        self.a_rates_b + self.b_rates_a
+    }
+}
+
+struct Member {
+    /// The positions the person has had, the current one last
+    history: Vec<Point>,
+    /// How selective the person is: only conversations rated above twice this number move them
+    friendship_threshold: u32,
+    interactions: Vec<Rating>,
+}
+
+pub struct FriendSpace {
+    boundary: BoundingBox,
+    /// How many of their last partners a person isn't paired with again
+    recent_interactions: usize,
+    /// People with more conversations than this are moved away from the ones who rated them badly
+    relocate_after: usize,
+    /// How many conversations and positions are kept per person, everything when None
+    remember: Option<usize>,
+    /// Ordered by uuid so that a seeded space always makes the same moves
+    members: BTreeMap<Uuid, Member>,
+    rng: StdRng,
+}
+
+impl Default for FriendSpace {
+    fn default() -> Self {
+        FriendSpace::new()
+    }
+}
+
+impl FriendSpace {
+    /// The unit square with the memory of the simulation: the last 10 partners are avoided and people move after 5 conversations.
+    pub fn new() -> FriendSpace {
+        FriendSpace::with_rng(StdRng::from_entropy())
+    }
+
+    /// Same as ```new``` but the random positions and partners are drawn from a seeded RNG.
+    pub fn seeded(seed: u64) -> FriendSpace {
+        FriendSpace::with_rng(StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(rng: StdRng) -> FriendSpace {
+        FriendSpace {
+            boundary: BoundingBox::unit(),
+            recent_interactions: 10,
+            relocate_after: 5,
+            remember: None,
+            members: BTreeMap::new(),
+            rng,
+        }
+    }
+
+    pub fn avoiding_recent(mut self, partners: usize) -> FriendSpace {
+        self.recent_interactions = partners;
+        self
+    }
+
+    pub fn relocating_after(mut self, interactions: usize) -> FriendSpace {
+        self.relocate_after = interactions;
+        self
+    }
+
+    /// Only keeps the last conversations and positions of everyone, for spaces that live as long as a server. Never fewer than pairing needs to avoid recent partners and to decide who is relocated.
+    pub fn remembering(mut self, interactions: usize) -> FriendSpace {
+        self.remember = Some(interactions);
+        self
+    }
+
+    /// How many conversations and positions are kept per person
+    fn memory(&self) -> usize {
+        match self.remember {
+            Some(remember) => remember.max(self.recent_interactions).max(self.relocate_after + 1),
+            None => usize::MAX,
+        }
+    }
+
+    /// Places the person at a random position. People that are already in friend-space stay where they are.
+    pub fn join(&mut self, person: Uuid, friendship_threshold: u32) -> Point {
+        if let Some(position) = self.position(&person) {
+            return position;
+        }
+
+        let position = Point::random_within(&self.boundary, &mut self.rng);
+        self.members.insert(
+            person,
+            Member {
+                history: vec![position],
+                friendship_threshold,
+                interactions: vec![],
+            },
+        );
+        position
+    }
+
+    /// Forgets everything about the person. Their partners keep the conversations they had with them. Returns false when the person hadn't joined.
+    pub fn leave(&mut self, person: &Uuid) -> bool {
+        self.members.remove(person).is_some()
+    }
+
+    /// Returns false when the person hasn't joined
+    pub fn move_to(&mut self, person: &Uuid, position: Point) -> bool {
+        let memory = self.memory();
+        match self.members.get_mut(person) {
+            Some(member) => {
+                member.history.push(position);
+                keep_last(&mut member.history, memory);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, person: &Uuid) -> bool {
+        self.members.contains_key(person)
+    }
+
+    pub fn position(&self, person: &Uuid) -> Option<Point> {
+        self.members.get(person).and_then(|member| member.history.last().copied())
+    }
+
+    /// The positions the person has had, the current one last. Only the latest ones when the space is ```remembering```.
+    pub fn history(&self, person: &Uuid) -> &[Point] {
+        self.members.get(person).map(|member| &member.history[..]).unwrap_or(&[])
+    }
+
+    pub fn interactions(&self, person: &Uuid) -> &[Rating] {
+        self.members.get(person).map(|member| &member.interactions[..]).unwrap_or(&[])
+    }
+
+    /// Remembers the conversation for both people. People that haven't joined are left out.
+    pub fn record(&mut self, rating: Rating) {
+        let memory = self.memory();
+        for person in [rating.person_a, rating.person_b].iter() {
+            if let Some(member) = self.members.get_mut(person) {
+                member.interactions.push(rating);
+                keep_last(&mut member.interactions, memory);
+            }
+        }
+    }
+
+    /// Fills in how ```you``` rated ```other``` in the last conversation between the two, for when the ratings come in one at a time after the conversation was recorded. When they haven't talked yet the conversation is recorded with only this side rated.
+    pub fn rate(&mut self, you: Uuid, other: Uuid, rating: i32) {
+        let talked = self.interactions(&you).iter().any(|interaction| interaction.other_person(&you) == other);
+        if !talked {
+            self.record(Rating::new(you, other, rating, 0));
+            return;
+        }
+
+        for (person, partner) in [(you, other), (other, you)].iter() {
+            let last = self.members.get_mut(person).and_then(|member| {
+                member
+                    .interactions
+                    .iter_mut()
+                    .rev()
+                    .find(|interaction| interaction.other_person(person) == *partner)
+            });
+            match last {
+                Some(last) if last.person_a == you => last.a_rates_b = rating,
+                Some(last) => last.b_rates_a = rating,
+                None => {}
+            }
+        }
+    }
+
+    /// The partners of the last n conversations, the latest last
+    pub fn recent_partners(&self, person: &Uuid, n: usize) -> Vec<Uuid> {
+        let interactions = self.interactions(person);
+        interactions[interactions.len().saturating_sub(n)..]
+            .iter()
+            .map(|rating| rating.other_person(person))
+            .collect()
+    }
+
+    /// Where everyone who rated the person badly is now
+    pub fn shunned_by(&self, person: &Uuid) -> Vec<Point> {
+        self.interactions(person)
+            .iter()
+            .filter(|rating| rating.other_person_rates_you(person) < 0)
+            .filter_map(|rating| self.position(&rating.other_person(person)))
+            .collect()
+    }
+
+    /// The closest of the choices that isn't one of the person's recent partners
+    pub fn nearest_partner(&self, person: &Uuid, choices: &[Uuid]) -> Option<Uuid> {
+        let position = self.position(person)?;
+        let recent = self.recent_partners(person, self.recent_interactions);
+
+        let mut nearest: Option<(f64, Uuid)> = None;
+        for choice in choices.iter().filter(|choice| *choice != person && !recent.contains(choice)) {
+            if let Some(other) = self.position(choice) {
+                let distance = position.squared_distance(&other);
+                // The first of equally close choices is kept
+                if nearest.map(|(closest, _)| distance < closest).unwrap_or(true) {
+                    nearest = Some((distance, *choice));
+                }
+            }
+        }
+        nearest.map(|(_, choice)| choice)
+    }
+
+    /// Moves the person to a random spot in the quadrant with the fewest people who rated them badly. Returns the new position, None when nobody did.
+    pub fn relocate(&mut self, person: &Uuid) -> Option<Point> {
+        let quadrant = least_crowded_quadrant(&self.boundary, &self.shunned_by(person))?;
+        let position = Point::random_within(&self.boundary.quadrant(quadrant), &mut self.rng);
+        self.move_to(person, position);
+        Some(position)
+    }
+
+    /// Moves everyone towards the weighted average of the partners they got along with. All moves are worked out from the current positions before anyone moves.
+    pub fn move_towards_friends(&mut self) {
+        let moves: Vec<(Uuid, Point)> = self
+            .members
+            .iter()
+            .filter_map(|(person, member)| {
+                let friends: Vec<(f64, f64, Point)> = member
+                    .interactions
+                    .iter()
+                    .filter(|rating| rating.combined() > 2 * member.friendship_threshold as i32)
+                    .filter_map(|rating| {
+                        let position = self.position(&rating.other_person(person))?;
+                        Some((
+                            rating.other_person_rates_you(person) as f64,
+                            rating.you_rate_other_person(person) as f64,
+                            position,
+                        ))
+                    })
+                    .collect();
+
+                Point::weighted_average(&friends).map(|position| (*person, position))
+            })
+            .collect();
+
+        for (person, position) in moves {
+            self.move_to(&person, position);
+        }
+    }
+
+    /// One round of the simulation: everyone moves towards their friends, then the online people are paired in order. Each takes the nearest person that wasn't a recent partner, or a random one when there is none, after being relocated first when they have been around for long enough. With an odd number of people the last one is left out.
+    ///
+    /// The conversations aren't recorded, that is up to the caller once the ratings are known. People that haven't joined are left out.
+    pub fn pair_round(&mut self, online: &[Uuid]) -> Vec<(Uuid, Uuid)> {
+        self.move_towards_friends();
+
+        let mut people: Vec<Uuid> = online.iter().filter(|person| self.contains(person)).copied().collect();
+        let mut pairs = vec![];
+        while people.len() >= 2 {
+            let person = people.remove(0);
+            if self.interactions(&person).len() > self.relocate_after {
+                self.relocate(&person);
+            }
+
+            let partner = match self.nearest_partner(&person, &people) {
+                Some(partner) => partner,
+                None => people[self.rng.gen_range(0..people.len())],
+            };
+            people.retain(|other| *other != partner);
+            pairs.push((person, partner));
+        }
+        pairs
+    }
+}
+
+/// Drops all but the last ```keep``` items
+fn keep_last<T>(items: &mut Vec<T>, keep: usize) {
+    let excess = items.len().saturating_sub(keep);
+    items.drain(..excess);
+}
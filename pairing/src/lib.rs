@@ -0,0 +1,9 @@
+//! Who should talk to whom, as studied by the simulation and used by the live server.
+//!
+//! Every person has a position in "friend-space", the unit square. After every round people drift towards the partners they got along with, and people with a history of bad conversations are moved to the part of the square where the fewest of the people who disliked them are. Pairing picks the nearest person that wasn't one of the recent partners, so both the simulation and the server end up matching people who are likely to get along.
+
+pub mod friend_space;
+pub mod space;
+
+pub use friend_space::{FriendSpace, Rating};
+pub use space::{least_crowded_quadrant, BoundingBox, Point, Quadrant};
@@ -0,0 +1,127 @@
+//! The geometry of friend-space.
+
+use rand::Rng;
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    pub fn random_within(boundary: &BoundingBox, rng: &mut impl Rng) -> Point {
+        Point {
+            x: rng.gen_range(boundary.left..boundary.right),
+            y: rng.gen_range(boundary.bottom..boundary.top),
+        }
+    }
+
+    pub fn squared_distance(&self, other: &Point) -> f64 {
+        (self.x - other.x).powi(2) + (self.y - other.y).powi(2)
+    }
+
+    /// The average of the points, each weighted by the two ratings next to it. None when the weights add up to nothing.
+    pub fn weighted_average(weighted: &[(f64, f64, Point)]) -> Option<Point> {
+        let total: f64 = weighted.iter().map(|(a, b, _)| a + b).sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let x = weighted.iter().map(|(a, b, point)| (a + b) * point.x).sum::<f64>() / total;
+        let y = weighted.iter().map(|(a, b, point)| (a + b) * point.y).sum::<f64>() / total;
+        Some(Point { x, y })
+    }
+
+    pub fn as_tuple(self) -> (f64, f64) {
+        (self.x, self.y)
+    }
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({},{})", self.x, self.y)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub left: f64,
+    pub right: f64,
+    pub top: f64,
+    pub bottom: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Quadrant {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl BoundingBox {
+    /// The whole of friend-space
+    pub fn unit() -> BoundingBox {
+        BoundingBox {
+            left: 0.0,
+            right: 1.0,
+            top: 1.0,
+            bottom: 0.0,
+        }
+    }
+
+    /// The left and the top edge belong to the neighbouring boxes, so that the quadrants of a box never share a point.
+    pub fn contains(&self, point: &Point) -> bool {
+        point.x <= self.right && point.x > self.left && point.y >= self.bottom && point.y < self.top
+    }
+
+    pub fn quadrant(&self, quadrant: Quadrant) -> BoundingBox {
+        let middle = (self.left + self.right) / 2.0;
+        let center = (self.bottom + self.top) / 2.0;
+
+        let (left, right) = match quadrant {
+            Quadrant::TopLeft | Quadrant::BottomLeft => (self.left, middle),
+            Quadrant::TopRight | Quadrant::BottomRight => (middle, self.right),
+        };
+        let (bottom, top) = match quadrant {
+            Quadrant::TopLeft | Quadrant::TopRight => (center, self.top),
+            Quadrant::BottomLeft | Quadrant::BottomRight => (self.bottom, center),
+        };
+
+        BoundingBox {
+            left,
+            right,
+            top,
+            bottom,
+        }
+    }
+}
+
+/// The quadrant of the boundary that holds the fewest of the points, None when the boundary holds none of them. Ties go to the bottom left, then the bottom right, the top right and the top left.
+pub fn least_crowded_quadrant(boundary: &BoundingBox, points: &[Point]) -> Option<Quadrant> {
+    let counted: Vec<(usize, Quadrant)> = [
+        Quadrant::BottomLeft,
+        Quadrant::BottomRight,
+        Quadrant::TopRight,
+        Quadrant::TopLeft,
+    ]
+    .iter()
+    .map(|quadrant| {
+        let inner = boundary.quadrant(*quadrant);
+        (points.iter().filter(|point| inner.contains(point)).count(), *quadrant)
+    })
+    .collect();
+
+    if counted.iter().all(|(count, _)| *count == 0) {
+        return None;
+    }
+
+    // min_by_key keeps the first of equal counts
+    counted.iter().min_by_key(|(count, _)| *count).map(|(_, quadrant)| *quadrant)
+}
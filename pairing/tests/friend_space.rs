@@ -0,0 +1,142 @@
+use pairing::{least_crowded_quadrant, BoundingBox, FriendSpace, Point, Quadrant, Rating};
+use uuid::Uuid;
+
+/// People joined at the given positions, in the same order
+fn placed(space: &mut FriendSpace, positions: &[(f64, f64)]) -> Vec<Uuid> {
+    positions
+        .iter()
+        .map(|(x, y)| {
+            let person = Uuid::new_v4();
+            space.join(person, 0);
+            space.move_to(&person, Point::new(*x, *y));
+            person
+        })
+        .collect()
+}
+
+#[test]
+fn the_quadrant_with_the_fewest_points_wins() {
+    let unit = BoundingBox::unit();
+    let points: Vec<Point> = [(0.1, 0.9), (0.2, 0.8), (0.9, 0.9), (0.9, 0.1), (0.1, 0.1)]
+        .iter()
+        .map(|(x, y)| Point::new(*x, *y))
+        .collect();
+
+    assert_eq!(least_crowded_quadrant(&unit, &points), Some(Quadrant::BottomLeft));
+    assert_eq!(least_crowded_quadrant(&unit, &points[..2]), Some(Quadrant::BottomLeft));
+    assert_eq!(least_crowded_quadrant(&unit, &points[2..]), Some(Quadrant::TopLeft));
+    assert_eq!(least_crowded_quadrant(&unit, &[]), None);
+
+    let top_right = unit.quadrant(Quadrant::TopRight);
+    assert_eq!((top_right.left, top_right.right, top_right.bottom, top_right.top), (0.5, 1.0, 0.5, 1.0));
+}
+
+#[test]
+fn the_nearest_person_is_picked_unless_they_just_talked() {
+    let mut space = FriendSpace::seeded(3).avoiding_recent(1);
+    let people = placed(&mut space, &[(0.1, 0.1), (0.9, 0.9), (0.2, 0.2), (0.5, 0.5)]);
+    let (me, far, near, middle) = (people[0], people[1], people[2], people[3]);
+
+    assert_eq!(space.nearest_partner(&me, &people), Some(near));
+    assert_eq!(space.pair_round(&people), vec![(me, near), (far, middle)]);
+
+    space.record(Rating::unrated(me, near));
+    assert_eq!(space.recent_partners(&near, 10), vec![me]);
+    assert_eq!(space.nearest_partner(&me, &people), Some(middle));
+
+    // Only the last partner is remembered
+    space.record(Rating::unrated(me, middle));
+    assert_eq!(space.nearest_partner(&me, &people), Some(near));
+    assert_eq!(space.nearest_partner(&me, &[me]), None);
+}
+
+#[test]
+fn people_drift_towards_friends_and_away_from_who_disliked_them() {
+    let mut space = FriendSpace::seeded(9).relocating_after(1);
+    let people = placed(&mut space, &[(0.2, 0.2), (0.8, 0.8), (0.9, 0.1)]);
+    let (me, friend, foe) = (people[0], people[1], people[2]);
+
+    space.record(Rating::new(me, friend, 10, 10));
+    space.move_towards_friends();
+    assert_eq!(space.position(&me), Some(Point::new(0.8, 0.8)));
+    assert_eq!(space.position(&friend), Some(Point::new(0.2, 0.2)));
+    assert_eq!(space.history(&me).len(), 3);
+
+    // Two conversations are more than relocating_after, and the foe is in the bottom right
+    space.record(Rating::new(me, foe, 0, -10));
+    let pairs = space.pair_round(&[me, friend]);
+    assert_eq!(pairs.len(), 1);
+    let moved = space.position(&me).unwrap();
+    assert!(BoundingBox::unit().quadrant(Quadrant::BottomLeft).contains(&moved));
+}
+
+#[test]
+fn a_seeded_space_pairs_everyone_the_same_way() {
+    let run = || {
+        let mut space = FriendSpace::seeded(42);
+        let people: Vec<Uuid> = (0..9u128).map(Uuid::from_u128).collect();
+        for person in &people {
+            space.join(*person, 3);
+        }
+
+        let mut rounds = vec![];
+        for _ in 0..20 {
+            let pairs = space.pair_round(&people);
+            for (a, b) in &pairs {
+                space.record(Rating::new(*a, *b, (a.as_u128() % 7) as i32, -((b.as_u128() % 5) as i32)));
+            }
+            rounds.push(pairs);
+        }
+        (rounds, people.iter().map(|person| space.position(person)).collect::<Vec<_>>())
+    };
+
+    let (rounds, positions) = run();
+    assert_eq!(run(), (rounds.clone(), positions));
+    assert!(rounds.iter().all(|pairs| pairs.len() == 4));
+}
+
+#[test]
+fn ratings_coming_in_one_at_a_time_fill_in_the_conversation() {
+    let mut space = FriendSpace::seeded(9);
+    let people = placed(&mut space, &[(0.2, 0.2), (0.8, 0.8), (0.5, 0.1)]);
+    let (alice, bob, carol) = (people[0], people[1], people[2]);
+
+    // Matched first, rated once the call is over
+    space.record(Rating::unrated(alice, bob));
+    space.rate(bob, alice, 9);
+    space.rate(alice, bob, 7);
+    assert_eq!(space.interactions(&alice), &[Rating::new(alice, bob, 7, 9)]);
+    assert_eq!(space.interactions(&bob), space.interactions(&alice));
+
+    // A call nobody matched them for
+    space.rate(carol, alice, -3);
+    assert_eq!(space.interactions(&carol), &[Rating::new(carol, alice, -3, 0)]);
+    assert_eq!(space.interactions(&alice).len(), 2);
+
+    space.move_towards_friends();
+    assert_eq!(space.position(&alice), Some(Point::new(0.8, 0.8)));
+    assert_eq!(space.position(&bob), Some(Point::new(0.2, 0.2)));
+}
+
+#[test]
+fn a_space_that_remembers_little_still_avoids_recent_partners_and_forgets_who_left() {
+    let mut space = FriendSpace::seeded(3).avoiding_recent(4).relocating_after(2).remembering(1);
+    let people = placed(&mut space, &[(0.5, 0.5), (0.1, 0.1), (0.2, 0.2), (0.3, 0.3), (0.4, 0.4), (0.6, 0.6)]);
+    let alice = people[0];
+
+    for partner in &people[1..] {
+        space.record(Rating::unrated(alice, *partner));
+        space.move_to(&alice, space.position(partner).unwrap());
+    }
+    assert_eq!(space.interactions(&alice).len(), 4);
+    assert_eq!(space.recent_partners(&alice, 4), people[2..].to_vec());
+    assert_eq!(space.history(&alice).len(), 4);
+    assert_eq!(space.position(&alice), Some(Point::new(0.6, 0.6)));
+
+    assert!(space.leave(&alice));
+    assert!(!space.leave(&alice));
+    assert!(!space.contains(&alice));
+    assert_eq!(space.interactions(&alice), &[]);
+    // Her partners still remember talking to her
+    assert_eq!(space.recent_partners(&people[1], 1), vec![alice]);
+}
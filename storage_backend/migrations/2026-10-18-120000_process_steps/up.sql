@@ -0,0 +1,17 @@
+-- Your SQL goes here
+
+CREATE TABLE process_steps (
+    id BIGSERIAL PRIMARY KEY,
+    process_id TEXT NOT NULL,
+    step_index BIGINT NOT NULL,
+    iteration BIGINT NOT NULL,
+    message_name TEXT NOT NULL,
+    status TEXT NOT NULL,
+    -- The posting entity, as json
+    posted_by TEXT NOT NULL,
+    recorded_at TIMESTAMP NOT NULL,
+    elapsed_ms BIGINT
+);
+
+-- Timelines are always read for a single process
+CREATE INDEX process_steps_by_process ON process_steps (process_id, id);
@@ -0,0 +1,49 @@
+//! Keeps the journals of processes in the ```process_steps``` table, so that their timelines can be looked at with ```process_timeline``` after the fact.
+
+use crate::insert_process_step;
+
+use async_trait::async_trait;
+use diesel::pg::PgConnection;
+use models::{JournalSink, ProcessStep};
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A sink for ```DeclarativeProcess::journal_to```. Steps that can't be written are counted instead of stopping the process.
+///
+/// Diesel blocks, so the steps are written on tokio's blocking threads, one at a time over the single connection. The journal has to be used from within a tokio runtime.
+pub struct DatabaseJournal {
+    connection: Arc<Mutex<PgConnection>>,
+    failed_writes: AtomicU64,
+}
+
+impl DatabaseJournal {
+    pub fn new(connection: PgConnection) -> DatabaseJournal {
+        DatabaseJournal {
+            connection: Arc::new(Mutex::new(connection)),
+            failed_writes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn failed_writes(&self) -> u64 {
+        self.failed_writes.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl JournalSink for DatabaseJournal {
+    async fn record(&self, step: &ProcessStep) {
+        let connection = self.connection.clone();
+        let step = step.clone();
+        let written = tokio::task::spawn_blocking(move || {
+            // A write that panicked didn't leave anything half done on the connection, the next write can go ahead
+            let connection = connection.lock().unwrap_or_else(PoisonError::into_inner);
+            insert_process_step(&connection, &step).is_ok()
+        })
+        .await;
+
+        if !matches!(written, Ok(true)) {
+            self.failed_writes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
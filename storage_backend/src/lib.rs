@@ -1,3 +1,4 @@
+pub mod journal;
 pub mod models;
 pub mod schema;
 
@@ -9,6 +10,7 @@ use chrono::Utc;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use dotenv::dotenv;
+use std::convert::TryFrom;
 use std::env;
 
 pub fn establish_connection() -> PgConnection {
@@ -18,7 +20,10 @@ pub fn establish_connection() -> PgConnection {
     PgConnection::establish(&database_url).expect(&format!("Error connecting to {}", database_url))
 }
 
-use self::models::{InteractionHistory, NewInteractionHistory, NewUser, User};
+use self::models::{
+    InteractionHistory, NewInteractionHistory, NewProcessStepRecord, NewUser, ProcessStepRecord,
+    User,
+};
 
 pub async fn create_user(conn: &PgConnection) -> Result<User, diesel::result::Error> {
     use self::schema::users::dsl::*;
@@ -67,3 +72,37 @@ pub async fn list_game_modes(conn: &PgConnection) -> Result<Vec<String>, diesel:
 
     //diesel::dsl::select(game_mode).limit(10).get_results(conn);
 }
+
+pub async fn record_process_step(
+    conn: &PgConnection,
+    step: &::models::ProcessStep,
+) -> Result<ProcessStepRecord, diesel::result::Error> {
+    insert_process_step(conn, step)
+}
+
+pub(crate) fn insert_process_step(
+    conn: &PgConnection,
+    step: &::models::ProcessStep,
+) -> Result<ProcessStepRecord, diesel::result::Error> {
+    use schema::process_steps;
+
+    diesel::insert_into(process_steps::table)
+        .values(&NewProcessStepRecord::from(step))
+        .get_result(conn)
+}
+
+/// Every recorded step of the process, in the order they were logged. Meant for finding out how a process got to where it is.
+pub async fn process_timeline(
+    conn: &PgConnection,
+    process: uuid::Uuid,
+) -> Result<Vec<::models::ProcessStep>, diesel::result::Error> {
+    use schema::process_steps::dsl::*;
+
+    process_steps
+        .filter(process_id.eq(process.to_string()))
+        .order(id.asc())
+        .load::<ProcessStepRecord>(conn)?
+        .into_iter()
+        .map(::models::ProcessStep::try_from)
+        .collect()
+}
@@ -1,5 +1,6 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, TimeZone, Utc};
 use serde::Serialize;
+use std::convert::TryFrom;
 
 #[derive(Queryable, Serialize)]
 pub struct User {
@@ -52,3 +53,71 @@ impl From<String> for GameMode {
         GameMode { valid_mode: string }
     }
 }
+
+#[derive(Queryable, Serialize)]
+pub struct ProcessStepRecord {
+    pub id: i64,
+    pub process_id: String,
+    pub step_index: i64,
+    pub iteration: i64,
+    pub message_name: String,
+    pub status: String,
+    pub posted_by: String,
+    pub recorded_at: NaiveDateTime,
+    pub elapsed_ms: Option<i64>,
+}
+
+use super::schema::process_steps;
+
+#[derive(Insertable)]
+#[table_name = "process_steps"]
+pub struct NewProcessStepRecord {
+    pub process_id: String,
+    pub step_index: i64,
+    pub iteration: i64,
+    pub message_name: String,
+    pub status: String,
+    pub posted_by: String, // The entity as json
+    pub recorded_at: NaiveDateTime,
+    pub elapsed_ms: Option<i64>,
+}
+
+impl From<&::models::ProcessStep> for NewProcessStepRecord {
+    fn from(step: &::models::ProcessStep) -> Self {
+        NewProcessStepRecord {
+            process_id: step.process.to_string(),
+            step_index: step.index as i64,
+            iteration: step.iteration as i64,
+            message_name: step.message_name.clone(),
+            // Unit variants are plain json strings, read back the same way in ```try_from```
+            status: serde_json::to_value(step.status)
+                .ok()
+                .and_then(|status| status.as_str().map(str::to_string))
+                .expect("step statuses are unit variants"),
+            posted_by: serde_json::to_string(&step.posted_by).expect("entities can always be turned into json"),
+            recorded_at: step.at.naive_utc(),
+            elapsed_ms: step.elapsed_ms,
+        }
+    }
+}
+
+/// Fails for rows that weren't written from a ```rust ProcessStep ```
+impl TryFrom<ProcessStepRecord> for ::models::ProcessStep {
+    type Error = diesel::result::Error;
+
+    fn try_from(record: ProcessStepRecord) -> Result<Self, Self::Error> {
+        let unreadable = |err: Box<dyn std::error::Error + Send + Sync>| diesel::result::Error::DeserializationError(err);
+
+        Ok(::models::ProcessStep {
+            process: record.process_id.parse().map_err(|err| unreadable(Box::new(err)))?,
+            index: usize::try_from(record.step_index).map_err(|err| unreadable(Box::new(err)))?,
+            iteration: u64::try_from(record.iteration).map_err(|err| unreadable(Box::new(err)))?,
+            message_name: record.message_name,
+            status: serde_json::from_value(serde_json::Value::String(record.status))
+                .map_err(|err| unreadable(Box::new(err)))?,
+            posted_by: serde_json::from_str(&record.posted_by).map_err(|err| unreadable(Box::new(err)))?,
+            at: Utc.from_utc_datetime(&record.recorded_at),
+            elapsed_ms: record.elapsed_ms,
+        })
+    }
+}
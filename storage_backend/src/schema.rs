@@ -44,6 +44,20 @@ table! {
     }
 }
 
+table! {
+    process_steps (id) {
+        id -> Int8,
+        process_id -> Text,
+        step_index -> Int8,
+        iteration -> Int8,
+        message_name -> Text,
+        status -> Text,
+        posted_by -> Text,
+        recorded_at -> Timestamp,
+        elapsed_ms -> Nullable<Int8>,
+    }
+}
+
 table! {
     user_question_responses (id, user_id) {
         id -> Int8,
@@ -77,6 +91,7 @@ allow_tables_to_appear_in_same_query!(
     game_modes,
     interaction_history,
     numeric_types,
+    process_steps,
     user_question_responses,
     users,
 );
@@ -0,0 +1,68 @@
+use chrono::{TimeZone, Utc};
+use models::{Entity, EntityDetails, ProcessStatus, ProcessStep};
+use storage_backend::models::{NewProcessStepRecord, ProcessStepRecord};
+use uuid::Uuid;
+
+use std::convert::TryFrom;
+
+fn step(status: ProcessStatus) -> ProcessStep {
+    ProcessStep {
+        process: Uuid::new_v4(),
+        index: 3,
+        iteration: 2,
+        message_name: "SdpRequest".to_string(),
+        status,
+        posted_by: Entity::new(EntityDetails::Client(Uuid::new_v4(), Some("127.0.0.1:4000".parse().unwrap()))),
+        at: Utc.timestamp_millis_opt(1_600_000_000_123).unwrap(),
+        elapsed_ms: Some(40),
+    }
+}
+
+/// The row the database hands back for what was inserted
+fn stored(id: i64, new: NewProcessStepRecord) -> ProcessStepRecord {
+    ProcessStepRecord {
+        id,
+        process_id: new.process_id,
+        step_index: new.step_index,
+        iteration: new.iteration,
+        message_name: new.message_name,
+        status: new.status,
+        posted_by: new.posted_by,
+        recorded_at: new.recorded_at,
+        elapsed_ms: new.elapsed_ms,
+    }
+}
+
+#[test]
+fn every_status_comes_back_out_of_the_table() {
+    let statuses = [
+        ProcessStatus::Received,
+        ProcessStatus::Sent,
+        ProcessStatus::Waiting,
+        ProcessStatus::Running,
+        ProcessStatus::Finished,
+    ];
+
+    for (id, status) in statuses.iter().enumerate() {
+        let step = step(*status);
+        let record = NewProcessStepRecord::from(&step);
+        assert_eq!(record.status, serde_json::to_value(status).unwrap());
+
+        assert_eq!(ProcessStep::try_from(stored(id as i64, record)).unwrap(), step);
+    }
+}
+
+#[test]
+fn rows_that_werent_written_from_a_step_are_refused() {
+    let broken = |change: fn(&mut ProcessStepRecord)| {
+        let mut record = stored(1, NewProcessStepRecord::from(&step(ProcessStatus::Sent)));
+        change(&mut record);
+        ProcessStep::try_from(record).is_err()
+    };
+
+    assert!(broken(|record| record.status = "Sent(3)".to_string()));
+    assert!(broken(|record| record.status = "\"Sent\"".to_string()));
+    assert!(broken(|record| record.process_id = "process 7".to_string()));
+    assert!(broken(|record| record.step_index = -1));
+    assert!(broken(|record| record.posted_by = "server".to_string()));
+}
@@ -0,0 +1,70 @@
+//! The command line of the server. Without a subcommand the server is started, everything else about it comes from the config.
+
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+usage:
+    websocket_server [--insecure]
+        Serves the websockets. --insecure serves ws:// without TLS, for local development only.
+
+    websocket_server generate-identity [PATH] [--password PASSWORD] [--host HOST]... [--days DAYS]
+        Writes a throwaway self-signed TLS identity (pkcs12) to PATH, dev-identity.p12 by default.
+        The certificate is valid for localhost, 127.0.0.1 and ::1 plus every --host.";
+
+pub const DEV_IDENTITY_PASSWORD: &str = "websocket_server-dev";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Invocation {
+    Serve { insecure: bool },
+    GenerateIdentity {
+        path: PathBuf,
+        password: String,
+        hosts: Vec<String>,
+        days: u32,
+    },
+    Help,
+}
+
+/// The arguments without the name of the binary
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Invocation, String> {
+    let mut args = args.into_iter();
+
+    match args.next().as_deref() {
+        None => Ok(Invocation::Serve { insecure: false }),
+        Some("--insecure") => match args.next() {
+            None => Ok(Invocation::Serve { insecure: true }),
+            Some(unexpected) => Err(format!("unexpected argument {:?}", unexpected)),
+        },
+        Some("-h") | Some("--help") | Some("help") => Ok(Invocation::Help),
+        Some("generate-identity") => {
+            let mut path = None;
+            let mut password = DEV_IDENTITY_PASSWORD.to_string();
+            let mut hosts = vec!["localhost".to_string(), "127.0.0.1".to_string(), "::1".to_string()];
+            let mut days = 30;
+
+            while let Some(arg) = args.next() {
+                let mut value = |flag: &str| args.next().ok_or(format!("{} needs a value", flag));
+                match arg.as_str() {
+                    "--password" => password = value("--password")?,
+                    "--host" => hosts.push(value("--host")?),
+                    "--days" => {
+                        days = value("--days")?
+                            .parse()
+                            .map_err(|_| "--days has to be a number of days".to_string())?
+                    }
+                    flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+                    _ if path.is_none() => path = Some(PathBuf::from(arg)),
+                    _ => return Err(format!("unexpected argument {:?}", arg)),
+                }
+            }
+
+            Ok(Invocation::GenerateIdentity {
+                path: path.unwrap_or_else(|| PathBuf::from("dev-identity.p12")),
+                password,
+                hosts,
+                days,
+            })
+        }
+        Some(unexpected) => Err(format!("unknown command {:?}", unexpected)),
+    }
+}
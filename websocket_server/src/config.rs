@@ -0,0 +1,264 @@
+//! Everything a deployment may want to change without a rebuild. The settings are read from a toml file and can be overridden one by one through environment variables, e.g. to keep the password of the TLS identity out of the file:
+//!
+//! ```toml
+//! bind_address = "0.0.0.0:2096"
+//!
+//! [tls]
+//! identity = "certificate.p12"
+//!
+//! [rounds]
+//! round_seconds = 20
+//! ```
+//!
+//! Every setting that is left out keeps its default. The config is validated as a whole once it is loaded, so a bad deployment fails at startup instead of on the first client.
+
+use serde::Deserialize;
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Where the config file is looked for when WEBSOCKET_SERVER_CONFIG isn't set. A missing default file means all defaults.
+pub const DEFAULT_CONFIG_PATH: &str = "websocket_server.toml";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigErrors {
+    Unreadable { path: PathBuf, reason: String },
+    Malformed { path: PathBuf, reason: String },
+    /// An environment variable couldn't be parsed into the setting it overrides
+    InvalidOverride { variable: &'static str, value: String },
+    /// Every problem ```validate``` found, so that a deployment can fix them all in one go
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigErrors::Unreadable { path, reason } => {
+                write!(f, "could not read {}: {}", path.display(), reason)
+            }
+            ConfigErrors::Malformed { path, reason } => {
+                write!(f, "{} is not a valid config: {}", path.display(), reason)
+            }
+            ConfigErrors::InvalidOverride { variable, value } => {
+                write!(f, "{} can't be set to {:?}", variable, value)
+            }
+            ConfigErrors::Invalid(problems) => write!(f, "invalid config: {}", problems.join("; ")),
+        }
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: SocketAddr,
+    /// Serves ws:// without TLS, the tls settings are ignored. Only meant for local development.
+    pub insecure: bool,
+    pub tls: TlsConfig,
+    pub rounds: RoundConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// A pkcs12 archive with the certificate and its private key
+    pub identity: PathBuf,
+    /// Has no default, it has to come from the file or from WEBSOCKET_SERVER_TLS_PASSWORD
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoundConfig {
+    /// Seconds between two rounds of the game loop
+    pub round_seconds: u64,
+    /// Clients that answered their last ping are pinged again after this many rounds
+    pub ping_every_x_rounds: u32,
+    /// Clients are dropped from the online clients when this many pings in a row go unanswered
+    pub remove_after_missed_pings: u32,
+    /// Messages past this many in a round are dropped and answered with ProtocolError::RateLimited
+    pub max_messages_per_round: u32,
+    /// How the clients that are waiting for a partner are paired up every round
+    pub matchmaking: MatchmakingStrategy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchmakingStrategy {
+    /// The clients pick their partners themselves
+    Off,
+    /// Random pairs, see models::RandomPairs
+    Random,
+    /// The nearest person in friend-space that wasn't a recent partner, like in the simulation. See pairing::FriendSpace.
+    Distance,
+}
+
+impl std::str::FromStr for MatchmakingStrategy {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "off" => Ok(MatchmakingStrategy::Off),
+            "random" => Ok(MatchmakingStrategy::Random),
+            "distance" => Ok(MatchmakingStrategy::Distance),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind_address: SocketAddr::from(([0, 0, 0, 0], 2096)),
+            insecure: false,
+            tls: TlsConfig::default(),
+            rounds: RoundConfig::default(),
+        }
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            identity: PathBuf::from("certificate.p12"),
+            password: None,
+        }
+    }
+}
+
+impl Default for RoundConfig {
+    fn default() -> Self {
+        RoundConfig {
+            round_seconds: 20,
+            ping_every_x_rounds: 2,
+            remove_after_missed_pings: 2,
+            max_messages_per_round: 50,
+            matchmaking: MatchmakingStrategy::Off,
+        }
+    }
+}
+
+impl RoundConfig {
+    pub fn round_interval(&self) -> Duration {
+        Duration::from_secs(self.round_seconds)
+    }
+}
+
+impl ServerConfig {
+    /// Reads the file named by WEBSOCKET_SERVER_CONFIG (or the default file), applies the environment overrides and validates the result.
+    pub fn load() -> Result<ServerConfig, ConfigErrors> {
+        let config = ServerConfig::read()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Like ```load``` but without validating, for callers that still change the config
+    pub fn read() -> Result<ServerConfig, ConfigErrors> {
+        let mut config = match std::env::var("WEBSOCKET_SERVER_CONFIG") {
+            Ok(path) => ServerConfig::from_file(Path::new(&path))?,
+            Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                ServerConfig::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            Err(_) => ServerConfig::default(),
+        };
+
+        config.apply_overrides(|variable| std::env::var(variable).ok())?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<ServerConfig, ConfigErrors> {
+        let contents = std::fs::read_to_string(path).map_err(|err| ConfigErrors::Unreadable {
+            path: path.to_path_buf(),
+            reason: err.to_string(),
+        })?;
+
+        toml::from_str(&contents).map_err(|err| ConfigErrors::Malformed {
+            path: path.to_path_buf(),
+            reason: err.to_string(),
+        })
+    }
+
+    /// Overrides every setting whose variable ```lookup``` knows. Taking the lookup as an argument keeps this independent of the process environment.
+    pub fn apply_overrides(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<(), ConfigErrors> {
+        fn parsed<T: std::str::FromStr>(variable: &'static str, value: String) -> Result<T, ConfigErrors> {
+            value
+                .parse()
+                .map_err(|_| ConfigErrors::InvalidOverride { variable, value })
+        }
+
+        if let Some(value) = lookup("WEBSOCKET_SERVER_BIND_ADDRESS") {
+            self.bind_address = parsed("WEBSOCKET_SERVER_BIND_ADDRESS", value)?;
+        }
+        if let Some(value) = lookup("WEBSOCKET_SERVER_INSECURE") {
+            self.insecure = parsed("WEBSOCKET_SERVER_INSECURE", value)?;
+        }
+        if let Some(value) = lookup("WEBSOCKET_SERVER_TLS_IDENTITY") {
+            self.tls.identity = PathBuf::from(value);
+        }
+        if let Some(value) = lookup("WEBSOCKET_SERVER_TLS_PASSWORD") {
+            self.tls.password = Some(value);
+        }
+        if let Some(value) = lookup("WEBSOCKET_SERVER_ROUND_SECONDS") {
+            self.rounds.round_seconds = parsed("WEBSOCKET_SERVER_ROUND_SECONDS", value)?;
+        }
+        if let Some(value) = lookup("WEBSOCKET_SERVER_PING_EVERY_X_ROUNDS") {
+            self.rounds.ping_every_x_rounds = parsed("WEBSOCKET_SERVER_PING_EVERY_X_ROUNDS", value)?;
+        }
+        if let Some(value) = lookup("WEBSOCKET_SERVER_REMOVE_AFTER_MISSED_PINGS") {
+            self.rounds.remove_after_missed_pings =
+                parsed("WEBSOCKET_SERVER_REMOVE_AFTER_MISSED_PINGS", value)?;
+        }
+        if let Some(value) = lookup("WEBSOCKET_SERVER_MAX_MESSAGES_PER_ROUND") {
+            self.rounds.max_messages_per_round = parsed("WEBSOCKET_SERVER_MAX_MESSAGES_PER_ROUND", value)?;
+        }
+        if let Some(value) = lookup("WEBSOCKET_SERVER_MATCHMAKING") {
+            self.rounds.matchmaking = parsed("WEBSOCKET_SERVER_MATCHMAKING", value)?;
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigErrors> {
+        let mut problems = Vec::new();
+
+        if self.rounds.round_seconds == 0 {
+            problems.push("rounds.round_seconds has to be at least 1".to_string());
+        }
+        if self.rounds.ping_every_x_rounds == 0 {
+            problems.push("rounds.ping_every_x_rounds has to be at least 1".to_string());
+        }
+        if self.rounds.remove_after_missed_pings == 0 {
+            problems.push("rounds.remove_after_missed_pings has to be at least 1".to_string());
+        }
+        if self.rounds.max_messages_per_round == 0 {
+            problems.push("rounds.max_messages_per_round has to be at least 1, or clients can't send anything".to_string());
+        }
+        // The tls settings are ignored in insecure mode
+        if !self.insecure {
+            if self.tls.password.is_none() {
+                problems.push(
+                    "tls.password is missing, set it in the file or through WEBSOCKET_SERVER_TLS_PASSWORD".to_string(),
+                );
+            }
+            if !self.tls.identity.is_file() {
+                problems.push(format!("the tls identity {} doesn't exist", self.tls.identity.display()));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigErrors::Invalid(problems))
+        }
+    }
+
+    /// The bytes of the TLS identity, read from the file the config names
+    pub fn read_identity(&self) -> Result<Vec<u8>, ConfigErrors> {
+        std::fs::read(&self.tls.identity).map_err(|err| ConfigErrors::Unreadable {
+            path: self.tls.identity.clone(),
+            reason: err.to_string(),
+        })
+    }
+}
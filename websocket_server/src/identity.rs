@@ -0,0 +1,79 @@
+//! The server's TLS identity: the acceptor built from the one the config names, and throwaway ones for local development and integration tests. Browsers will still warn about a throwaway certificate until it is trusted by hand, it is signed by nobody but itself.
+
+use crate::config::ServerConfig;
+
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::x509::extension::SubjectAlternativeName;
+use openssl::x509::{X509NameBuilder, X509};
+use tokio_native_tls::native_tls::{self, Identity};
+
+use std::net::IpAddr;
+
+/// A pkcs12 archive with a fresh key and a self-signed certificate for the hosts, which can be either names or ip addresses. The first host is also the common name.
+pub fn generate_self_signed(hosts: &[String], days: u32, password: &str) -> Result<Vec<u8>, ErrorStack> {
+    let key = PKey::from_rsa(Rsa::generate(2048)?)?;
+
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(
+        Nid::COMMONNAME,
+        hosts.first().map(String::as_str).unwrap_or("localhost"),
+    )?;
+    let name = name.build();
+
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+
+    let serial = serial.to_asn1_integer()?;
+    let not_before = Asn1Time::days_from_now(0)?;
+    let not_after = Asn1Time::days_from_now(days)?;
+
+    let mut certificate = X509::builder()?;
+    certificate.set_version(2)?;
+    certificate.set_serial_number(&serial)?;
+    certificate.set_subject_name(&name)?;
+    certificate.set_issuer_name(&name)?;
+    certificate.set_pubkey(&key)?;
+    certificate.set_not_before(&not_before)?;
+    certificate.set_not_after(&not_after)?;
+
+    let mut alternative_names = SubjectAlternativeName::new();
+    for host in hosts {
+        if host.parse::<IpAddr>().is_ok() {
+            alternative_names.ip(host);
+        } else {
+            alternative_names.dns(host);
+        }
+    }
+    let alternative_names = alternative_names.build(&certificate.x509v3_context(None, None))?;
+    certificate.append_extension(alternative_names)?;
+    certificate.sign(&key, MessageDigest::sha256())?;
+    let certificate = certificate.build();
+
+    Pkcs12::builder()
+        .name("websocket_server")
+        .pkey(&key)
+        .cert(&certificate)
+        .build2(password)?
+        .to_der()
+}
+
+/// Accepts TLS connections with the identity the config names
+pub fn tls_acceptor(config: &ServerConfig) -> Result<tokio_native_tls::TlsAcceptor, String> {
+    let identity = config.read_identity().map_err(|err| err.to_string())?;
+    let password = config.tls.password.as_deref().unwrap_or_default();
+    let cert = Identity::from_pkcs12(&identity, password).map_err(|err| {
+        format!("{} is not a usable identity: {}", config.tls.identity.display(), err)
+    })?;
+
+    let acceptor = native_tls::TlsAcceptor::builder(cert)
+        .build()
+        .map_err(|err| err.to_string())?;
+    Ok(tokio_native_tls::TlsAcceptor::from(acceptor))
+}
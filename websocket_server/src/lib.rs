@@ -0,0 +1,5 @@
+//! Everything about the server that can be used and tested without serving: the command line, the config and the TLS identity. The websockets themselves are served by the binary.
+
+pub mod cli;
+pub mod config;
+pub mod identity;
@@ -1,11 +1,9 @@
-use linked_hash_set::LinkedHashSet;
 // use tokio::stream::StreamExt;
 use tokio::sync::mpsc;
 use tokio::{
-    net::{TcpListener, TcpStream},
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
     sync::mpsc::Receiver,
-    sync::mpsc::Sender,
-    sync::Mutex,
 };
 
 use futures_util::sink::SinkExt;
@@ -16,6 +14,8 @@ use tokio_stream::StreamExt;
 
 use tokio::time;
 
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::{HeaderValue, StatusCode};
 use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
 
 use std::{
@@ -25,17 +25,43 @@ use std::{
 
 // use tungstenite::Message;
 
-use log::info;
+use log::{error, info, warn};
 use tracing::{instrument, Level};
 
-use models::{Client, Command, EntityDetails, EntityTypes, PingStatus};
+use pairing::FriendSpace;
 
-use native_tls::Identity;
-use tokio_native_tls::native_tls;
+use models::{
+    Client, Codec, CodecErrors, Command, Effect, Entity, EntityDetails, EntityTypes, Envelope, Inbound,
+    PingTime, ProtocolError, RandomPairs, ServerState, MIN_PROTOCOL_VERSION,
+};
+
+use websocket_server::cli::{self, Invocation};
+use websocket_server::config::{MatchmakingStrategy, RoundConfig, ServerConfig};
+use websocket_server::identity::{self, tls_acceptor};
+
+/// How many conversations and positions friend-space keeps per client
+const FRIEND_SPACE_MEMORY: usize = 20;
+
+/// What a websocket is served over: a TLS stream, or a plain TCP stream in insecure mode
+trait ClientStream: AsyncRead + AsyncWrite + Unpin + Send + std::fmt::Debug + 'static {}
+impl<S: AsyncRead + AsyncWrite + Unpin + Send + std::fmt::Debug + 'static> ClientStream for S {}
+
+/// Json travels in text frames, the other codecs in binary frames. The envelope is encoded for the protocol version the client negotiated.
+fn encode_frame(codec: Codec, version: u32, envelope: &Envelope) -> Result<Message, CodecErrors> {
+    let encoded = envelope.encode_for(codec, version)?;
+
+    if codec.is_binary() {
+        Ok(Message::Binary(encoded))
+    } else {
+        Ok(Message::Text(
+            String::from_utf8(encoded).expect("json is always valid utf-8"),
+        ))
+    }
+}
 
 #[instrument()]
 async fn send_message(
-    stream: &mut WebSocketStream<tokio_native_tls::TlsStream<TcpStream>>,
+    stream: &mut WebSocketStream<impl ClientStream>,
     message: tokio_tungstenite::tungstenite::Message,
 ) {
     match message {
@@ -122,31 +148,63 @@ async fn send_message(
 #[instrument]
 async fn establish_and_maintain_each_client_ws_connection(
     tx_server_state_manager: mpsc::Sender<(Envelope, Option<mpsc::Sender<Envelope>>)>,
-
-    stream: tokio_native_tls::TlsStream<TcpStream>,
+    server: EntityDetails,
+    stream: impl ClientStream,
     peer_address: SocketAddr,
 ) {
     let (goes_to_specific_ws_client_tx, mut goes_to_specific_ws_client_rx) =
         mpsc::channel::<Envelope>(10);
 
-    let address: Option<std::net::SocketAddr> = Some(peer_address);
-
-    let this_client = Client {
-        username: None,
-        email: None,
-        user_id: uuid::Uuid::new_v4(),
-        current_socket_addr: address,
-        status: Some(models::Status::WaitingForPartner),
-        ping_status: PingStatus::NeverPinged,
+    let this_client = Client::from_user_id(uuid::Uuid::new_v4());
+    // Whatever the client claims, everything arriving over this websocket was sent by it
+    let this_entity = EntityDetails::Client(this_client.user_id, Some(peer_address));
+
+    // The codec is settled during the websocket upgrade, see models::codec
+    let mut codec = Codec::default();
+    // The refusal is tungstenite's own response type, however large it is
+    #[allow(clippy::result_large_err)]
+    let pick_codec = |request: &Request, mut response: Response| {
+        let offered = request
+            .headers()
+            .get("Sec-WebSocket-Protocol")
+            .map(|offered| offered.to_str().unwrap_or_default());
+
+        match Codec::negotiate(offered) {
+            Some(picked) => {
+                codec = picked;
+                if offered.is_some() {
+                    response.headers_mut().insert(
+                        "Sec-WebSocket-Protocol",
+                        HeaderValue::from_static(picked.subprotocol()),
+                    );
+                }
+                Ok(response)
+            }
+            None => {
+                let mut refusal = ErrorResponse::new(Some(format!(
+                    "None of the offered subprotocols ({:?}) is a codec this server speaks",
+                    offered
+                )));
+                *refusal.status_mut() = StatusCode::BAD_REQUEST;
+                Err(refusal)
+            }
+        }
     };
 
-    let mut ws_stream = tokio_tungstenite::accept_async(stream)
-        .await
-        .expect("failed to accept websocket.");
+    let mut ws_stream = match tokio_tungstenite::accept_hdr_async(stream, pick_codec).await {
+        Ok(ws_stream) => ws_stream,
+        Err(err) => {
+            info!("Couldn't accept the websocket from {:?}: {:?}", peer_address, err);
+            return;
+        }
+    };
+    info!("{:?} talks {}", peer_address, codec.subprotocol());
+    // Clients that never send a handshake speak the protocol from before the handshake existed
+    let mut version = MIN_PROTOCOL_VERSION;
 
     let envelope = Envelope::new(
-        EntityDetails::Server,
-        EntityDetails::Server,
+        server,
+        server,
         None,
         Command::ServerInitiated(this_client.clone()),
     );
@@ -183,15 +241,28 @@ async fn establish_and_maintain_each_client_ws_connection(
             control_message = goes_to_specific_ws_client_rx.recv() => {
             match control_message {
                 Some(control_message) => {
-                    match ws_stream.send(tokio_tungstenite::tungstenite::Message::Binary(control_message.clone().serialize())).await {
-                        Ok(_) => {info!("successfully received the control message!: {:?}", control_message.clone());
+                    // Only the server's own answer to the handshake counts, not one relayed from another client
+                    match &control_message.command {
+                        Command::HandshakeAccepted(negotiated) if control_message.sender.entity_detail == server => {
+                            version = negotiated.version;
+                        }
+                        _ => {}
+                    }
+                    match encode_frame(codec, version, &control_message) {
+                        Ok(frame) => match ws_stream.send(frame).await {
+                            Ok(_) => {info!("successfully received the control message!: {:?}", control_message.clone());
 
+                            },
+                            Err(err) => {info!("Couldn't send the message properly due to the following err: {:?}", err)}
                         },
-                        Err(err) => {info!("Couldn't send the message properly due to the following err: {:?}", err)}
+                        Err(err) => {info!("Couldn't encode {:?}: {}", control_message, err)}
                     }
                 }
                 None => {
-                    info!("Somehow received a None error message :x");
+                    info!("The server dropped this connection, closing the websocket");
+                    if let Err(err) = ws_stream.close(None).await {
+                        info!("Couldn't close the websocket properly due to the following err: {:?}", err);
+                    }
                     return
                 }
 
@@ -205,12 +276,26 @@ async fn establish_and_maintain_each_client_ws_connection(
             val = ws_stream.try_next() => {
                 match val {
                     Ok(value) => {
-                        if value.is_some() {
-                        match value.unwrap() {
-                                Message::Text(text) => {info!("received text: {:?}", text);},
+                        if let Some(value) = value {
+                        match value {
+                                Message::Text(text) => {
+                                    match Codec::Json.decode::<Envelope>(text.as_bytes()) {
+                                        Ok(mut control_message) => {
+                                            control_message.sender = Entity::new(this_entity);
+                                            if let Err(err) = tx_server_state_manager.send((control_message, None)).await {
+                                                info!("Received the following error: {:?}. This is an error with trying to connect to the server state manager... Not sure how to recover from this one :[", err);
+                                            }
+                                        },
+                                        Err(oh_boy) => {
+                                            info!("Error receiving message from ws client: {}", oh_boy);
+                                            report_malformed_payload(&mut ws_stream, codec, version, server, this_client.user_id, oh_boy).await;
+                                        }
+                                    }
+                                },
                                 Message::Binary(bin) => {
-                                    match Envelope::deserialize(&bin) {
-                                        Ok(control_message) => {
+                                    match codec.decode::<Envelope>(&bin) {
+                                        Ok(mut control_message) => {
+                                            control_message.sender = Entity::new(this_entity);
                                             match tx_server_state_manager.send((control_message, None)).await
                                             {
                                                 Ok(_) => {},
@@ -218,7 +303,10 @@ async fn establish_and_maintain_each_client_ws_connection(
 
                                             }
                                         },
-                                        Err(oh_boy) => {info!("Error receiving message from ws client: {:?}", oh_boy)}
+                                        Err(oh_boy) => {
+                                            info!("Error receiving message from ws client: {}", oh_boy);
+                                            report_malformed_payload(&mut ws_stream, codec, version, server, this_client.user_id, oh_boy).await;
+                                        }
                                     }
                                 },
                                 Message::Close(_reason) => {
@@ -226,10 +314,10 @@ async fn establish_and_maintain_each_client_ws_connection(
 
 
                                     let envelope = Envelope::new(
-                                        EntityDetails::Client(this_client.user_id.clone()),
-                                        EntityDetails::Server,
+                                        this_entity,
+                                        server,
                                         None,
-                                        Command::ClosedConnection(this_client.user_id.clone())
+                                        Command::ClosedConnection(this_client.user_id)
                                     );
 
                                     match tx_server_state_manager
@@ -262,10 +350,10 @@ async fn establish_and_maintain_each_client_ws_connection(
                         // info!("The client is trying to close the connection for the following reason: {:?}", reason);
 
                     let envelope = Envelope::new(
-                        EntityDetails::Client(this_client.user_id.clone()),
-                        EntityDetails::Server,
+                        this_entity,
+                        server,
                         None,
-                        Command::ClosedConnection(this_client.user_id.clone())
+                        Command::ClosedConnection(this_client.user_id)
                     );
 
                     match tx_server_state_manager
@@ -288,33 +376,135 @@ async fn establish_and_maintain_each_client_ws_connection(
     }
 }
 
-#[instrument]
+/// Undecodable frames never reach the state manager, so the connection answers them itself
+async fn report_malformed_payload(
+    ws_stream: &mut WebSocketStream<impl ClientStream>,
+    codec: Codec,
+    version: u32,
+    server: EntityDetails,
+    client: uuid::Uuid,
+    err: CodecErrors,
+) {
+    let envelope = Envelope::new(
+        server,
+        EntityDetails::Client(client, None),
+        None,
+        Command::Error(ProtocolError::MalformedPayload(err.to_string())),
+    );
+
+    match encode_frame(codec, version, &envelope) {
+        Ok(frame) => send_message(ws_stream, frame).await,
+        Err(err) => info!("Couldn't encode the error for the client: {}", err),
+    }
+}
+
+/// What the state manager makes of an envelope. None when the server is neither its receiver nor its intermediary.
+fn inbound(envelope: &Envelope) -> Option<Inbound> {
+    let sender = match envelope.sender.entity_detail {
+        EntityDetails::Client(uuid, _) => Some(uuid),
+        _ => None,
+    };
+
+    if envelope.receiver.entity_type == EntityTypes::Server {
+        return Some(Inbound {
+            sender,
+            receiver: None,
+            command: envelope.command.clone(),
+        });
+    }
+
+    match (&envelope.intermediary, envelope.receiver.entity_detail) {
+        (Some(intermediary), EntityDetails::Client(receiver, _)) if intermediary.entity_type == EntityTypes::Server => {
+            Some(Inbound {
+                sender,
+                receiver: Some(receiver),
+                command: envelope.command.clone(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Sends the command to the client's connection. Returns false when the connection is gone.
 async fn send_command_to_client_by_uuid(
+    server: EntityDetails,
     client: uuid::Uuid,
     command: Command,
-    online_connections: &mut HashMap<uuid::Uuid, (Client, mpsc::Sender<Envelope>)>,
-) {
-    //let mut online_connections = HashMap::<uuid::Uuid, (Client, mpsc::Sender<Envelope>)>::new();
+    connections: &HashMap<uuid::Uuid, mpsc::Sender<Envelope>>,
+) -> bool {
     let envelope = Envelope::new(
-        EntityDetails::Server,
-        EntityDetails::Client(client.clone()),
+        server,
+        EntityDetails::Client(client, None),
         None,
         command,
     );
 
-    let (_client, connection_channel) = online_connections
-        .get_mut(&client)
-        .expect("couldn't find client in online connections");
+    match connections.get(&client) {
+        Some(connection_channel) => match connection_channel.send(envelope).await {
+            Ok(_) => true,
+            Err(err) => {
+                info!("Received the following error: {:?}", err);
+                false
+            }
+        },
+        None => {
+            info!("There is no connection to {} anymore", client);
+            false
+        }
+    }
+}
 
-    match connection_channel.clone().send(envelope).await {
-        Ok(_) => {}
-        Err(err) => info!("Received the following error: {:?}", err),
+/// Carries out the effects in order. Clients whose connection turned out to be gone are returned so that their connection can be closed in the state as well.
+async fn apply_effects(
+    server: EntityDetails,
+    effects: Vec<Effect>,
+    envelope: Option<&Envelope>,
+    connections: &mut HashMap<uuid::Uuid, mpsc::Sender<Envelope>>,
+) -> Vec<uuid::Uuid> {
+    let mut gone = vec![];
+
+    for effect in effects {
+        match effect {
+            Effect::Send { to, command } => {
+                if !send_command_to_client_by_uuid(server, to, command, connections).await {
+                    gone.push(to);
+                }
+            }
+            Effect::Broadcast { to, command } => {
+                for client in to {
+                    if !send_command_to_client_by_uuid(server, client, command.clone(), connections).await {
+                        gone.push(client);
+                    }
+                }
+            }
+            Effect::Relay { next_hop, .. } => {
+                // Relays only come out of an envelope, which is passed on unchanged
+                let relayed = match (envelope, connections.get(&next_hop)) {
+                    (Some(envelope), Some(connection_channel)) => connection_channel.send(envelope.clone()).await.is_ok(),
+                    _ => false,
+                };
+                if relayed {
+                    info!("Relayed a message to {}", next_hop);
+                } else {
+                    info!("Couldn't relay the message to {}", next_hop);
+                    gone.push(next_hop);
+                }
+            }
+            Effect::Close(client) => {
+                // Dropping the sender makes the connection task close the websocket once everything before it has gone out
+                connections.remove(&client);
+            }
+        }
     }
+
+    gone.sort();
+    gone.dedup();
+    gone
 }
 
 #[instrument]
-async fn game_loop(status_processer_notifier: tokio::sync::mpsc::Sender<u64>, round_interval: u64) {
-    let mut interval = time::interval(time::Duration::from_secs(round_interval));
+async fn game_loop(status_processer_notifier: tokio::sync::mpsc::Sender<u64>, round_interval: time::Duration) {
+    let mut interval = time::interval(round_interval);
     let mut round_number: u64 = 1;
 
     loop {
@@ -323,449 +513,86 @@ async fn game_loop(status_processer_notifier: tokio::sync::mpsc::Sender<u64>, ro
             .send(round_number)
             .await
             .expect("how could you fail?! Just send the new round notification");
-        round_number = round_number + 1;
+        round_number += 1;
     }
 }
 
-#[instrument]
+/// Owns the connections to the clients and feeds everything that happens into a models::ServerState, which decides what to do about it.
+#[instrument(skip(state))]
 async fn server_global_state_manager(
     mut global_state_update_transceiver: Receiver<(Envelope, Option<mpsc::Sender<Envelope>>)>,
-    global_state_update_sender: Sender<(Envelope, Option<mpsc::Sender<Envelope>>)>,
+    mut state: ServerState,
+    server: EntityDetails,
+    round_interval: time::Duration,
 ) {
-    // the global_state_update_sender is the mechanism by which the sever gives itself commands
-
-    let online_connections =
-        Mutex::new(HashMap::<uuid::Uuid, (Client, mpsc::Sender<Envelope>)>::new());
+    let mut connections = HashMap::<uuid::Uuid, mpsc::Sender<Envelope>>::new();
 
     let (status_processer_notifier_tx, mut status_processer_notifier_rx) = mpsc::channel::<u64>(10);
 
-    tokio::spawn(async move { game_loop(status_processer_notifier_tx, 20).await });
-
-    let current_round = 0;
+    tokio::spawn(async move { game_loop(status_processer_notifier_tx, round_interval).await });
 
     loop {
-        tokio::select! {
-
-                                            // This is the game time tracker... keeps track of the current round. Can be used for performing system-wide periodic behavior
-                                            game_notifier = status_processer_notifier_rx.recv() => {
-
-                                                match game_notifier {
-                                                    Some(current_round) => {
-                                                        let mut online_connections = online_connections.lock().await;
-
-                                                        let ping_every_x_rounds : u64 = 2;
-                                                        let remove_after_x_rounds : u64 = 4;
-
-
-                                                        let ( clients,  _client_connections) : (LinkedHashSet<Client>, Vec<mpsc::Sender<Envelope>>) = online_connections.values().cloned().unzip();
-
-                                                        let mut ping_list = HashMap::<uuid::Uuid, Envelope>::new();
-
-                                                        for client in clients {
-                                                            match client.ping_status {
-                                                                PingStatus::Pinged(_round_number) => {
-                                                                    info!("This client {:#?} seems unresponsive :[... put the logic to remove them from the list here!", client);
-
-                                                                    // if (current_round - round_number )> remove_after_x_rounds{
-                                                                    //     let ping = Envelope::new(
-                                                                    //         EntityDetails::Server,
-                                                                    //         EntityDetails::Client(client.user_id),
-                                                                    //         None,
-                                                                    //         Command::Ping(client.user_id, current_round)
-                                                                    //     );
-                                                                    //     ping_list.insert(client.user_id, ping);
-                                                                    // }
-
-
-                                                                }
-                                                                PingStatus::NeverPinged => {
-                                                                    let ping = Envelope::new(
-                                                                        EntityDetails::Server,
-                                                                        EntityDetails::Client(client.user_id),
-                                                                        None,
-                                                                        Command::Ping(client.user_id, current_round)
-                                                                    );
-
-                                                                    ping_list.insert(client.user_id, ping);
-
-
-                                                                }
-                                                                PingStatus::Ponged(round_number) => {
-                                                                    if (current_round - round_number )> ping_every_x_rounds{
-                                                                        let ping = Envelope::new(
-                                                                            EntityDetails::Server,
-                                                                            EntityDetails::Client(client.user_id),
-                                                                            None,
-                                                                            Command::Ping(client.user_id, current_round)
-                                                                        );
-                                                                        ping_list.insert(client.user_id, ping);
-                                                                    }
-                                                                }
-                                                            }
-                                                        }
-
-
-                                                        for (client_uuid, ping_envelope) in ping_list {
-                                                            match online_connections.get_mut(&client_uuid) {
-                                                                Some((client, client_sender)) => {
-
-                                                                    match client_sender.send(ping_envelope).await {
-                                                                        Ok(_) => {
-                                                                            client.ping_status = PingStatus::Pinged(current_round);
-                                                                        }
-                                                                        Err(err) => {
-                                                                            info!("Was not able to send the ping to the client. Recieved the following error: {:#?}", err);
-                                                                        }
-
-                                                                    }
-                                                                }
-                                                                None => {}
-                        }
-                                                        }
-
-
-                                                    }
-                                                    None => {info!("none...");}
-                                                }
-
-                                            },
-
-                                            some_connection = global_state_update_transceiver.recv() => {
-                                                if let Some((control_message, client_controller_channel) ) = some_connection {
-
-                                            info!("Received connection in status_manager");
-                                            let first_clone = control_message.clone();
-
-                                                if control_message.receiver.entity_type == EntityTypes::Server {
-
-
-                                                    match control_message.command {
-                                                        Command::BroadcastUpdate => {
-                                                            let mut online_connections = online_connections.lock().await;
-
-                                                            let ( clients,  _client_connections) : (LinkedHashSet<Client>, Vec<mpsc::Sender<Envelope>>) = online_connections.values().cloned().unzip();
-
-                                                            let clients = clients.clone();
-                                                            let clients_cloned = clients.clone();
-
-
-
-                                                            let keys : LinkedHashSet<uuid::Uuid> =  online_connections.keys().cloned().collect();
-
-
-                                                            let keys_clone : LinkedHashSet<uuid::Uuid> = keys.clone().to_owned();
-
-
-                                                            let hashmap = keys_clone.into_iter().zip(clients_cloned).collect::<HashMap<uuid::Uuid, Client>>();
-
-                                                            for uuid in keys  {
-                                                                let hashmap = hashmap.clone();
-                                                                send_command_to_client_by_uuid(uuid.clone(), Command::OnlineClients(hashmap, current_round), &mut online_connections).await
-                                                            }
-
-                                                        }
-                                                        Command::EndCall(person_a, person_b) => {
-                                                            let mut online_connections = online_connections.lock().await;
-                                                            match online_connections.get_mut(&person_a) {
-                                                                Some((client,_)) => {
-                                                                    client.status = Some(models::Status::WaitingForPartner);
-                                                                }
-                                                                None => {
-                                                                    panic!();
-                                                                }
-                                                            };
-                                                            match online_connections.get_mut(&person_b) {
-                                                                Some((client,_)) => {
-                                                                    client.status = Some(models::Status::WaitingForPartner);
-                                                                    let update = Envelope::new(
-                                                                        EntityDetails::Server,
-                                                                        EntityDetails::Server,
-                                                                        None,
-                                                                        Command::BroadcastUpdate
-                                                                    );
-        
-        
-                                                                    match global_state_update_sender.send((update,None)).await {
-            Ok(_) => {info!("broadcasting the client is in fact ending the call!")}
-            Err(_) => {}
-        }
-                                                                }
-                                                                None => {
-                                                                    panic!();
-                                                                }
-                                                            };
-
-                                                            
-
-                                                                                           }
-                                                        Command::InCall(initiator, receiver) => {
-                                                            let mut online_connections = online_connections.lock().await;
-                                                            match online_connections.get_mut(&initiator) {
-                                                                Some((client,_)) => {
-                                                                    client.status = Some(models::Status::InCall(initiator, receiver));
-                                                                }
-                                                                None => {
-                                                                    panic!();
-                                                                }
-                                                            };
-                                                            match online_connections.get_mut(&receiver) {
-                                                                Some((client,_)) => {
-                                                                    client.status = Some(models::Status::InCall(initiator, receiver));
-                                                                }
-                                                                None => {
-                                                                    panic!();
-                                                                }
-                                                            };
-
-                                                            let update = Envelope::new(
-                                                                EntityDetails::Server,
-                                                                EntityDetails::Server,
-                                                                None,
-                                                                Command::BroadcastUpdate
-                                                            );
-
-
-                                                            global_state_update_sender.send((update,None)).await.unwrap();
-                                                        }
-                                                        Command::UpdateClient(client) => {
-                                                            let mut online_connections = online_connections.lock().await;
-                                                            match online_connections.get_mut(&client.user_id) {
-                                                                Some((online_client,_)) => {
-                                                                    online_client.update(client);
-
-                                                                    let update = Envelope::new(
-                                                                        EntityDetails::Server,
-                                                                        EntityDetails::Server,
-                                                                        None,
-                                                                        Command::BroadcastUpdate
-                                                                    );
-
-
-                                                                    global_state_update_sender.send((update,None)).await.unwrap();
-                                                                }
-                                                                None => {
-                                                                    panic!();
-                                                                }
-                                                            };
-                                                        }
-                                                        Command::Ping(_client, _round) => {
-                                                            info!("The server should not be implementing the ping...");
-
-                                                        }
-                                                        Command::Pong(client, round) => {
-                                                            let mut online_connections = online_connections.lock().await;
-                                                            match online_connections.get_mut(&client){
-                            Some((client, _client_sender)) => {
-                                client.ping_status = PingStatus::Ponged(round);
-
-                            }
-                            None => {
-                                info!("This is not good... The client is responding to a ping but it has already been removed from the online_client list");
-                            }
-                        }
-                                                        }
-                                                        Command::IceCandidate(_) => {
-                                                            info!("The server should not be receiving ice candidates.");
-                                                        }
-
-
-                                                Command::Error(error) => {
-                                                    info!("Received the following error: {:?}", error);
-                                                }
-
-                                                Command::SdpRequest(sdp) => {
-                                                    info!("Received SdpRequest message with sdp: {:?}",sdp);
-                                                }
-                                                Command::SdpResponse(sdp) => {
-                                                    info!("Received SdpRequest message with sdp: {:?}",sdp);
-                                                }
-                                                Command::ServerInitiated(client) => {
-                                                    let client_id = client.user_id;
-
-                                                    let mut online_connections = online_connections.lock().await;
-
-
-
-
-                                                    if let Some( client_connection) = client_controller_channel {
-
-
-                                                        match online_connections.insert(client_id, (client.clone(), client_connection.clone())) {
-                    Some(_) => {}
+        let mut gone = tokio::select! {
+            // This is the game time tracker... keeps track of the current round. Can be used for performing system-wide periodic behavior
+            game_notifier = status_processer_notifier_rx.recv() => {
+                match game_notifier {
+                    Some(round) => {
+                        apply_effects(server, state.tick(round), None, &mut connections).await
+                    }
                     None => {
-                        let envelope = Envelope::new(
-                            EntityDetails::Server,
-                            EntityDetails::Client(client.user_id.clone()),
-                            None,
-                            Command::ServerInitiated(client.clone())
-                        );
-
-                        match client_connection.send(envelope).await
-                        {
-                            Ok(_) => {
-                                info!("Initialized the client");
-
-
-                            },
-                            Err(err) => {info!("Received the following error: {:?}", err)}
-
-                        }
+                        info!("none...");
+                        continue;
                     }
                 }
+            },
 
+            some_connection = global_state_update_transceiver.recv() => {
+                let (envelope, client_controller_channel) = match some_connection {
+                    Some(some_connection) => some_connection,
+                    None => continue,
+                };
 
+                let message = match inbound(&envelope) {
+                    Some(message) => message,
+                    None => {
+                        info!("When passing messages for the server, be sure to make sure that the server is either the receiver or the intermediary...");
+                        info!("The following was received by the server but not addressed to the server: {:?}", envelope);
+                        continue;
+                    }
+                };
 
+                if let (Command::ServerInitiated(client), Some(client_connection)) = (&message.command, client_controller_channel) {
+                    connections.insert(client.user_id, client_connection);
+                }
 
-
-
-                                                    }
-
-                                                }
-
-                                                Command::OnlineClients(_clients, _round_number) => {
-                                                    // oof just not useful.
-                                                }
-                                                
-
-                                                Command::ClosedConnection(client) => {
-
-                                                    let mut online_connections = online_connections.lock().await;
-
-
-                                                    info!("Before closing the connection the online connections are: {:?}", online_connections.clone());
-                                                    {
-                                                    match online_connections.remove_entry(&client){
-                                                        Some((_uuid,(_client, _channel))) => {
-                                                            let update = Envelope::new(
-                                                                EntityDetails::Server,
-                                                                EntityDetails::Server,
-                                                                None,
-                                                                Command::BroadcastUpdate
-                                                            );
-        
-        
-                                                            global_state_update_sender.send((update,None)).await.unwrap();
-                                                        },
-                                                        None => {
-
-                                                        }
-                                                    }
-                                }
-                                                    info!("After closing the connection the online_connections are: {:?}", online_connections.clone());
-
-                                                    
-
-                                                    }
-
-                                                }
-
-                                            }
-                                                else if control_message.intermediary.is_some() {
-                                                    info!("the server is acting as an intermediary for the following message: {:?}", first_clone.clone());
-                                                    let intermediary = control_message.intermediary.unwrap();
-                                                    if intermediary.entity_type == EntityTypes::Server {
-
-                                                        //pass the message to the receiver
-                                                        if let EntityDetails::Client(receiver_uuid) = control_message.receiver.entity_detail {
-
-                                                            let mut online_connections = online_connections.lock().await;
-
-
-                                                            match online_connections.get_mut(&receiver_uuid){
-                                                                Some((client, client_channel)) => {
-                                                                    info!("Trying to re-route the message to the appropriate client.");
-                                                                    match client_channel.send(first_clone.clone()).await
-                                                                    {
-                                                                        Ok(_) => {info!("sent message!");
-                                                                        let second_clone = first_clone.clone();
-
-
-                                                                        match second_clone.command {
-                                                                            Command::EndCall(person_a,person_b) => {
-
-                                                                                let end_call = Envelope::new(
-                                                                                    EntityDetails::Server,
-                                                                                    EntityDetails::Server,
-                                                                                    None,
-                                                                                    Command::InCall(person_a, person_b)
-                                                                                );
-
-                                                                                match global_state_update_sender.send((end_call, None)).await {
-            Ok(_) => {
-                info!("sending message to end call to myself...")
-            }
-            Err(err) => {
-                info!("Received the following error {:#?}", err);
+                apply_effects(server, state.handle(message), Some(&envelope), &mut connections).await
             }
+        };
+
+        // Losing a connection can lose more of them, e.g. through the broadcast it causes
+        while let Some(client) = gone.pop() {
+            info!("The connection to {} is gone, removing them from the online clients", client);
+            let effects = state.handle(Inbound::from_server(Command::ClosedConnection(client)));
+            connections.remove(&client);
+            gone.extend(apply_effects(server, effects, None, &mut connections).await);
         }
-                                                                            }
-                                                                            Command::SdpRequest(_sdp) => {
-                                                                                let sender = first_clone.sender;
-
-                                                                                let receiver = first_clone.receiver;
-
-                                                                                let make_in_call = Envelope::new(
-                                                                                    EntityDetails::Server,
-                                                                                    EntityDetails::Server,
-                                                                                    None,
-                                                                                    Command::InCall(sender.get_uuid().unwrap(), receiver.get_uuid().unwrap())
-                                                                                );
-
-                                                                                global_state_update_sender.send((make_in_call, None)).await.expect("FUCK... FUCK FUCK FUCK DON'T BREAK PLZ");
-
-
-                                                                            }
-                                                                            _ => {
-                                                                                //every other command that doesn't need to be covered :]
-                                                                            }
-                                                                        }
-                                                                    },
-                                                                        Err(err) => {
-                                                                            info!("Error: {:?}",  err);
-                                                                            info!("Need to remove the client from the list of online clients");
-
-                                                                            let connection_closed = Envelope::new(
-                                                                                EntityDetails::Server,
-                                                                                EntityDetails::Server,
-                                                                                None,
-                                                                                Command::ClosedConnection(client.user_id)
-                                                                            );
-
-                                                                            global_state_update_sender.send((connection_closed, None)).await.expect("This should absolutely not fail... ^_^ I'm so sorry I failed you future self.");
-                                                                        }
-                                                                    }
-                                                                }
-                                                                None => {
-
-                                                                    info!("Need to remove the client from the list of online clients");
-
-                                                                    let connection_closed = Envelope::new(
-                                                                        EntityDetails::Server,
-                                                                        EntityDetails::Server,
-                                                                        None,
-                                                                        Command::ClosedConnection(receiver_uuid)
-                                                                    );
-
-                                                                    global_state_update_sender.send((connection_closed, None)).await.expect("This should absolutely not fail... ^_^ I'm so sorry I failed you future self.");
-                                                                }
-                                                            }
-                                                        }
-
-
-
-                                                    }
-                                                }
-                                                else {
-                                                    info!("When passing messages for the server, be sure to make sure that the server is either the receiver or the intermediary...");
-                                                    info!("The following was received by the server but not addressed to the server: {:?}", first_clone);
-                                                }
-                                                }
-                                            }
-
-
+    }
+}
 
-                                        }
+/// The state the server starts out with: nobody online yet, pinged and paired as the config says
+fn server_state(rounds: &RoundConfig) -> ServerState {
+    let mut state = ServerState::new(
+        PingTime::Every(rounds.ping_every_x_rounds),
+        rounds.remove_after_missed_pings,
+        rounds.max_messages_per_round,
+    );
+    match rounds.matchmaking {
+        MatchmakingStrategy::Off => {}
+        MatchmakingStrategy::Random => state.matchmake_with(Box::new(RandomPairs::new())),
+        // Clients leave friend-space when they go offline, and only their latest partners and positions are kept while they are online
+        MatchmakingStrategy::Distance => state.matchmake_with(Box::new(FriendSpace::new().remembering(FRIEND_SPACE_MEMORY))),
     }
+    state
 }
 
 #[tokio::main]
@@ -778,39 +605,109 @@ async fn main() {
         //.with_span_events(FmtSpan::FULL)
         .init();
 
-    let listener = TcpListener::bind("0.0.0.0:2096")
+    let insecure = match cli::parse(std::env::args().skip(1)) {
+        Ok(Invocation::Serve { insecure }) => insecure,
+        Ok(Invocation::GenerateIdentity { path, password, hosts, days }) => {
+            let written = identity::generate_self_signed(&hosts, days, &password)
+                .map_err(|err| err.to_string())
+                .and_then(|der| std::fs::write(&path, der).map_err(|err| err.to_string()));
+            match written {
+                Ok(()) => {
+                    println!(
+                        "Wrote a self-signed identity for {:?} to {}, serve with it through\n\n    WEBSOCKET_SERVER_TLS_IDENTITY={} WEBSOCKET_SERVER_TLS_PASSWORD={} websocket_server",
+                        hosts,
+                        path.display(),
+                        path.display(),
+                        password
+                    );
+                    return;
+                }
+                Err(err) => {
+                    error!("Could not write the identity to {}: {}", path.display(), err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Ok(Invocation::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let config = ServerConfig::read().and_then(|mut config| {
+        config.insecure |= insecure;
+        config.validate().map(|_| config)
+    });
+    let config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Not starting the server: {}", err);
+            std::process::exit(1);
+        }
+    };
+    info!("Starting the server with {:?}", config.rounds);
+
+    let tls_acceptor = if config.insecure {
+        warn!("Serving ws:// without TLS on {}, never do this outside of local development", config.bind_address);
+        None
+    } else {
+        match tls_acceptor(&config) {
+            Ok(tls_acceptor) => Some(tls_acceptor),
+            Err(err) => {
+                error!("Not starting the server: {}", err);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let listener = TcpListener::bind(config.bind_address)
         .await
         .expect("Couldn't bind to server address!");
 
     let (global_state_updater_tx, global_state_updater_rx) =
         mpsc::channel::<(Envelope, Option<mpsc::Sender<Envelope>>)>(10);
 
-    let global_state_updater_tx_clone = global_state_updater_tx.clone();
-
-    tokio::spawn(async {
+    let state = server_state(&config.rounds);
+    let server = EntityDetails::Server(state.uuid(), config.bind_address);
+    let round_interval = config.rounds.round_interval();
+    tokio::spawn(async move {
         info!("setting up a status manager");
-        server_global_state_manager(global_state_updater_rx, global_state_updater_tx_clone).await
+        server_global_state_manager(global_state_updater_rx, state, server, round_interval).await
     });
 
-    let der = include_bytes!("../certificate.p12");
-    let cert = Identity::from_pkcs12(der, "elliot").expect("identity to work..");
-    let tls_acceptor = tokio_native_tls::TlsAcceptor::from(
-        native_tls::TlsAcceptor::builder(cert).build().unwrap(),
-    );
-
     loop {
-        let tls_acceptor = tls_acceptor.clone();
         let (stream, remote_addr) = listener.accept().await.unwrap();
 
         let global_state_updater_tx_clone = global_state_updater_tx.clone();
 
         info!("Accepted connection from {}", remote_addr);
 
+        let tls_acceptor = match &tls_acceptor {
+            Some(tls_acceptor) => tls_acceptor.clone(),
+            None => {
+                tokio::spawn(async move {
+                    establish_and_maintain_each_client_ws_connection(
+                        global_state_updater_tx_clone,
+                        server,
+                        stream,
+                        remote_addr,
+                    )
+                    .await
+                });
+                continue;
+            }
+        };
+
         match tls_acceptor.accept(stream).await {
             Ok(tls_stream) => {
                 tokio::spawn(async move {
                     establish_and_maintain_each_client_ws_connection(
                         global_state_updater_tx_clone,
+                        server,
                         tls_stream,
                         remote_addr,
                     )
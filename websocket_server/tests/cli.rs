@@ -0,0 +1,56 @@
+use websocket_server::cli::{parse, Invocation, DEV_IDENTITY_PASSWORD};
+
+use std::path::PathBuf;
+
+fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(String::from).collect()
+}
+
+#[test]
+fn serving_is_the_default_and_insecure_is_a_flag() {
+    assert_eq!(parse(args("")), Ok(Invocation::Serve { insecure: false }));
+    assert_eq!(parse(args("--insecure")), Ok(Invocation::Serve { insecure: true }));
+    assert_eq!(parse(args("--help")), Ok(Invocation::Help));
+    assert_eq!(parse(args("help")), Ok(Invocation::Help));
+}
+
+#[test]
+fn generate_identity_has_defaults_for_everything() {
+    assert_eq!(
+        parse(args("generate-identity")),
+        Ok(Invocation::GenerateIdentity {
+            path: PathBuf::from("dev-identity.p12"),
+            password: DEV_IDENTITY_PASSWORD.to_string(),
+            hosts: args("localhost 127.0.0.1 ::1"),
+            days: 30,
+        })
+    );
+}
+
+#[test]
+fn generate_identity_flags_can_come_in_any_order() {
+    assert_eq!(
+        parse(args(
+            "generate-identity --host example.test ci.p12 --days 2 --password secret --host 10.0.0.1"
+        )),
+        Ok(Invocation::GenerateIdentity {
+            path: PathBuf::from("ci.p12"),
+            password: "secret".to_string(),
+            hosts: args("localhost 127.0.0.1 ::1 example.test 10.0.0.1"),
+            days: 2,
+        })
+    );
+}
+
+#[test]
+fn mistakes_are_explained() {
+    let error = |line: &str| parse(args(line)).unwrap_err();
+
+    assert_eq!(error("serve"), "unknown command \"serve\"");
+    assert_eq!(error("--insecure please"), "unexpected argument \"please\"");
+    assert_eq!(error("generate-identity --days"), "--days needs a value");
+    assert_eq!(error("generate-identity --days soon"), "--days has to be a number of days");
+    assert_eq!(error("generate-identity --password"), "--password needs a value");
+    assert_eq!(error("generate-identity --force"), "unknown option --force");
+    assert_eq!(error("generate-identity a.p12 b.p12"), "unexpected argument \"b.p12\"");
+}
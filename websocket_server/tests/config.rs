@@ -0,0 +1,140 @@
+use uuid::Uuid;
+use websocket_server::config::{ConfigErrors, MatchmakingStrategy, RoundConfig, ServerConfig};
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// A config file with the given contents, in a fresh spot of the temporary directory
+fn config_file(contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("websocket_server-{}.toml", Uuid::new_v4()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn environment(variables: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let variables: HashMap<String, String> = variables
+        .iter()
+        .map(|(variable, value)| (variable.to_string(), value.to_string()))
+        .collect();
+    move |variable| variables.get(variable).cloned()
+}
+
+#[test]
+fn overrides_win_over_the_file_and_the_rest_keeps_its_defaults() {
+    let path = config_file("bind_address = \"127.0.0.1:9000\"\n\n[rounds]\nround_seconds = 5\nmatchmaking = \"random\"\n");
+    let mut config = ServerConfig::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(config.bind_address, "127.0.0.1:9000".parse().unwrap());
+    assert_eq!(config.rounds.ping_every_x_rounds, RoundConfig::default().ping_every_x_rounds);
+
+    config
+        .apply_overrides(environment(&[
+            ("WEBSOCKET_SERVER_BIND_ADDRESS", "0.0.0.0:2097"),
+            ("WEBSOCKET_SERVER_INSECURE", "true"),
+            ("WEBSOCKET_SERVER_TLS_IDENTITY", "elsewhere.p12"),
+            ("WEBSOCKET_SERVER_TLS_PASSWORD", "hunter2"),
+            ("WEBSOCKET_SERVER_ROUND_SECONDS", "30"),
+            ("WEBSOCKET_SERVER_PING_EVERY_X_ROUNDS", "3"),
+            ("WEBSOCKET_SERVER_REMOVE_AFTER_MISSED_PINGS", "4"),
+            ("WEBSOCKET_SERVER_MAX_MESSAGES_PER_ROUND", "100"),
+            ("WEBSOCKET_SERVER_MATCHMAKING", "distance"),
+        ]))
+        .unwrap();
+
+    assert_eq!(config.bind_address, "0.0.0.0:2097".parse().unwrap());
+    assert!(config.insecure);
+    assert_eq!(config.tls.identity, PathBuf::from("elsewhere.p12"));
+    assert_eq!(config.tls.password.as_deref(), Some("hunter2"));
+    assert_eq!(
+        config.rounds,
+        RoundConfig {
+            round_seconds: 30,
+            ping_every_x_rounds: 3,
+            remove_after_missed_pings: 4,
+            max_messages_per_round: 100,
+            matchmaking: MatchmakingStrategy::Distance,
+        }
+    );
+    assert_eq!(config.validate(), Ok(()));
+}
+
+#[test]
+fn overrides_that_dont_parse_name_their_variable() {
+    let invalid = |variable: &'static str, value: &str| {
+        let mut config = ServerConfig::default();
+        let refused = config.apply_overrides(environment(&[(variable, value)]));
+        assert_eq!(
+            refused,
+            Err(ConfigErrors::InvalidOverride {
+                variable,
+                value: value.to_string(),
+            })
+        );
+        assert_eq!(config, ServerConfig::default());
+    };
+
+    invalid("WEBSOCKET_SERVER_BIND_ADDRESS", "localhost");
+    invalid("WEBSOCKET_SERVER_INSECURE", "yes");
+    invalid("WEBSOCKET_SERVER_ROUND_SECONDS", "-1");
+    invalid("WEBSOCKET_SERVER_PING_EVERY_X_ROUNDS", "two");
+    invalid("WEBSOCKET_SERVER_REMOVE_AFTER_MISSED_PINGS", "");
+    invalid("WEBSOCKET_SERVER_MAX_MESSAGES_PER_ROUND", "1e3");
+    invalid("WEBSOCKET_SERVER_MATCHMAKING", "nearest");
+}
+
+#[test]
+fn unknown_keys_and_missing_files_are_refused() {
+    for contents in &["bind_adress = \"0.0.0.0:2096\"\n", "[rounds]\nround_secs = 5\n", "[tls]\ncertificate = \"a.p12\"\n"] {
+        let path = config_file(contents);
+        let read = ServerConfig::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(read, Err(ConfigErrors::Malformed { .. })), "{:?} was read as {:?}", contents, read);
+    }
+
+    let missing = std::env::temp_dir().join(format!("websocket_server-{}.toml", Uuid::new_v4()));
+    assert!(matches!(ServerConfig::from_file(&missing), Err(ConfigErrors::Unreadable { .. })));
+}
+
+#[test]
+fn every_problem_is_reported_at_once() {
+    let mut config = ServerConfig::default();
+    config.tls.identity = std::env::temp_dir().join(format!("websocket_server-{}.p12", Uuid::new_v4()));
+    config.rounds = RoundConfig {
+        round_seconds: 0,
+        ping_every_x_rounds: 0,
+        remove_after_missed_pings: 0,
+        max_messages_per_round: 0,
+        matchmaking: MatchmakingStrategy::Off,
+    };
+
+    let problems = match config.validate() {
+        Err(ConfigErrors::Invalid(problems)) => problems,
+        other => panic!("expected the problems, got {:?}", other),
+    };
+    let settings = [
+        "rounds.round_seconds",
+        "rounds.ping_every_x_rounds",
+        "rounds.remove_after_missed_pings",
+        "rounds.max_messages_per_round",
+        "tls.password",
+        "tls identity",
+    ];
+    assert_eq!(problems.len(), settings.len());
+    for (problem, setting) in problems.iter().zip(settings.iter()) {
+        assert!(problem.contains(setting), "{:?} should be about {}", problem, setting);
+    }
+
+    // Without TLS only the rounds are a problem
+    config.insecure = true;
+    assert!(matches!(config.validate(), Err(ConfigErrors::Invalid(problems)) if problems.len() == 4));
+
+    config.rounds = RoundConfig::default();
+    assert_eq!(config.validate(), Ok(()));
+
+    config.insecure = false;
+    config.tls.password = Some("hunter2".to_string());
+    config.tls.identity = config_file("not really a pkcs12 archive");
+    let validated = config.validate();
+    std::fs::remove_file(&config.tls.identity).unwrap();
+    assert_eq!(validated, Ok(()));
+}
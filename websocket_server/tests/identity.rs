@@ -0,0 +1,65 @@
+use websocket_server::config::ServerConfig;
+use websocket_server::identity::{generate_self_signed, tls_acceptor};
+
+use openssl::pkcs12::Pkcs12;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_native_tls::native_tls::{Certificate, TlsConnector};
+use uuid::Uuid;
+
+use std::path::PathBuf;
+
+/// A config serving a freshly generated identity, which is written to a file of its own
+fn config_with_identity(hosts: &[&str], password: &str) -> (ServerConfig, Vec<u8>) {
+    let hosts: Vec<String> = hosts.iter().map(|host| host.to_string()).collect();
+    let der = generate_self_signed(&hosts, 1, password).unwrap();
+
+    let path: PathBuf = std::env::temp_dir().join(format!("websocket_server-{}.p12", Uuid::new_v4()));
+    std::fs::write(&path, &der).unwrap();
+
+    let mut config = ServerConfig::default();
+    config.tls.identity = path;
+    config.tls.password = Some(password.to_string());
+    (config, der)
+}
+
+#[test]
+fn the_password_has_to_match() {
+    let (mut config, _) = config_with_identity(&["localhost"], "right");
+    assert!(tls_acceptor(&config).is_ok());
+
+    config.tls.password = Some("wrong".to_string());
+    let err = tls_acceptor(&config).unwrap_err();
+    assert!(err.contains("is not a usable identity"), "{}", err);
+    std::fs::remove_file(&config.tls.identity).unwrap();
+}
+
+#[tokio::test]
+async fn clients_that_trust_the_certificate_can_connect() {
+    let (config, der) = config_with_identity(&["localhost", "127.0.0.1"], "secret");
+    let acceptor = tls_acceptor(&config).unwrap();
+    std::fs::remove_file(&config.tls.identity).unwrap();
+
+    let certificate = Pkcs12::from_der(&der).unwrap().parse2("secret").unwrap().cert.unwrap();
+    let connector = TlsConnector::builder()
+        .add_root_certificate(Certificate::from_der(&certificate.to_der().unwrap()).unwrap())
+        .build()
+        .unwrap();
+    let connector = tokio_native_tls::TlsConnector::from(connector);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = acceptor.accept(stream).await.unwrap();
+        stream.write_all(b"hello").await.unwrap();
+    });
+
+    // The certificate names localhost, which is checked against the name the client connects to
+    let stream = TcpStream::connect(address).await.unwrap();
+    let mut stream = connector.connect("localhost", stream).await.unwrap();
+    let mut greeting = vec![0; 5];
+    stream.read_exact(&mut greeting).await.unwrap();
+    assert_eq!(greeting, b"hello");
+    server.await.unwrap();
+}
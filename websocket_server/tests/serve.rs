@@ -0,0 +1,157 @@
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use models::{Client, Codec, Command, Entity, EntityDetails, Envelope, Handshake, ProtocolError};
+use serde::Deserialize;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use uuid::Uuid;
+
+use std::net::{SocketAddr, TcpListener};
+use std::process::{Child, Command as Process, Stdio};
+use std::time::Duration;
+
+/// The server binary serving ws:// on a free port, killed once dropped
+struct Server {
+    process: Child,
+    address: SocketAddr,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.process.kill().ok();
+        self.process.wait().ok();
+    }
+}
+
+fn serve() -> Server {
+    let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let process = Process::new(env!("CARGO_BIN_EXE_websocket_server"))
+        .arg("--insecure")
+        .env("WEBSOCKET_SERVER_BIND_ADDRESS", address.to_string())
+        .env("WEBSOCKET_SERVER_MATCHMAKING", "off")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    Server { process, address }
+}
+
+async fn connect(
+    server: &Server,
+) -> impl Stream<Item = Result<Message, WsError>> + Sink<Message, Error = WsError> + Unpin {
+    for _ in 0..50 {
+        match tokio_tungstenite::connect_async(format!("ws://{}", server.address)).await {
+            Ok((stream, _)) => return stream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+    panic!("the server never started listening")
+}
+
+/// The next frame that isn't a ping, pings can come in between at any time. Without a subprotocol the server speaks bincode.
+async fn next_frame<S>(connection: &mut S) -> Vec<u8>
+where
+    S: Stream<Item = Result<Message, WsError>> + Unpin,
+{
+    loop {
+        let frame = tokio::time::timeout(Duration::from_secs(5), connection.next())
+            .await
+            .expect("the server went quiet")
+            .unwrap()
+            .unwrap();
+        if let Message::Binary(bytes) = frame {
+            match Codec::Bincode.decode::<Envelope>(&bytes) {
+                Ok(envelope) if matches!(envelope.command, Command::Ping(_, _)) => {}
+                _ => return bytes,
+            }
+        }
+    }
+}
+
+async fn next<S>(connection: &mut S) -> Envelope
+where
+    S: Stream<Item = Result<Message, WsError>> + Unpin,
+{
+    Codec::Bincode.decode(&next_frame(connection).await).unwrap()
+}
+
+/// A frame from the client to the server
+fn send_as_client(server: EntityDetails, command: Command) -> Message {
+    // The sender is filled in by the server, whatever the client claims
+    let envelope = Envelope::new(EntityDetails::Client(Uuid::nil(), None), server, None, command);
+    Message::Binary(Codec::Bincode.encode(&envelope).unwrap())
+}
+
+#[tokio::test]
+async fn clients_are_greeted_negotiated_and_kept_to_themselves() {
+    let server = serve();
+
+    let mut connection = connect(&server).await;
+
+    let greeting = next(&mut connection).await;
+    let me = match greeting.command {
+        Command::ServerInitiated(client) => client.user_id,
+        other => panic!("expected a greeting, got {:?}", other),
+    };
+    let server_entity = greeting.sender.entity_detail;
+
+    let send = |command: Command| send_as_client(server_entity, command);
+
+    connection.send(send(Command::Handshake(Handshake::current()))).await.unwrap();
+    assert!(matches!(next(&mut connection).await.command, Command::HandshakeAccepted(_)));
+
+    let someone_else = Uuid::new_v4();
+    connection
+        .send(send(Command::UpdateClient(Client::from_user_id(someone_else))))
+        .await
+        .unwrap();
+    assert_eq!(next(&mut connection).await.command, Command::Error(ProtocolError::Impersonation(someone_else)));
+
+    connection
+        .send(send(Command::UpdateClient(Client {
+            username: Some("alice".to_string()),
+            ..Client::from_user_id(me)
+        })))
+        .await
+        .unwrap();
+    match next(&mut connection).await.command {
+        Command::OnlineClients(online, _) => assert_eq!(online[&me].username.as_deref(), Some("alice")),
+        other => panic!("expected the online clients, got {:?}", other),
+    }
+}
+
+/// The start of ```Command``` as clients from before the coded errors know it
+#[derive(Debug, Deserialize, PartialEq)]
+enum LegacyCommand {
+    BroadcastUpdate,
+    InCall(Uuid, Uuid),
+    EndCall(Uuid, Uuid),
+    UpdateClient(Client),
+    Error(String),
+}
+
+/// Bincode only knows the fields by their position
+#[derive(Debug, Deserialize)]
+struct LegacyEnvelope {
+    _sender: Entity,
+    _receiver: Entity,
+    _intermediary: Option<Entity>,
+    command: LegacyCommand,
+}
+
+#[tokio::test]
+async fn clients_that_never_shake_hands_get_errors_as_plain_strings() {
+    let server = serve();
+    let mut connection = connect(&server).await;
+    let server_entity = next(&mut connection).await.sender.entity_detail;
+
+    let someone_else = Uuid::new_v4();
+    connection
+        .send(send_as_client(server_entity, Command::UpdateClient(Client::from_user_id(someone_else))))
+        .await
+        .unwrap();
+
+    let legacy: LegacyEnvelope = Codec::Bincode.decode(&next_frame(&mut connection).await).unwrap();
+    assert_eq!(
+        legacy.command,
+        LegacyCommand::Error(ProtocolError::Impersonation(someone_else).message())
+    );
+}
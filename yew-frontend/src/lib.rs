@@ -11,7 +11,10 @@ use yew::prelude::*;
 use yew::ComponentLink;
 
 // This local trait is for shared objects between the frontend and the backend
-use models::{Client, Command, EntityDetails, ContextualizedCommand, PingStatus, Status};
+use models::{
+    Client, Codec, Command, ContextualizedCommand, EntityDetails, Handshake, Negotiated,
+    PingStatus, PresenceEvent, ProtocolError, Status,
+};
 
 use std::{collections::HashMap, net::SocketAddr};
 
@@ -35,11 +38,14 @@ use std::panic;
 #[derive(Hash, Eq, PartialEq, Debug, Clone)]
 enum State {
     ConnectedToWebsocketServer,
+    VersionChecked,
     ConnectedToRtcPeer,
 }
 
 static WEBSOCKET_URL: &str = "wss://liminalnook.com:2096";
 static STUN_SERVER: &str = "stun:stun.l.google.com:19302";
+/// Offered to the server as the websocket subprotocol, so every frame in both directions uses it
+const WIRE_CODEC: Codec = Codec::Bincode;
 
 struct Model {
     round_number: Option<u64>,
@@ -55,6 +61,10 @@ struct Model {
     username: Option<String>,
     status: Option<Status>,
     ping_status: PingStatus,
+    /// The protocol version and capabilities agreed on with the server
+    protocol: Option<Negotiated>,
+    /// The last error the server reported, shown above the states
+    protocol_error: Option<String>,
     partner: Option<Uuid>,
     link: ComponentLink<Self>,
     websocket: Option<WebSocket>,
@@ -71,6 +81,7 @@ impl Model {
         self.user_id = None;
         self.connection_socket_address = None;
         self.partner = None;
+        self.protocol = None;
         self.websocket = None;
         self.peers = HashMap::new();
         self.states = HashSet::new();
@@ -118,6 +129,14 @@ enum Msg {
     EndWebsocketConnection,
     SendWsMessage(Envelope),
     Ping(u64),
+    SendHandshake,
+    ReportPresence(PresenceEvent),
+    /// How the last call went, from -10 to 10
+    RateLastPartner(i32),
+    ProtocolNegotiated(Negotiated),
+    ServerError(ProtocolError),
+    /// The server paired us up, the first uuid sends the sdp offer
+    Matched(Uuid, Uuid),
 }
 
 extern crate web_sys;
@@ -153,16 +172,20 @@ impl Model {
 
         let message = data.clone();
 
-        let data = &data.serialize();
-
-        match ws.send_with_u8_array(data) {
-            Ok(_) => self.link.send_message(Msg::LogEvent(format!(
-                "Successfully sent the ws message: {:#?}",
-                message
-            ))),
+        match WIRE_CODEC.encode(&data) {
+            Ok(data) => match ws.send_with_u8_array(&data) {
+                Ok(_) => self.link.send_message(Msg::LogEvent(format!(
+                    "Successfully sent the ws message: {:#?}",
+                    message
+                ))),
+                Err(err) => self.link.send_message(Msg::LogEvent(format!(
+                    "There was an error sending the ws message: {:#?}",
+                    err
+                ))),
+            },
             Err(err) => self.link.send_message(Msg::LogEvent(format!(
-                "There was an error sending the ws message: {:#?}",
-                err
+                "Couldn't encode the ws message {:#?}: {}",
+                message, err
             ))),
         }
 
@@ -182,19 +205,50 @@ impl Model {
         let client_clone = client.clone();
         let client_clone2 = client.clone();
 
-        let mut disable_button: bool = true;
-
-        if client.status.is_some() {
-            if client.status.clone().unwrap() == models::Status::WaitingForPartner {
-                disable_button = false;
+        // Both sides have to be free for the call to start
+        let disable_button = match self.user_id {
+            Some(user_id) => {
+                let call_started = PresenceEvent::CallStarted(user_id, client.user_id);
+                !models::allowed(&self.status, &call_started)
+                    || !models::allowed(&client.status, &call_started)
             }
-        }
+            None => true,
+        };
 
         html!(<li> <button disabled=disable_button, onclick=self.link.callback( move |_| {
                     Msg::MakeSdpRequestToClient(client_clone.user_id.clone())
                 } ) > {format!("{:#?} : {:#?}", client_clone2.username.clone() , client_clone2.current_socket_addr.clone())} </button> </li>)
     }
 
+    /// A button for each presence change the current status allows
+    fn show_presence_buttons(&self) -> Html {
+        let choices = vec![
+            (PresenceEvent::AnsweredQuestions, "I'm done answering the questions"),
+            (PresenceEvent::WentIdle, "Take a break"),
+            (PresenceEvent::CameBack, "I'm back"),
+        ];
+
+        html!(
+            <div>
+            {for choices.into_iter().filter(|(event, _)| models::allowed(&self.status, event)).map(|(event, label)| {
+                html!(<button onclick=self.link.callback(move |_| Msg::ReportPresence(event))> {label} </button>)
+            })}
+            {if self.status == Some(Status::AnsweringQuestionAboutLastPartner) {
+                html!(
+                    <div>
+                    {"How was your last call? "}
+                    {for [-10, -5, 0, 5, 10].iter().copied().map(|rating| {
+                        html!(<button onclick=self.link.callback(move |_| Msg::RateLastPartner(rating))> {rating} </button>)
+                    })}
+                    </div>
+                )
+            } else {
+                html!()
+            }}
+            </div>
+        )
+    }
+
     fn setup_websocket_object_callbacks(&mut self, ws: WebSocket) -> WebSocket {
         let cloned = self.link.clone();
 
@@ -205,7 +259,7 @@ impl Model {
             if let Ok(abuf) = e.data().dyn_into::<js_sys::ArrayBuffer>() {
                 let array = js_sys::Uint8Array::new(&abuf);
 
-                match Envelope::deserialize(&array.to_vec()) {
+                match WIRE_CODEC.decode::<Envelope>(&array.to_vec()) {
                     Ok(result) => {
                         cloned.send_message(Msg::LogEvent(format!(
                             "Received the following command message: {:#?}",
@@ -220,10 +274,7 @@ impl Model {
                                 )));
                             }
                             Command::Error(error) => {
-                                cloned.send_message(Msg::LogEvent(format!(
-                                    "Received the following error: {}",
-                                    error,
-                                )));
+                                cloned.send_message(Msg::ServerError(error));
                             }
                             Command::Pong(_, _) => {
                                 cloned.send_message(Msg::LogEvent(format!("The server will never pong the client.. that's absolutely improper")));
@@ -242,6 +293,7 @@ impl Model {
                                     )),
                                     Msg::SetClient(client.clone()),
                                     Msg::AddState(State::ConnectedToWebsocketServer),
+                                    Msg::SendHandshake,
                                     Msg::SetupWebRtc(),
                                     Msg::RequestClientBroadcast,
                                 ];
@@ -278,6 +330,18 @@ impl Model {
                             Command::UpdateClient(_) => {
                                 cloned.send_message(Msg::LogEvent(format!("This is used for updating the server state. This will not be implemented on the client")));
                             }
+                            Command::Handshake(_) => {
+                                cloned.send_message(Msg::LogEvent(format!("The server will never send a handshake to the client")));
+                            }
+                            Command::HandshakeAccepted(negotiated) => {
+                                cloned.send_message(Msg::ProtocolNegotiated(negotiated));
+                            }
+                            Command::UpdatePresence(_) => {
+                                cloned.send_message(Msg::LogEvent(format!("The server sends presence changes as part of the client, not on their own")));
+                            }
+                            Command::Matched(initiator, receiver) => {
+                                cloned.send_message(Msg::Matched(initiator, receiver));
+                            }
                         }
                     }
                     Err(uhh) => {
@@ -646,6 +710,8 @@ impl Component for Model {
             states: HashSet::<State>::new(),
             status: None,
             ping_status: PingStatus::NeverPinged,
+            protocol: None,
+            protocol_error: None,
         }
     }
 
@@ -676,7 +742,10 @@ impl Component for Model {
                         Status::WaitingForPartner => {
                             self.link.send_message(Msg::ClosedWebRtcConnection);
                         }
-                        Status::AnsweringQuestionAboutLastPartner => {}
+                        Status::AnsweringQuestionAboutLastPartner => {
+                            self.link.send_message(Msg::ClosedWebRtcConnection);
+                        }
+                        Status::Idle => {}
                     },
                     None => {
                         self.link.send_message(Msg::ResetPage);
@@ -702,6 +771,89 @@ impl Component for Model {
                 true
             }
 
+            Msg::ReportPresence(event) => {
+                let update = Envelope::new(
+                    EntityDetails::Client(self.user_id.unwrap()),
+                    EntityDetails::Server,
+                    None,
+                    Command::UpdatePresence(event),
+                );
+
+                self.link.send_message(Msg::SendWsMessage(update));
+                false
+            }
+
+            Msg::RateLastPartner(rating) => {
+                let rating = Envelope::new(
+                    EntityDetails::Client(self.user_id.unwrap()),
+                    EntityDetails::Server,
+                    None,
+                    Command::RateLastPartner(rating),
+                );
+
+                self.link.send_message(Msg::SendWsMessage(rating));
+                false
+            }
+
+            Msg::SendHandshake => {
+                let handshake = Envelope::new(
+                    EntityDetails::Client(self.user_id.unwrap()),
+                    EntityDetails::Server,
+                    None,
+                    Command::Handshake(Handshake::current()),
+                );
+
+                self.link.send_message(Msg::SendWsMessage(handshake));
+                false
+            }
+
+            Msg::ProtocolNegotiated(negotiated) => {
+                self.link.send_message(Msg::LogEvent(format!(
+                    "Talking to the server with protocol version {} (downgraded: {})",
+                    negotiated.version, negotiated.downgraded
+                )));
+                self.protocol = Some(negotiated);
+                self.protocol_error = None;
+                self.link.send_message(Msg::AddState(State::VersionChecked));
+                true
+            }
+
+            Msg::ServerError(error) => {
+                self.link.send_message(Msg::LogEvent(format!(
+                    "Received the following error: {:?}",
+                    error
+                )));
+                self.protocol_error = Some(error.to_string());
+                if error.is_fatal() {
+                    self.link.send_message(Msg::EndWebsocketConnection);
+                }
+                true
+            }
+
+            Msg::Matched(initiator, receiver) => {
+                match self.user_id {
+                    Some(me) if me == initiator => {
+                        self.link.send_message(Msg::LogEvent(format!(
+                            "Matched with {}, sending them an offer",
+                            receiver
+                        )));
+                        self.link.send_message(Msg::MakeSdpRequestToClient(receiver));
+                    }
+                    Some(me) if me == receiver => {
+                        self.link.send_message(Msg::LogEvent(format!(
+                            "Matched with {}, waiting for their offer",
+                            initiator
+                        )));
+                        self.partner = Some(initiator);
+                    }
+                    _ => self.link.send_message(Msg::LogEvent(format!(
+                        "Received a match between {} and {}, which isn't ours",
+                        initiator, receiver
+                    ))),
+                }
+                false
+            }
+
             Msg::MaxLogSize => {
                 self.event_log_length = self.event_log.len();
 
@@ -923,7 +1075,7 @@ impl Component for Model {
                 true
             }
             
-            Msg::InitiateWebsocketConnectionProcess => match WebSocket::new(WEBSOCKET_URL) {
+            Msg::InitiateWebsocketConnectionProcess => match WebSocket::new_with_str(WEBSOCKET_URL, WIRE_CODEC.subprotocol()) {
                 Ok(ws) => {
                     let ws = self.setup_websocket_object_callbacks(ws);
 
@@ -1209,6 +1361,13 @@ impl Component for Model {
                 {self.show_events_in_table() }
 
 
+                {
+                    match &self.protocol_error {
+                        Some(error) => html!(<p> {error} </p>),
+                        None => html!(<></>),
+                    }
+                }
+
                 <div>
                 <h1> {"States of the system (can contain multiple values concurrently):"} </h1>
                 {
@@ -1253,6 +1412,8 @@ impl Component for Model {
                                 )
                             }  else {html!(<p> {"No peers online this round."} </p>)} }
 
+                            {self.show_presence_buttons()}
+
                             <button onclick=self.link.callback(|_| {
                                 Msg::CloseWebsocketConnection
                             })>